@@ -0,0 +1,4 @@
+-- 排行榜分数更新策略
+-- replace: 直接覆盖  max: 保留最高分  min: 保留最低分  increment: 累加
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `update_policy` varchar(32) NOT NULL DEFAULT 'replace';
@@ -0,0 +1,4 @@
+-- 排行榜排序方式
+-- desc: 分数越高排名越靠前  asc: 分数越低排名越靠前
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `sort_order` varchar(32) NOT NULL DEFAULT 'desc';
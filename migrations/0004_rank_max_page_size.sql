@@ -0,0 +1,3 @@
+-- 排行榜单次查询最多返回的用户数
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `max_page_size` int NOT NULL DEFAULT 30;
@@ -0,0 +1,57 @@
+-- 排行榜分数类型
+-- integer: 64位整数  decimal: 定点小数，按 score_precision 放大后以整数存储
+-- min_score、max_score 为按精度放大后的存储值
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `score_type` varchar(32) NOT NULL DEFAULT 'integer',
+    ADD COLUMN `score_precision` int NOT NULL DEFAULT 0,
+    ADD COLUMN `min_score` bigint NOT NULL DEFAULT 0,
+    ADD COLUMN `max_score` bigint NOT NULL DEFAULT 100000000;
+
+-- 排行榜表分数改为 bigint
+DROP PROCEDURE IF EXISTS CREATE_RANK_TABLE;
+DELIMITER ;;
+CREATE PROCEDURE CREATE_RANK_TABLE(IN appid VARCHAR(64) CHARSET utf8,IN rank_key VARCHAR(64) CHARSET utf8)
+BEGIN
+		SET @createTbsql = CONCAT('CREATE TABLE IF NOT EXISTS ','rank_',appid,'_',rank_key,
+		"(
+			`openid` varchar(190) NOT NULL ,
+			`nick_name` varchar(190) NOT NULL DEFAULT 'momo',
+			`score` bigint NOT NULL,
+			PRIMARY KEY (`openid`)
+		) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4"
+		);
+		-- 执行动态生成的sql语句
+		-- 预定义sql语句，从用户变量中获取
+		PREPARE temp FROM @createTbsql;
+		EXECUTE temp;
+		-- 释放资源，后续还可以使用
+		deallocate prepare temp;
+END
+;;
+
+-- 升级已经存在的排行榜表
+CREATE PROCEDURE UPGRADE_RANK_TABLE_SCORE()
+BEGIN
+		DECLARE done INT DEFAULT 0;
+		DECLARE cur_appid VARCHAR(190);
+		DECLARE cur_rank_key VARCHAR(190);
+		DECLARE cur CURSOR FOR SELECT `appid`,`rank_key` FROM `rank_table_config`;
+		DECLARE CONTINUE HANDLER FOR NOT FOUND SET done = 1;
+		OPEN cur;
+		upgrade_loop: LOOP
+			FETCH cur INTO cur_appid, cur_rank_key;
+			IF done = 1 THEN
+				LEAVE upgrade_loop;
+			END IF;
+			SET @alterTbsql = CONCAT('ALTER TABLE ','rank_',cur_appid,'_',cur_rank_key,' MODIFY `score` bigint NOT NULL');
+			PREPARE temp FROM @alterTbsql;
+			EXECUTE temp;
+			deallocate prepare temp;
+		END LOOP;
+		CLOSE cur;
+END
+;;
+DELIMITER ;
+
+CALL UPGRADE_RANK_TABLE_SCORE();
+DROP PROCEDURE IF EXISTS UPGRADE_RANK_TABLE_SCORE;
@@ -0,0 +1,49 @@
+-- 排行榜表增加分数更新时间(秒)，用于同分排序和重建redis排行榜
+DROP PROCEDURE IF EXISTS CREATE_RANK_TABLE;
+DELIMITER ;;
+CREATE PROCEDURE CREATE_RANK_TABLE(IN appid VARCHAR(64) CHARSET utf8,IN rank_key VARCHAR(64) CHARSET utf8)
+BEGIN
+		SET @createTbsql = CONCAT('CREATE TABLE IF NOT EXISTS ','rank_',appid,'_',rank_key,
+		"(
+			`openid` varchar(190) NOT NULL ,
+			`nick_name` varchar(190) NOT NULL DEFAULT 'momo',
+			`score` bigint NOT NULL,
+			`update_time` bigint NOT NULL DEFAULT 0,
+			PRIMARY KEY (`openid`)
+		) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4"
+		);
+		-- 执行动态生成的sql语句
+		-- 预定义sql语句，从用户变量中获取
+		PREPARE temp FROM @createTbsql;
+		EXECUTE temp;
+		-- 释放资源，后续还可以使用
+		deallocate prepare temp;
+END
+;;
+
+-- 升级已经存在的排行榜表
+CREATE PROCEDURE UPGRADE_RANK_TABLE_UPDATE_TIME()
+BEGIN
+		DECLARE done INT DEFAULT 0;
+		DECLARE cur_appid VARCHAR(190);
+		DECLARE cur_rank_key VARCHAR(190);
+		DECLARE cur CURSOR FOR SELECT `appid`,`rank_key` FROM `rank_table_config`;
+		DECLARE CONTINUE HANDLER FOR NOT FOUND SET done = 1;
+		OPEN cur;
+		upgrade_loop: LOOP
+			FETCH cur INTO cur_appid, cur_rank_key;
+			IF done = 1 THEN
+				LEAVE upgrade_loop;
+			END IF;
+			SET @alterTbsql = CONCAT('ALTER TABLE ','rank_',cur_appid,'_',cur_rank_key,' ADD COLUMN `update_time` bigint NOT NULL DEFAULT 0');
+			PREPARE temp FROM @alterTbsql;
+			EXECUTE temp;
+			deallocate prepare temp;
+		END LOOP;
+		CLOSE cur;
+END
+;;
+DELIMITER ;
+
+CALL UPGRADE_RANK_TABLE_UPDATE_TIME();
+DROP PROCEDURE IF EXISTS UPGRADE_RANK_TABLE_UPDATE_TIME;
@@ -0,0 +1,7 @@
+-- 同分排序规则
+-- earliest: 先上榜的排名靠前  latest: 后上榜的排名靠前  shared: 同分不区分先后
+-- 排名方式
+-- ordinal: 1-2-3-4  competition: 1-2-2-4  dense: 1-2-2-3
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `tie_policy` varchar(32) NOT NULL DEFAULT 'earliest',
+    ADD COLUMN `rank_style` varchar(32) NOT NULL DEFAULT 'ordinal';
@@ -0,0 +1,22 @@
+-- 排行榜归档，计划任务清理排行榜前保存上一期的排名
+-- 需要 MySQL 8.0 以上版本（窗口函数）
+CREATE TABLE IF NOT EXISTS `rank_archive_period` (
+                        `period_id` bigint NOT NULL AUTO_INCREMENT,
+                        `appid` varchar(190) NOT NULL ,
+                        `rank_key` varchar(190) NOT NULL ,
+                        `start_time` bigint NOT NULL DEFAULT 0,
+                        `end_time` bigint NOT NULL DEFAULT 0,
+                        `total` int NOT NULL DEFAULT 0,
+                        PRIMARY KEY (`period_id`),
+                        KEY `idx_appid_rank_key` (`appid`,`rank_key`,`period_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+CREATE TABLE IF NOT EXISTS `rank_archive_entry` (
+                        `period_id` bigint NOT NULL,
+                        `position` int NOT NULL,
+                        `ranking` int NOT NULL,
+                        `openid` varchar(190) NOT NULL ,
+                        `nick_name` varchar(190) NOT NULL DEFAULT 'momo',
+                        `score` bigint NOT NULL,
+                        PRIMARY KEY (`period_id`,`position`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
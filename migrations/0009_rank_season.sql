@@ -0,0 +1,7 @@
+-- 排行榜赛季，计划任务每次清理排行榜后加1
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `season` int NOT NULL DEFAULT 1;
+
+-- 归档的赛季
+ALTER TABLE `rank_archive_period`
+    ADD COLUMN `season` int NOT NULL DEFAULT 1;
@@ -0,0 +1,25 @@
+-- 排行榜奖励档位，json 数组
+-- [{"reward":"gold","min_rank":1,"max_rank":1},{"reward":"top1","top_percent":1}]
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `reward_brackets` varchar(2048) NOT NULL DEFAULT '[]';
+
+-- 排行榜奖励结算，每个玩家每期最多一条，游戏后端拉取后确认
+CREATE TABLE IF NOT EXISTS `rank_reward_settlement` (
+                        `id` bigint NOT NULL AUTO_INCREMENT,
+                        `appid` varchar(190) NOT NULL ,
+                        `rank_key` varchar(190) NOT NULL ,
+                        `season` int NOT NULL DEFAULT 1,
+                        `period_id` bigint NOT NULL,
+                        `openid` varchar(190) NOT NULL ,
+                        `nick_name` varchar(190) NOT NULL DEFAULT 'momo',
+                        `ranking` int NOT NULL,
+                        `score` bigint NOT NULL,
+                        `reward` varchar(190) NOT NULL ,
+                        -- 0 未确认 1 已确认
+                        `status` tinyint NOT NULL DEFAULT 0,
+                        `create_time` bigint NOT NULL DEFAULT 0,
+                        `ack_time` bigint NOT NULL DEFAULT 0,
+                        PRIMARY KEY (`id`),
+                        UNIQUE KEY `uk_period_openid` (`period_id`,`openid`),
+                        KEY `idx_appid_rank_key_status` (`appid`,`rank_key`,`status`,`id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
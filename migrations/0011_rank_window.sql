@@ -0,0 +1,5 @@
+-- 滚动时间窗口排行榜，window_seconds 为0时不是滚动排行榜
+-- 分数按 bucket_seconds 分桶保存在redis中，窗口外的桶会被删除
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `window_seconds` bigint NOT NULL DEFAULT 0,
+    ADD COLUMN `bucket_seconds` bigint NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- 排行榜分组，同一个appid下相同分组的排行榜可以一次更新分数
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `board_group` varchar(64) NOT NULL DEFAULT '';
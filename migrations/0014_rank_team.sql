@@ -0,0 +1,14 @@
+-- 队伍排行榜，disabled 不生成，sum 全部成员分数之和，top_k_sum 最好的 team_top_k 个成员分数之和
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `team_aggregate` varchar(16) NOT NULL DEFAULT 'disabled',
+    ADD COLUMN `team_top_k` int NOT NULL DEFAULT 0;
+
+-- 队伍成员，同一个appid下每个玩家最多属于一个队伍
+CREATE TABLE IF NOT EXISTS `rank_team_member` (
+                        `appid` varchar(190) NOT NULL ,
+                        `openid` varchar(190) NOT NULL ,
+                        `team_id` varchar(64) NOT NULL ,
+                        `update_time` bigint NOT NULL DEFAULT 0,
+                        PRIMARY KEY (`appid`,`openid`),
+                        KEY `idx_appid_team_id` (`appid`,`team_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
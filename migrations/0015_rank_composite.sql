@@ -0,0 +1,4 @@
+-- 组合排行榜的来源排行榜，json 数组，为空表示不是组合排行榜
+-- [{"rank_key":"pvp","weight":0.5},{"rank_key":"raid","weight":0.3}]
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `composite_sources` varchar(1024) NOT NULL DEFAULT '[]';
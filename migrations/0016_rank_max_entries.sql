@@ -0,0 +1,5 @@
+-- 排行榜最多保留的人数，0 不限制，超出的玩家由后台任务从redis中移除
+-- prune_mysql 为1时同时删除mysql中被移除玩家的分数
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `max_entries` bigint NOT NULL DEFAULT 0,
+    ADD COLUMN `prune_mysql` tinyint(1) NOT NULL DEFAULT 0;
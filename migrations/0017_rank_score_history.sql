@@ -0,0 +1,16 @@
+-- 分数提交历史，与排行榜分数在同一个事务中写入，只追加不修改
+CREATE TABLE IF NOT EXISTS `rank_score_history` (
+                        `id` bigint NOT NULL AUTO_INCREMENT,
+                        `appid` varchar(190) NOT NULL ,
+                        `rank_key` varchar(190) NOT NULL ,
+                        `openid` varchar(190) NOT NULL ,
+                        -- 提交前的分数，首次上榜为 NULL
+                        `old_score` bigint DEFAULT NULL,
+                        `new_score` bigint NOT NULL,
+                        `submitted_score` bigint NOT NULL,
+                        `source_ip` varchar(64) NOT NULL DEFAULT '',
+                        `request_id` varchar(64) NOT NULL DEFAULT '',
+                        `create_time` bigint NOT NULL DEFAULT 0,
+                        PRIMARY KEY (`id`),
+                        KEY `idx_appid_rank_key_openid` (`appid`,`rank_key`,`openid`,`id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,12 @@
+-- 封禁的玩家，ban 拒绝提交分数，shadow 影子封禁(接受提交但只有自己能看到)
+-- 同一个appid下每个玩家最多一条，expire_time 为 0 表示永久
+CREATE TABLE IF NOT EXISTS `rank_ban` (
+                        `appid` varchar(190) NOT NULL ,
+                        `openid` varchar(190) NOT NULL ,
+                        `ban_type` varchar(16) NOT NULL DEFAULT 'ban',
+                        `reason` varchar(255) NOT NULL DEFAULT '',
+                        `expire_time` bigint NOT NULL DEFAULT 0,
+                        `create_time` bigint NOT NULL DEFAULT 0,
+                        PRIMARY KEY (`appid`,`openid`),
+                        KEY `idx_expire_time` (`expire_time`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,26 @@
+-- 防作弊规则，分数按精度放大后存储
+-- cheat_max_score 允许的最高分，cheat_max_increase 单次提交最多增加的分数，为 NULL 时不限制
+-- cheat_max_per_minute 每个玩家每分钟最多提交的次数，cheat_min_interval 两次提交的最小间隔(秒)，为 0 时不限制
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `cheat_max_score` bigint DEFAULT NULL,
+    ADD COLUMN `cheat_max_increase` bigint DEFAULT NULL,
+    ADD COLUMN `cheat_max_per_minute` int NOT NULL DEFAULT 0,
+    ADD COLUMN `cheat_min_interval` int NOT NULL DEFAULT 0;
+
+-- 违反防作弊规则被拒绝的提交，只追加不修改，供人工审核
+CREATE TABLE IF NOT EXISTS `rank_cheat_violation` (
+                        `id` bigint NOT NULL AUTO_INCREMENT,
+                        `appid` varchar(190) NOT NULL ,
+                        `rank_key` varchar(190) NOT NULL ,
+                        `openid` varchar(190) NOT NULL ,
+                        `rule` varchar(32) NOT NULL ,
+                        `score` bigint NOT NULL,
+                        -- 提交时玩家在排行榜上的分数，不在榜上为 NULL
+                        `current_score` bigint DEFAULT NULL,
+                        `source_ip` varchar(64) NOT NULL DEFAULT '',
+                        `request_id` varchar(64) NOT NULL DEFAULT '',
+                        `create_time` bigint NOT NULL DEFAULT 0,
+                        PRIMARY KEY (`id`),
+                        KEY `idx_appid_openid` (`appid`,`openid`,`id`),
+                        KEY `idx_appid` (`appid`,`id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,30 @@
+-- 可疑分数的处理方式：reject 拒绝，review 进入审核队列
+-- review_top_ratio 离群阈值，结果分数与最差分数的差距超过榜首的这个倍数时进入审核队列，为 0 时不校验
+ALTER TABLE `rank_table_config`
+    ADD COLUMN `cheat_action` varchar(16) NOT NULL DEFAULT 'reject',
+    ADD COLUMN `review_top_ratio` double NOT NULL DEFAULT 0;
+
+-- 等待审核的可疑分数，通过后写入排行榜，分数按精度放大后存储
+CREATE TABLE IF NOT EXISTS `rank_score_review` (
+                        `id` bigint NOT NULL AUTO_INCREMENT,
+                        `appid` varchar(190) NOT NULL ,
+                        `rank_key` varchar(190) NOT NULL ,
+                        `openid` varchar(190) NOT NULL ,
+                        `nick_name` varchar(190) NOT NULL DEFAULT '',
+                        `rank_partition` varchar(64) NOT NULL DEFAULT '',
+                        `score` bigint NOT NULL,
+                        -- 提交时玩家在排行榜上的分数，不在榜上为 NULL
+                        `current_score` bigint DEFAULT NULL,
+                        -- 提交时榜首的分数，排行榜为空时为 NULL
+                        `top_score` bigint DEFAULT NULL,
+                        -- 进入审核的原因：违反的防作弊规则或 outlier
+                        `reason` varchar(32) NOT NULL ,
+                        `status` varchar(16) NOT NULL DEFAULT 'pending',
+                        `season` int NOT NULL DEFAULT 1,
+                        `source_ip` varchar(64) NOT NULL DEFAULT '',
+                        `request_id` varchar(64) NOT NULL DEFAULT '',
+                        `create_time` bigint NOT NULL DEFAULT 0,
+                        `review_time` bigint NOT NULL DEFAULT 0,
+                        PRIMARY KEY (`id`),
+                        KEY `idx_appid_status` (`appid`,`status`,`id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
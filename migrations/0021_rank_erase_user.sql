@@ -0,0 +1,7 @@
+-- 删除玩家时按玩家查询归档排名、奖励结算和可疑分数审核
+ALTER TABLE `rank_archive_entry`
+    ADD KEY `idx_openid` (`openid`);
+ALTER TABLE `rank_reward_settlement`
+    ADD KEY `idx_appid_openid` (`appid`,`openid`);
+ALTER TABLE `rank_score_review`
+    ADD KEY `idx_appid_openid` (`appid`,`openid`);
@@ -1,7 +1,8 @@
 use std::collections::HashSet;
+use std::net::IpAddr;
 
 use dotenv;
-use once_cell::sync::OnceCell;
+use once_cell::sync::{Lazy, OnceCell};
 
 pub static CMD_ARGS: OnceCell<HashSet<String>> = OnceCell::new();
 
@@ -25,12 +26,26 @@ pub fn init() {
     assert!(CMD_ARGS.set(std::env::args().collect()).is_ok());
 }
 
+// 可信的反向代理地址，环境变量 TRUSTED_PROXIES，多个用逗号分隔，不设置时不信任任何代理
+static TRUSTED_PROXIES: Lazy<HashSet<IpAddr>> = Lazy::new(|| {
+    std::env::var("TRUSTED_PROXIES")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect()
+});
+
+/// 连接地址是否是可信的反向代理
+pub fn is_trusted_proxy(ip: &IpAddr) -> bool {
+    TRUSTED_PROXIES.contains(ip)
+}
+
 pub fn get(parameter: &str) -> String {
     let env_parameter = std::env::var(parameter)
         .expect(&format!("{} is not defined in the environment.", parameter));
     return env_parameter;
 }
 
-// 计算分数的基准时间
-// 12023/10/01 00:00:00
-pub const CALC_SCORE_BASE_TIME_STAMP: f64 = 317265609600.0;
+// 同分排序时间的基准时间
+// 2023/10/01 00:00:00 (UTC+8)
+pub const TIE_BREAK_BASE_TIME_STAMP: i64 = 1696089600;
@@ -3,8 +3,14 @@
 //!
 
 use serde::{Deserialize, Serialize};
+use serde_json::Number;
 use validator::Validate;
 
+use crate::model::user::{
+    AntiCheatRule, BanType, CheatAction, CompositeSource, RankStyle, ReviewStatus, RewardBracket,
+    ScoreType, SortOrder, TeamAggregate, TiePolicy, UpdatePolicy, DEFAULT_MAX_PAGE_SIZE,
+};
+
 #[derive(Clone, Serialize, Deserialize, Validate)]
 pub struct UpdateScoreRequest {
     #[validate(length(
@@ -22,12 +28,94 @@ pub struct UpdateScoreRequest {
     #[validate(length(min = 3, max = 64, message = "key must be between 3 and 64 characters"))]
     pub openid: String,
     pub nick_name: String,
-    #[validate(range(
-        min = 0,
-        max = 100_000_000,
-        message = "score must be between 0 and 100_000_000"
+    /// 整数排行榜只接受整数，小数排行榜按配置的精度四舍五入，范围由排行榜配置决定
+    pub score: f64,
+    /// 分数所属的赛季，填写时与排行榜当前赛季不一致会被拒绝
+    #[serde(default)]
+    pub season: Option<i32>,
+    /// 玩家所在的分区(如国家、服务器)，不填时沿用之前提交的分区
+    #[serde(default)]
+    #[validate(length(
+        min = 1,
+        max = 64,
+        message = "partition must be between 1 and 64 characters"
+    ))]
+    pub partition: Option<String>,
+}
+
+/// 提交分数请求的来源，写入分数提交历史
+#[derive(Clone, Default)]
+pub struct ScoreRequestSource {
+    pub source_ip: String,
+    pub request_id: String,
+}
+
+/// 按排行榜分组更新分数，同一个分数写入分组内所有的排行榜
+#[derive(Clone, Deserialize, Validate)]
+pub struct UpdateGroupScoreReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 1,
+        max = 64,
+        message = "board_group must be between 1 and 64 characters"
+    ))]
+    pub board_group: String,
+    #[validate(length(min = 3, max = 64, message = "key must be between 3 and 64 characters"))]
+    pub openid: String,
+    pub nick_name: String,
+    /// 按每个排行榜的配置分别校验和转换
+    pub score: f64,
+    /// 玩家所在的分区(如国家、服务器)，不填时沿用之前提交的分区
+    #[serde(default)]
+    #[validate(length(
+        min = 1,
+        max = 64,
+        message = "partition must be between 1 and 64 characters"
+    ))]
+    pub partition: Option<String>,
+}
+
+/// 批量更新分数
+#[derive(Clone, Deserialize, Validate)]
+pub struct BatchUpdateScoreReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
     ))]
-    pub score: i32,
+    pub appid: String,
+    /// 可以包含同一个appid下多个排行榜的分数，每一项单独校验
+    #[validate(length(min = 1, max = 500, message = "items must be between 1 and 500"))]
+    pub items: Vec<UpdateScoreRequest>,
+}
+
+/// 更新分数的结果
+#[derive(Clone, Serialize)]
+pub struct UpdateScoreRes {
+    /// 分数是否进入审核队列，审核通过后才写入排行榜
+    pub pending_review: bool,
+}
+
+/// 批量更新分数每一项的结果
+#[derive(Clone, Serialize)]
+pub struct BatchUpdateScoreItemRes {
+    /// 在请求 items 中的下标
+    pub index: usize,
+    pub rank_key: String,
+    pub openid: String,
+    pub success: bool,
+    /// 分数是否进入审核队列，进入审核队列时 success 为 true，审核通过后才写入排行榜
+    pub pending_review: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg: Option<String>,
+    /// 排行榜当前的赛季，排行榜不存在时不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub season: Option<i32>,
 }
 
 #[derive(Clone, Serialize)]
@@ -37,9 +125,15 @@ pub struct UserScoreRes {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub nick_name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub score: Option<i32>,
+    pub score: Option<Number>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ranking: Option<i32>,
+    /// 排名百分比，例如 3.5 表示前 3.5%
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percentile: Option<f64>,
+    /// 排行榜限制了人数时返回，true 表示在保留的人数之外
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub below_cutoff: Option<bool>,
 }
 
 impl Default for UserScoreRes {
@@ -49,6 +143,8 @@ impl Default for UserScoreRes {
             nick_name: Default::default(),
             score: Default::default(),
             ranking: Default::default(),
+            percentile: Default::default(),
+            below_cutoff: Default::default(),
         }
     }
 }
@@ -73,6 +169,54 @@ pub struct UserRankingReq {
         message = "rank_key must be between 3 and 20 characters"
     ))]
     pub rank_key: String,
+    /// 获取排名时同时返回排名百分比
+    #[serde(default)]
+    pub percentile: bool,
+    /// 分区，不填查询整个排行榜
+    #[serde(default)]
+    #[validate(length(
+        min = 1,
+        max = 64,
+        message = "partition must be between 1 and 64 characters"
+    ))]
+    pub partition: Option<String>,
+}
+
+/// 排行榜统计
+#[derive(Clone, Deserialize, Validate)]
+pub struct RankStatsReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    /// 分区，不填查询整个排行榜
+    #[serde(default)]
+    #[validate(length(
+        min = 1,
+        max = 64,
+        message = "partition must be between 1 and 64 characters"
+    ))]
+    pub partition: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RankStatsRes {
+    /// 上榜人数
+    pub total: i64,
+    /// 榜上最低分，榜上没有玩家时不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<Number>,
+    /// 榜上最高分，榜上没有玩家时不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_score: Option<Number>,
 }
 
 #[derive(Clone, Deserialize, Validate)]
@@ -89,40 +233,845 @@ pub struct TopNUserReq {
         message = "rank_key must be between 1 and 20 characters"
     ))]
     pub rank_key: String,
-    #[validate(range(min = 1, max = 30, message = "top_n must be between 1 and 30"))]
+    /// 上限由排行榜配置的 max_page_size 决定
+    #[validate(range(min = 1, message = "top_n must be greater than 0"))]
     pub top_n: i32,
+    /// 分区，不填查询整个排行榜
+    #[serde(default)]
+    #[validate(length(
+        min = 1,
+        max = 64,
+        message = "partition must be between 1 and 64 characters"
+    ))]
+    pub partition: Option<String>,
 }
 
+/// 批量获取用户分数和排名
 #[derive(Clone, Deserialize, Validate)]
-pub struct AddRankConfigReq {
+pub struct BulkUserRankReq {
     #[validate(length(
         min = 3,
         max = 64,
         message = "appid must be between 3 and 64 characters"
     ))]
     pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    #[validate(length(min = 1, max = 200, message = "openids must be between 1 and 200"))]
+    pub openids: Vec<String>,
+    /// 分区，不填查询整个排行榜
+    #[serde(default)]
     #[validate(length(
         min = 1,
         max = 64,
-        message = "rank_key must be between 1 and 64 characters"
+        message = "partition must be between 1 and 64 characters"
+    ))]
+    pub partition: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct BulkUserRankRes {
+    pub openid: String,
+    /// 是否在榜上，不在榜上时只有 openid
+    pub on_board: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nick_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ranking: Option<i32>,
+}
+
+/// 好友排行榜，在指定的玩家中排名
+#[derive(Clone, Deserialize, Validate)]
+pub struct FriendRankReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
     ))]
     pub rank_key: String,
+    #[validate(length(min = 1, max = 500, message = "openids must be between 1 and 500"))]
+    pub openids: Vec<String>,
+    /// 分区，不填查询整个排行榜
+    #[serde(default)]
     #[validate(length(
-        min = 8,
+        min = 1,
         max = 64,
-        message = "app_secret must be between 8 and 64 characters"
+        message = "partition must be between 1 and 64 characters"
     ))]
-    pub app_secret: String,
+    pub partition: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct FriendRankRes {
+    pub openid: String,
+    pub nick_name: String,
+    pub score: Number,
+    /// 在指定玩家中的排名
+    pub ranking: i32,
+    /// 在整个排行榜中的排名
+    pub global_ranking: i32,
+}
+
+/// 获取用户上下相邻的玩家
+#[derive(Clone, Deserialize, Validate)]
+pub struct AroundUserReq {
     #[validate(length(
-        min = 0,
-        max = 200,
-        message = "cron_expression must be between 1 and 200 characters"
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
     ))]
-    pub cron_expression: String,
+    pub appid: String,
     #[validate(length(
-        min = 0,
-        max = 200,
-        message = "remark must be between 1 and 200 characters"
+        min = 3,
+        max = 64,
+        message = "openid must be between 3 and 64 characters"
     ))]
-    pub remark: String,
+    pub openid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    /// 上下各取多少名玩家，总数不能超过排行榜配置的 max_page_size
+    #[validate(range(min = 1, message = "count must be greater than 0"))]
+    pub count: i32,
+    /// 分区，不填查询整个排行榜
+    #[serde(default)]
+    #[validate(length(
+        min = 1,
+        max = 64,
+        message = "partition must be between 1 and 64 characters"
+    ))]
+    pub partition: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct AroundUserRes {
+    /// 用户自己的排名，0 未上榜
+    pub ranking: i32,
+    /// 包含用户自己在内的相邻玩家，未上榜时为空
+    pub users: Vec<UserScoreRes>,
+}
+
+/// 分页获取排行榜
+#[derive(Clone, Deserialize, Validate)]
+pub struct RankRangeReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    /// 起始排名偏移，从0开始
+    #[validate(range(min = 0, message = "offset must be greater than or equal to 0"))]
+    pub offset: i32,
+    /// 上限由排行榜配置的 max_page_size 决定
+    #[validate(range(min = 1, message = "limit must be greater than 0"))]
+    pub limit: i32,
+    /// 分区，不填查询整个排行榜
+    #[serde(default)]
+    #[validate(length(
+        min = 1,
+        max = 64,
+        message = "partition must be between 1 and 64 characters"
+    ))]
+    pub partition: Option<String>,
+}
+
+/// 获取排行榜的分区
+#[derive(Clone, Deserialize, Validate)]
+pub struct RankPartitionsReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RankPartitionRes {
+    pub partition: String,
+    /// 分区的上榜人数
+    pub total: i64,
+}
+
+/// 加入队伍，已经在其他队伍时转到新队伍
+#[derive(Clone, Deserialize, Validate)]
+pub struct JoinTeamReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "openid must be between 3 and 64 characters"
+    ))]
+    pub openid: String,
+    #[validate(length(
+        min = 1,
+        max = 64,
+        message = "team_id must be between 1 and 64 characters"
+    ))]
+    pub team_id: String,
+}
+
+/// 离开队伍
+#[derive(Clone, Deserialize, Validate)]
+pub struct LeaveTeamReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "openid must be between 3 and 64 characters"
+    ))]
+    pub openid: String,
+}
+
+/// 队伍排行榜前N名
+#[derive(Clone, Deserialize, Validate)]
+pub struct TeamTopReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    /// 上限由排行榜配置的 max_page_size 决定
+    #[validate(range(min = 1, message = "top_n must be greater than 0"))]
+    pub top_n: i32,
+}
+
+/// 获取队伍排名或成员分数
+#[derive(Clone, Deserialize, Validate)]
+pub struct TeamRankReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    #[validate(length(
+        min = 1,
+        max = 64,
+        message = "team_id must be between 1 and 64 characters"
+    ))]
+    pub team_id: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct TeamScoreRes {
+    pub team_id: String,
+    /// 队伍分数，队伍不在榜上时不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<Number>,
+    /// 队伍排名，0 未上榜
+    pub ranking: i32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct TeamMemberRes {
+    pub openid: String,
+    /// 是否在榜上，不在榜上时只有 openid
+    pub on_board: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nick_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<Number>,
+    /// 在整个排行榜中的排名
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ranking: Option<i32>,
+    /// 分数是否计入队伍分数
+    pub counted: bool,
+}
+
+/// 分页获取排行榜归档的期
+#[derive(Clone, Deserialize, Validate)]
+pub struct RankArchivePeriodsReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    #[validate(range(min = 0, message = "offset must be greater than or equal to 0"))]
+    pub offset: i32,
+    /// 上限由排行榜配置的 max_page_size 决定
+    #[validate(range(min = 1, message = "limit must be greater than 0"))]
+    pub limit: i32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RankArchivePeriodRes {
+    pub period_id: i64,
+    pub season: i32,
+    /// 开始时间(秒)
+    pub start_time: i64,
+    /// 结束时间(秒)
+    pub end_time: i64,
+    /// 上榜人数
+    pub total: i32,
+}
+
+/// 分页获取排行榜归档的排名
+#[derive(Clone, Deserialize, Validate)]
+pub struct RankArchiveRangeReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    pub period_id: i64,
+    /// 起始排名偏移，从0开始
+    #[validate(range(min = 0, message = "offset must be greater than or equal to 0"))]
+    pub offset: i32,
+    /// 上限由排行榜配置的 max_page_size 决定
+    #[validate(range(min = 1, message = "limit must be greater than 0"))]
+    pub limit: i32,
+}
+
+/// 拉取未确认的奖励结算
+#[derive(Clone, Deserialize, Validate)]
+pub struct RewardSettlementsReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    #[validate(range(min = 1, max = 500, message = "limit must be between 1 and 500"))]
+    pub limit: i32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RewardSettlementRes {
+    /// 结算id，确认时使用
+    pub id: i64,
+    pub season: i32,
+    pub period_id: i64,
+    pub openid: String,
+    pub nick_name: String,
+    pub ranking: i32,
+    pub score: Number,
+    pub reward: String,
+    /// 结算时间(秒)
+    pub create_time: i64,
+}
+
+/// 确认奖励结算
+#[derive(Clone, Deserialize, Validate)]
+pub struct AckRewardSettlementsReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    #[validate(length(min = 1, max = 500, message = "ids must be between 1 and 500"))]
+    pub ids: Vec<i64>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct AckRewardSettlementsRes {
+    /// 本次确认的数量，已经确认过的不计算
+    pub acked: u64,
+}
+
+/// 分页获取玩家的分数提交历史
+#[derive(Clone, Deserialize, Validate)]
+pub struct ScoreHistoryReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "openid must be between 3 and 64 characters"
+    ))]
+    pub openid: String,
+    #[validate(range(min = 0, message = "offset must be greater than or equal to 0"))]
+    pub offset: i32,
+    #[validate(range(min = 1, max = 500, message = "limit must be between 1 and 500"))]
+    pub limit: i32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ScoreHistoryRes {
+    pub id: i64,
+    /// 提交前的分数，首次上榜时不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_score: Option<Number>,
+    /// 按更新策略处理后的分数
+    pub new_score: Number,
+    /// 本次提交的分数
+    pub submitted_score: Number,
+    pub source_ip: String,
+    pub request_id: String,
+    /// 提交时间(秒)
+    pub create_time: i64,
+}
+
+/// 封禁玩家，对appid下所有的排行榜生效，重复封禁时更新封禁方式、原因和过期时间
+#[derive(Clone, Deserialize, Validate)]
+pub struct BanUserReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "openid must be between 3 and 64 characters"
+    ))]
+    pub openid: String,
+    /// 封禁方式，默认拒绝提交分数
+    #[serde(default)]
+    pub ban_type: BanType,
+    #[serde(default)]
+    #[validate(length(max = 255, message = "reason must not exceed 255 characters"))]
+    pub reason: String,
+    /// 过期时间(秒)，默认 0 永久
+    #[serde(default)]
+    #[validate(range(min = 0, message = "expire_time must be greater than or equal to 0"))]
+    pub expire_time: i64,
+}
+
+/// 解除封禁
+#[derive(Clone, Deserialize, Validate)]
+pub struct UnbanUserReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "openid must be between 3 and 64 characters"
+    ))]
+    pub openid: String,
+}
+
+/// 分页获取appid下的封禁
+#[derive(Clone, Deserialize, Validate)]
+pub struct BanListReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(range(min = 0, message = "offset must be greater than or equal to 0"))]
+    pub offset: i32,
+    #[validate(range(min = 1, max = 500, message = "limit must be between 1 and 500"))]
+    pub limit: i32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct BanRes {
+    pub openid: String,
+    pub ban_type: BanType,
+    pub reason: String,
+    /// 过期时间(秒)，0 表示永久
+    pub expire_time: i64,
+    /// 封禁时间(秒)
+    pub create_time: i64,
+}
+
+/// 分页获取appid下违反防作弊规则的提交
+#[derive(Clone, Deserialize, Validate)]
+pub struct CheatViolationListReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    /// 只获取这个玩家的记录，不填获取所有玩家
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "openid must be between 3 and 64 characters"
+    ))]
+    pub openid: Option<String>,
+    #[validate(range(min = 0, message = "offset must be greater than or equal to 0"))]
+    pub offset: i32,
+    #[validate(range(min = 1, max = 500, message = "limit must be between 1 and 500"))]
+    pub limit: i32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct CheatViolationRes {
+    pub id: i64,
+    pub rank_key: String,
+    pub openid: String,
+    /// 违反的规则
+    pub rule: AntiCheatRule,
+    /// 本次提交的分数
+    pub score: Number,
+    /// 提交时玩家在排行榜上的分数，不在榜上时不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_score: Option<Number>,
+    pub source_ip: String,
+    pub request_id: String,
+    /// 提交时间(秒)
+    pub create_time: i64,
+}
+
+/// 分页获取appid下的可疑分数审核，先提交的在前
+#[derive(Clone, Deserialize, Validate)]
+pub struct ScoreReviewListReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    /// 审核状态，默认等待审核
+    #[serde(default)]
+    pub status: ReviewStatus,
+    #[validate(range(min = 0, message = "offset must be greater than or equal to 0"))]
+    pub offset: i32,
+    #[validate(range(min = 1, max = 500, message = "limit must be between 1 and 500"))]
+    pub limit: i32,
+}
+
+/// 审核通过或拒绝一条等待审核的可疑分数
+#[derive(Clone, Deserialize, Validate)]
+pub struct ScoreReviewReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(range(min = 1, message = "id must be greater than 0"))]
+    pub id: i64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ScoreReviewRes {
+    pub id: i64,
+    pub rank_key: String,
+    pub openid: String,
+    pub nick_name: String,
+    /// 提交的分区，没有提交分区时不返回
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub partition: String,
+    /// 本次提交的分数
+    pub score: Number,
+    /// 提交时玩家在排行榜上的分数，不在榜上时不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_score: Option<Number>,
+    /// 提交时榜首的分数，排行榜为空时不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_score: Option<Number>,
+    /// 进入审核的原因：违反的防作弊规则或 outlier
+    pub reason: String,
+    pub status: ReviewStatus,
+    /// 提交时排行榜的赛季
+    pub season: i32,
+    pub source_ip: String,
+    pub request_id: String,
+    /// 提交时间(秒)
+    pub create_time: i64,
+    /// 审核时间(秒)，未审核为 0
+    pub review_time: i64,
+}
+
+/// 从排行榜中移除玩家
+#[derive(Clone, Deserialize, Validate)]
+pub struct RemoveUserReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "openid must be between 3 and 64 characters"
+    ))]
+    pub openid: String,
+}
+
+/// 从appid下所有的排行榜中删除玩家
+#[derive(Clone, Deserialize, Validate)]
+pub struct EraseUserReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "openid must be between 3 and 64 characters"
+    ))]
+    pub openid: String,
+}
+
+/// 玩家在一个排行榜上被移除的数据
+#[derive(Clone, Serialize)]
+pub struct RemovedRankRes {
+    pub rank_key: String,
+    /// 是否从redis排行榜中移除
+    pub redis: bool,
+    /// 删除的mysql分数行数
+    pub mysql_rows: u64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct EraseUserRes {
+    /// 玩家有数据的排行榜
+    pub ranks: Vec<RemovedRankRes>,
+    /// 是否删除了昵称
+    pub user_info: bool,
+    /// 是否离开了队伍
+    pub team_member: bool,
+    /// 删除的分数提交历史条数
+    pub score_history_rows: u64,
+    /// 删除的归档排名条数
+    pub archive_entry_rows: u64,
+    /// 删除的奖励结算条数
+    pub reward_settlement_rows: u64,
+    /// 删除的违规记录条数
+    pub cheat_violation_rows: u64,
+    /// 删除的可疑分数审核条数
+    pub score_review_rows: u64,
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct AddRankConfigReq {
+    #[validate(length(
+        min = 3,
+        max = 64,
+        message = "appid must be between 3 and 64 characters"
+    ))]
+    pub appid: String,
+    #[validate(length(
+        min = 3,
+        max = 20,
+        message = "rank_key must be between 3 and 20 characters"
+    ))]
+    pub rank_key: String,
+    #[validate(length(
+        min = 8,
+        max = 64,
+        message = "app_secret must be between 8 and 64 characters"
+    ))]
+    pub app_secret: String,
+    #[validate(length(
+        min = 0,
+        max = 200,
+        message = "cron_expression must be between 1 and 200 characters"
+    ))]
+    pub cron_expression: String,
+    #[validate(length(
+        min = 0,
+        max = 200,
+        message = "remark must be between 1 and 200 characters"
+    ))]
+    pub remark: String,
+    /// 分数更新策略，默认直接覆盖
+    #[serde(default)]
+    pub update_policy: UpdatePolicy,
+    /// 排序方式，默认分数越高排名越靠前
+    #[serde(default)]
+    pub sort_order: SortOrder,
+    /// 单次查询最多返回的用户数
+    #[serde(default = "default_max_page_size")]
+    #[validate(range(
+        min = 1,
+        max = 1000,
+        message = "max_page_size must be between 1 and 1000"
+    ))]
+    pub max_page_size: i32,
+    /// 分数类型，默认整数
+    #[serde(default)]
+    pub score_type: ScoreType,
+    /// 小数排行榜的小数位数，整数排行榜忽略
+    #[serde(default)]
+    #[validate(range(min = 0, max = 6, message = "score_precision must be between 0 and 6"))]
+    pub score_precision: i32,
+    /// 分数下限，不填默认为0
+    pub min_score: Option<f64>,
+    /// 分数上限，不填默认为 100_000_000
+    pub max_score: Option<f64>,
+    /// 同分排序规则，默认先上榜的排名靠前
+    #[serde(default)]
+    pub tie_policy: TiePolicy,
+    /// 排名方式，默认不并列
+    #[serde(default)]
+    pub rank_style: RankStyle,
+    /// 奖励档位，按顺序匹配，计划任务清理排行榜时结算
+    #[serde(default)]
+    #[validate(length(max = 50, message = "reward_brackets must be at most 50"))]
+    pub reward_brackets: Vec<RewardBracket>,
+    /// 滚动时间窗口(秒)，默认0不是滚动排行榜，滚动排行榜不能配置计划任务
+    #[serde(default)]
+    #[validate(range(
+        min = 0,
+        max = 31536000,
+        message = "window_seconds must be between 0 and 31536000"
+    ))]
+    pub window_seconds: i64,
+    /// 滚动排行榜每个分桶的时长(秒)，需要能整除 window_seconds
+    #[serde(default)]
+    #[validate(range(
+        min = 0,
+        max = 31536000,
+        message = "bucket_seconds must be between 0 and 31536000"
+    ))]
+    pub bucket_seconds: i64,
+    /// 排行榜分组，默认不属于任何分组
+    #[serde(default)]
+    #[validate(length(max = 64, message = "board_group must be at most 64 characters"))]
+    pub board_group: String,
+    /// 队伍排行榜的聚合方式，默认不生成队伍排行榜，滚动排行榜不支持
+    #[serde(default)]
+    pub team_aggregate: TeamAggregate,
+    /// top_k_sum 统计的成员数量
+    #[serde(default)]
+    #[validate(range(min = 0, max = 100, message = "team_top_k must be between 0 and 100"))]
+    pub team_top_k: i32,
+    /// 组合排行榜的来源排行榜，默认不是组合排行榜。组合排行榜不接受提交分数
+    #[serde(default)]
+    #[validate(length(max = 10, message = "composite_sources must be at most 10"))]
+    pub composite_sources: Vec<CompositeSource>,
+    /// 排行榜最多保留的人数，默认0不限制，超出的玩家由后台任务从redis中移除
+    #[serde(default)]
+    #[validate(range(
+        min = 0,
+        max = 100000000,
+        message = "max_entries must be between 0 and 100000000"
+    ))]
+    pub max_entries: i64,
+    /// 裁剪排行榜时是否同时删除mysql中的分数，默认保留。保留时被裁剪的玩家不会从mysql重新加载回redis，
+    /// 再次提交分数时按mysql中更新后的分数重新上榜
+    #[serde(default)]
+    pub prune_mysql: bool,
+    /// 防作弊允许的最高分，累加策略校验累加后的分数，不填不限制
+    pub cheat_max_score: Option<f64>,
+    /// 防作弊单次提交最多增加的分数，不填不限制
+    pub cheat_max_increase: Option<f64>,
+    /// 防作弊每个玩家每分钟最多提交的次数，默认0不限制
+    ///
+    /// 按自然分钟计数，不是滑动窗口，在分钟交界处前后最多可以连续提交两倍的次数，
+    /// 需要严格限制时配合 cheat_min_interval 使用。只有被接受的提交计数
+    #[serde(default)]
+    #[validate(range(
+        min = 0,
+        max = 10000,
+        message = "cheat_max_per_minute must be between 0 and 10000"
+    ))]
+    pub cheat_max_per_minute: i32,
+    /// 防作弊两次提交的最小间隔(秒)，默认0不限制
+    #[serde(default)]
+    #[validate(range(
+        min = 0,
+        max = 86400,
+        message = "cheat_min_interval must be between 0 and 86400"
+    ))]
+    pub cheat_min_interval: i32,
+    /// 违反防作弊分数规则时的处理方式，默认拒绝，review 进入审核队列。提交频率的规则总是拒绝
+    #[serde(default)]
+    pub cheat_action: CheatAction,
+    /// 离群阈值，需要大于1，默认0不校验。结果分数与最差分数的差距超过榜首的这个倍数时进入审核队列
+    #[serde(default)]
+    pub review_top_ratio: f64,
+}
+
+fn default_max_page_size() -> i32 {
+    DEFAULT_MAX_PAGE_SIZE
 }
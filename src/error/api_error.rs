@@ -1,4 +1,6 @@
-use crate::error::{db_error::DbError, token_error::TokenError, user_error::UserError};
+use crate::error::{
+    db_error::DbError, rank_error::RankError, token_error::TokenError, user_error::UserError,
+};
 use axum::response::{IntoResponse, Response};
 use thiserror::Error;
 
@@ -14,6 +16,8 @@ pub enum ApiError {
     DbError(#[from] DbError),
     #[error(transparent)]
     RequestError(#[from] RequestError),
+    #[error(transparent)]
+    RankError(#[from] RankError),
 }
 
 impl IntoResponse for ApiError {
@@ -23,6 +27,7 @@ impl IntoResponse for ApiError {
             ApiError::UserError(error) => error.into_response(),
             ApiError::DbError(error) => error.into_response(),
             ApiError::RequestError(error) => error.into_response(),
+            ApiError::RankError(error) => error.into_response(),
         }
     }
 }
@@ -20,6 +20,23 @@ pub const TOKEN_CREATION_ERROR: u32 = 12003;
 pub const SOMETHING_WENT_WRONG: u32 = 13001;
 pub const UNIQUE_CONSTRAINT_VIOLATION: u32 = 13002;
 
+// 排行榜错误
+// 14xxx
+pub const RANK_CONFIG_NOT_FOUND: u32 = 14001;
+pub const PAGE_SIZE_TOO_LARGE: u32 = 14002;
+pub const INVALID_SCORE: u32 = 14003;
+pub const RANK_ARCHIVE_NOT_FOUND: u32 = 14004;
+pub const SEASON_MISMATCH: u32 = 14005;
+pub const BOARD_GROUP_NOT_FOUND: u32 = 14006;
+pub const INVALID_PARTITION: u32 = 14007;
+pub const TEAM_RANK_DISABLED: u32 = 14008;
+pub const INVALID_TEAM: u32 = 14009;
+pub const COMPOSITE_RANK_READ_ONLY: u32 = 14010;
+pub const USER_BANNED: u32 = 14011;
+pub const BAN_NOT_FOUND: u32 = 14012;
+pub const CHEAT_DETECTED: u32 = 14013;
+pub const SCORE_REVIEW_NOT_FOUND: u32 = 14014;
+
 // request错误
 // 20xxx
 pub const VALIDATION_ERROR: u32 = 20001;
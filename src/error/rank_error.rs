@@ -0,0 +1,87 @@
+use crate::error::error_code;
+use crate::response::api_response::ApiErrorResponse;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RankError {
+    #[error("rank config is not exist")]
+    RankConfigNotFound,
+    #[error("page size must not exceed {0}")]
+    PageSizeTooLarge(i32),
+    #[error("{0}")]
+    InvalidScore(String),
+    #[error("rank archive is not exist")]
+    ArchiveNotFound,
+    #[error("season is over, current season is {0}")]
+    SeasonMismatch(i32),
+    #[error("board group is not exist")]
+    BoardGroupNotFound,
+    #[error("{0}")]
+    InvalidPartition(String),
+    #[error("team rank is disabled")]
+    TeamRankDisabled,
+    #[error("{0}")]
+    InvalidTeam(String),
+    #[error("composite rank does not accept score")]
+    CompositeRankReadOnly,
+    #[error("user is banned")]
+    UserBanned,
+    #[error("user is not banned")]
+    BanNotFound,
+    #[error("score is rejected by anti cheat rule: {0}")]
+    CheatDetected(String),
+    #[error("pending score review is not exist")]
+    ScoreReviewNotFound,
+}
+
+impl RankError {
+    fn get_code(&self) -> u32 {
+        match self {
+            RankError::RankConfigNotFound => error_code::RANK_CONFIG_NOT_FOUND,
+            RankError::PageSizeTooLarge(_) => error_code::PAGE_SIZE_TOO_LARGE,
+            RankError::InvalidScore(_) => error_code::INVALID_SCORE,
+            RankError::ArchiveNotFound => error_code::RANK_ARCHIVE_NOT_FOUND,
+            RankError::SeasonMismatch(_) => error_code::SEASON_MISMATCH,
+            RankError::BoardGroupNotFound => error_code::BOARD_GROUP_NOT_FOUND,
+            RankError::InvalidPartition(_) => error_code::INVALID_PARTITION,
+            RankError::TeamRankDisabled => error_code::TEAM_RANK_DISABLED,
+            RankError::InvalidTeam(_) => error_code::INVALID_TEAM,
+            RankError::CompositeRankReadOnly => error_code::COMPOSITE_RANK_READ_ONLY,
+            RankError::UserBanned => error_code::USER_BANNED,
+            RankError::BanNotFound => error_code::BAN_NOT_FOUND,
+            RankError::CheatDetected(_) => error_code::CHEAT_DETECTED,
+            RankError::ScoreReviewNotFound => error_code::SCORE_REVIEW_NOT_FOUND,
+        }
+    }
+}
+
+impl IntoResponse for RankError {
+    fn into_response(self) -> Response {
+        let status_code = match self {
+            RankError::RankConfigNotFound => StatusCode::NOT_FOUND,
+            RankError::PageSizeTooLarge(_) => StatusCode::BAD_REQUEST,
+            RankError::InvalidScore(_) => StatusCode::BAD_REQUEST,
+            RankError::ArchiveNotFound => StatusCode::NOT_FOUND,
+            RankError::SeasonMismatch(_) => StatusCode::CONFLICT,
+            RankError::BoardGroupNotFound => StatusCode::NOT_FOUND,
+            RankError::InvalidPartition(_) => StatusCode::BAD_REQUEST,
+            RankError::TeamRankDisabled => StatusCode::BAD_REQUEST,
+            RankError::InvalidTeam(_) => StatusCode::BAD_REQUEST,
+            RankError::CompositeRankReadOnly => StatusCode::BAD_REQUEST,
+            RankError::UserBanned => StatusCode::FORBIDDEN,
+            RankError::BanNotFound => StatusCode::NOT_FOUND,
+            RankError::CheatDetected(_) => StatusCode::FORBIDDEN,
+            RankError::ScoreReviewNotFound => StatusCode::NOT_FOUND,
+        };
+
+        ApiErrorResponse::send(
+            status_code.as_u16(),
+            self.get_code(),
+            Some(self.to_string()),
+        )
+    }
+}
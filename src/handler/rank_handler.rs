@@ -1,7 +1,18 @@
 use std::collections::HashMap;
+use std::net::SocketAddr;
 
+use crate::config::parameter;
 use crate::dto::rank_dto::{
-    AddRankConfigReq, TopNUserReq, UpdateScoreRequest, UserRankingReq, UserScoreRes,
+    AckRewardSettlementsReq, AckRewardSettlementsRes, AddRankConfigReq, AroundUserReq,
+    AroundUserRes, BanListReq, BanRes, BanUserReq, BatchUpdateScoreItemRes, BatchUpdateScoreReq,
+    BulkUserRankReq, BulkUserRankRes, CheatViolationListReq, CheatViolationRes, EraseUserReq,
+    EraseUserRes, FriendRankReq, FriendRankRes, JoinTeamReq, LeaveTeamReq, RankArchivePeriodRes,
+    RankArchivePeriodsReq, RankArchiveRangeReq, RankPartitionRes, RankPartitionsReq, RankRangeReq,
+    RankStatsReq, RankStatsRes, RemoveUserReq, RemovedRankRes, RewardSettlementRes,
+    RewardSettlementsReq, ScoreHistoryReq, ScoreHistoryRes, ScoreRequestSource, ScoreReviewListReq,
+    ScoreReviewReq, ScoreReviewRes, TeamMemberRes, TeamRankReq, TeamScoreRes, TeamTopReq,
+    TopNUserReq, UnbanUserReq, UpdateGroupScoreReq, UpdateScoreRequest, UpdateScoreRes,
+    UserRankingReq, UserScoreRes,
 };
 
 use crate::error::{api_error::ApiError, request_error::ValidatedRequest};
@@ -9,58 +20,406 @@ use crate::response::api_response::{ApiErrorResponse, ApiSuccessResponse};
 use crate::state::rank_config_state::RankConfigState;
 use crate::state::rank_state::RankState;
 use axum::{
-    extract::{Query, State},
+    extract::{ConnectInfo, Query, State},
+    http::HeaderMap,
     Json,
 };
 use axum_macros::debug_handler;
 
+/// 提交分数请求的来源
+///
+/// 连接地址是可信的反向代理时优先取 x-forwarded-for 中从右往左第一个不是可信代理的地址，其次 x-real-ip，
+/// 否则这两个请求头可以被客户端伪造，只使用连接地址
+fn get_request_source(addr: &SocketAddr, headers: &HeaderMap) -> ScoreRequestSource {
+    let header_value = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+    let forwarded_ip = match parameter::is_trusted_proxy(&addr.ip()) {
+        // 客户端可以在 x-forwarded-for 的前面添加任意地址，只有代理追加的地址可信
+        true => header_value("x-forwarded-for")
+            .and_then(|value| {
+                let ips: Vec<&str> = value
+                    .split(',')
+                    .map(|ip| ip.trim())
+                    .filter(|ip| !ip.is_empty())
+                    .collect();
+                ips.iter()
+                    .rev()
+                    .find(|ip| {
+                        !ip.parse()
+                            .map(|ip| parameter::is_trusted_proxy(&ip))
+                            .unwrap_or(false)
+                    })
+                    .or(ips.first())
+                    .copied()
+            })
+            .or_else(|| header_value("x-real-ip")),
+        false => None,
+    };
+    let source_ip = forwarded_ip
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| addr.ip().to_string());
+    ScoreRequestSource {
+        source_ip: source_ip.chars().take(64).collect(),
+        request_id: header_value("x-request-id")
+            .unwrap_or_default()
+            .chars()
+            .take(64)
+            .collect(),
+    }
+}
+
 pub async fn update_rank_score(
     State(state): State<RankState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     ValidatedRequest(payload): ValidatedRequest<UpdateScoreRequest>,
-) -> Result<Json<ApiSuccessResponse<()>>, ApiError> {
-    state.rank_service.update_rank_score(payload).await?;
-    Ok(Json(ApiSuccessResponse::from_with_nodata()))
+) -> Result<Json<ApiSuccessResponse<UpdateScoreRes>>, ApiError> {
+    let source = get_request_source(&addr, &headers);
+    // 返回校验分数时使用的赛季
+    let (season, res) = state
+        .rank_service
+        .update_rank_score(payload, &source)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res).with_season(season)))
+}
+
+pub async fn batch_update_rank_score(
+    State(state): State<RankState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    ValidatedRequest(payload): ValidatedRequest<BatchUpdateScoreReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<BatchUpdateScoreItemRes>>>, ApiError> {
+    let source = get_request_source(&addr, &headers);
+    let res = state
+        .rank_service
+        .batch_update_rank_score(payload, &source)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res)))
+}
+
+pub async fn update_group_score(
+    State(state): State<RankState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    ValidatedRequest(payload): ValidatedRequest<UpdateGroupScoreReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<BatchUpdateScoreItemRes>>>, ApiError> {
+    let source = get_request_source(&addr, &headers);
+    let res = state
+        .rank_service
+        .update_group_score(payload, &source)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res)))
 }
 
 pub async fn get_user_rank(
     State(state): State<RankState>,
     ValidatedRequest(payload): ValidatedRequest<UserRankingReq>,
 ) -> Result<Json<ApiSuccessResponse<UserScoreRes>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
     let res = state
         .rank_service
-        .get_user_ranking(&payload.appid, &payload.openid, &payload.rank_key)
+        .get_user_ranking(
+            &payload.appid,
+            &payload.openid,
+            &payload.rank_key,
+            &payload.partition,
+        )
         .await?;
-    Ok(Json(ApiSuccessResponse::send(UserScoreRes {
-        ranking: Some(res),
-        ..Default::default()
-    })))
+    let percentile = if payload.percentile {
+        state
+            .rank_service
+            .get_ranking_percentile(&payload.appid, &payload.rank_key, &payload.partition, res)
+            .await?
+    } else {
+        None
+    };
+    let below_cutoff = state
+        .rank_service
+        .get_below_cutoff(&payload.appid, &payload.openid, &payload.rank_key, res)
+        .await?;
+    Ok(Json(
+        ApiSuccessResponse::send(UserScoreRes {
+            ranking: Some(res),
+            percentile,
+            below_cutoff,
+            ..Default::default()
+        })
+        .with_season(season),
+    ))
+}
+
+pub async fn get_rank_stats(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<RankStatsReq>,
+) -> Result<Json<ApiSuccessResponse<RankStatsRes>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
+    let res = state
+        .rank_service
+        .get_rank_stats(&payload.appid, &payload.rank_key, &payload.partition)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res).with_season(season)))
 }
 
 pub async fn get_user_score(
     State(state): State<RankState>,
     ValidatedRequest(payload): ValidatedRequest<UserRankingReq>,
 ) -> Result<Json<ApiSuccessResponse<UserScoreRes>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
     let score = state
         .rank_service
-        .get_user_score(&payload.appid, &payload.openid, &payload.rank_key)
+        .get_user_score(
+            &payload.appid,
+            &payload.openid,
+            &payload.rank_key,
+            &payload.partition,
+        )
         .await?;
-    Ok(Json(ApiSuccessResponse::send(UserScoreRes {
-        score: Some(score),
-        ..Default::default()
-    })))
+    Ok(Json(
+        ApiSuccessResponse::send(UserScoreRes {
+            score: Some(score),
+            ..Default::default()
+        })
+        .with_season(season),
+    ))
+}
+
+pub async fn get_bulk_user_rank(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<BulkUserRankReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<BulkUserRankRes>>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
+    let res = state
+        .rank_service
+        .get_bulk_user_rank(
+            &payload.appid,
+            &payload.rank_key,
+            &payload.partition,
+            &payload.openids,
+        )
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res).with_season(season)))
+}
+
+pub async fn get_friend_user_rank(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<FriendRankReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<FriendRankRes>>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
+    let res = state
+        .rank_service
+        .get_friend_user_rank(
+            &payload.appid,
+            &payload.rank_key,
+            &payload.partition,
+            &payload.openids,
+        )
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res).with_season(season)))
 }
 
 pub async fn get_top_user_rank(
     State(state): State<RankState>,
     ValidatedRequest(payload): ValidatedRequest<TopNUserReq>,
 ) -> Result<Json<ApiSuccessResponse<Vec<UserScoreRes>>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
     let users = state
         .rank_service
-        .get_top_user_rank(&payload.appid, &payload.rank_key, payload.top_n)
+        .get_top_user_rank(
+            &payload.appid,
+            &payload.rank_key,
+            &payload.partition,
+            payload.top_n,
+        )
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(users).with_season(season)))
+}
+
+pub async fn get_range_user_rank(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<RankRangeReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<UserScoreRes>>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
+    let users = state
+        .rank_service
+        .get_range_user_rank(
+            &payload.appid,
+            &payload.rank_key,
+            &payload.partition,
+            payload.offset,
+            payload.limit,
+        )
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(users).with_season(season)))
+}
+
+pub async fn get_rank_partitions(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<RankPartitionsReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<RankPartitionRes>>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
+    let res = state
+        .rank_service
+        .get_rank_partitions(&payload.appid, &payload.rank_key)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res).with_season(season)))
+}
+
+pub async fn join_team(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<JoinTeamReq>,
+) -> Result<Json<ApiSuccessResponse<()>>, ApiError> {
+    state.rank_service.join_team(payload).await?;
+    Ok(Json(ApiSuccessResponse::from_with_nodata()))
+}
+
+pub async fn leave_team(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<LeaveTeamReq>,
+) -> Result<Json<ApiSuccessResponse<()>>, ApiError> {
+    state.rank_service.leave_team(payload).await?;
+    Ok(Json(ApiSuccessResponse::from_with_nodata()))
+}
+
+pub async fn get_team_top_rank(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<TeamTopReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<TeamScoreRes>>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
+    let res = state
+        .rank_service
+        .get_team_top_rank(&payload.appid, &payload.rank_key, payload.top_n)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res).with_season(season)))
+}
+
+pub async fn get_team_rank(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<TeamRankReq>,
+) -> Result<Json<ApiSuccessResponse<TeamScoreRes>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
+    let res = state
+        .rank_service
+        .get_team_rank(&payload.appid, &payload.rank_key, &payload.team_id)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res).with_season(season)))
+}
+
+pub async fn get_team_members(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<TeamRankReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<TeamMemberRes>>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
+    let res = state
+        .rank_service
+        .get_team_members(&payload.appid, &payload.rank_key, &payload.team_id)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res).with_season(season)))
+}
+
+pub async fn get_archive_periods(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<RankArchivePeriodsReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<RankArchivePeriodRes>>>, ApiError> {
+    let res = state
+        .rank_service
+        .get_archive_periods(
+            &payload.appid,
+            &payload.rank_key,
+            payload.offset,
+            payload.limit,
+        )
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res)))
+}
+
+pub async fn get_archive_range_rank(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<RankArchiveRangeReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<UserScoreRes>>>, ApiError> {
+    let users = state
+        .rank_service
+        .get_archive_range_rank(
+            &payload.appid,
+            &payload.rank_key,
+            payload.period_id,
+            payload.offset,
+            payload.limit,
+        )
         .await?;
     Ok(Json(ApiSuccessResponse::send(users)))
 }
 
+pub async fn get_reward_settlements(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<RewardSettlementsReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<RewardSettlementRes>>>, ApiError> {
+    let res = state
+        .rank_service
+        .get_reward_settlements(&payload.appid, &payload.rank_key, payload.limit)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res)))
+}
+
+pub async fn ack_reward_settlements(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<AckRewardSettlementsReq>,
+) -> Result<Json<ApiSuccessResponse<AckRewardSettlementsRes>>, ApiError> {
+    let acked = state
+        .rank_service
+        .ack_reward_settlements(&payload.appid, &payload.rank_key, &payload.ids)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(AckRewardSettlementsRes {
+        acked,
+    })))
+}
+
+pub async fn get_around_user_rank(
+    State(state): State<RankState>,
+    ValidatedRequest(payload): ValidatedRequest<AroundUserReq>,
+) -> Result<Json<ApiSuccessResponse<AroundUserRes>>, ApiError> {
+    let season = state
+        .rank_service
+        .get_rank_season(&payload.appid, &payload.rank_key)?;
+    let res = state
+        .rank_service
+        .get_around_user_rank(
+            &payload.appid,
+            &payload.openid,
+            &payload.rank_key,
+            &payload.partition,
+            payload.count,
+        )
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res).with_season(season)))
+}
+
 // 添加排行榜
 #[debug_handler]
 pub async fn add_rank_config(
@@ -71,6 +430,102 @@ pub async fn add_rank_config(
     Ok(Json(ApiSuccessResponse::from_with_nodata()))
 }
 
+// 分页获取玩家的分数提交历史
+pub async fn get_score_history(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<ScoreHistoryReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<ScoreHistoryRes>>>, ApiError> {
+    let res = state
+        .rank_config_service
+        .get_score_history(&payload)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res)))
+}
+
+// 封禁玩家
+pub async fn ban_user(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<BanUserReq>,
+) -> Result<Json<ApiSuccessResponse<()>>, ApiError> {
+    state.rank_config_service.ban_user(&payload).await?;
+    Ok(Json(ApiSuccessResponse::from_with_nodata()))
+}
+
+// 解除封禁
+pub async fn unban_user(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<UnbanUserReq>,
+) -> Result<Json<ApiSuccessResponse<()>>, ApiError> {
+    state.rank_config_service.unban_user(&payload).await?;
+    Ok(Json(ApiSuccessResponse::from_with_nodata()))
+}
+
+// 分页获取appid下的封禁
+pub async fn get_bans(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<BanListReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<BanRes>>>, ApiError> {
+    let res = state.rank_config_service.get_bans(&payload).await?;
+    Ok(Json(ApiSuccessResponse::send(res)))
+}
+
+// 分页获取appid下违反防作弊规则的提交
+pub async fn get_cheat_violations(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<CheatViolationListReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<CheatViolationRes>>>, ApiError> {
+    let res = state
+        .rank_config_service
+        .get_cheat_violations(&payload)
+        .await?;
+    Ok(Json(ApiSuccessResponse::send(res)))
+}
+
+// 分页获取appid下的可疑分数审核
+pub async fn get_score_reviews(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<ScoreReviewListReq>,
+) -> Result<Json<ApiSuccessResponse<Vec<ScoreReviewRes>>>, ApiError> {
+    let res = state.rank_service.get_score_reviews(&payload).await?;
+    Ok(Json(ApiSuccessResponse::send(res)))
+}
+
+// 审核通过可疑分数，写入排行榜
+pub async fn approve_score_review(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<ScoreReviewReq>,
+) -> Result<Json<ApiSuccessResponse<()>>, ApiError> {
+    state.rank_service.approve_score_review(&payload).await?;
+    Ok(Json(ApiSuccessResponse::from_with_nodata()))
+}
+
+// 审核拒绝可疑分数
+pub async fn reject_score_review(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<ScoreReviewReq>,
+) -> Result<Json<ApiSuccessResponse<()>>, ApiError> {
+    state.rank_service.reject_score_review(&payload).await?;
+    Ok(Json(ApiSuccessResponse::from_with_nodata()))
+}
+
+// 从排行榜中移除玩家
+pub async fn remove_user(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<RemoveUserReq>,
+) -> Result<Json<ApiSuccessResponse<RemovedRankRes>>, ApiError> {
+    let res = state.rank_config_service.remove_user(&payload).await?;
+    Ok(Json(ApiSuccessResponse::send(res)))
+}
+
+// 从appid下所有的排行榜中删除玩家
+pub async fn erase_user(
+    State(state): State<RankConfigState>,
+    ValidatedRequest(payload): ValidatedRequest<EraseUserReq>,
+) -> Result<Json<ApiSuccessResponse<EraseUserRes>>, ApiError> {
+    let res = state.rank_config_service.erase_user(&payload).await?;
+    Ok(Json(ApiSuccessResponse::send(res)))
+}
+
 // 删除排行榜
 #[debug_handler]
 pub async fn delete_rank_config(
@@ -1,5 +1,6 @@
 //! 内部用到的数据模型
 //!
+use crate::config::parameter::TIE_BREAK_BASE_TIME_STAMP;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -21,9 +22,630 @@ pub struct User {
 pub struct UserScoreInfo {
     #[sqlx(skip)]
     pub appid: String,
+    #[sqlx(skip)]
+    pub rank_key: String,
+    pub openid: String,
+    pub nick_name: String,
+    /// 存储的整数分数，小数分数按排行榜配置的精度放大
+    pub score: i64,
+    /// 分数更新时间(秒)，用于同分排序
+    pub update_time: i64,
+    /// 玩家所在的分区，空表示没有提交分区
+    #[sqlx(rename = "rank_partition")]
+    pub partition: String,
+}
+
+/// 排行榜归档的一期
+#[derive(Clone, Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct RankArchivePeriod {
+    pub period_id: i64,
+    pub appid: String,
+    pub rank_key: String,
+    /// 归档的赛季
+    pub season: i32,
+    /// 开始时间(秒)，上一期的结束时间，第一期为最早上榜时间
+    pub start_time: i64,
+    /// 结束时间(秒)
+    pub end_time: i64,
+    /// 上榜人数
+    pub total: i32,
+}
+
+/// 排行榜归档的玩家排名
+#[derive(Clone, Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct RankArchiveEntry {
+    /// 在榜上的位置，从1开始
+    pub position: i32,
+    /// 按排名方式计算的排名
+    pub ranking: i32,
+    pub openid: String,
+    pub nick_name: String,
+    pub score: i64,
+}
+
+/// 分数提交历史，只追加不修改
+#[derive(Clone, Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct RankScoreHistory {
+    pub id: i64,
+    pub appid: String,
+    pub rank_key: String,
+    pub openid: String,
+    /// 提交前的分数，首次上榜为 None
+    pub old_score: Option<i64>,
+    /// 按更新策略处理后的分数
+    pub new_score: i64,
+    /// 本次提交的分数
+    pub submitted_score: i64,
+    pub source_ip: String,
+    pub request_id: String,
+    /// 提交时间(秒)
+    pub create_time: i64,
+}
+
+/// 默认单次查询最多返回的用户数
+pub const DEFAULT_MAX_PAGE_SIZE: i32 = 30;
+
+/// 排行榜分数更新策略
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdatePolicy {
+    /// 直接覆盖
+    #[default]
+    Replace,
+    /// 保留最高分
+    Max,
+    /// 保留最低分
+    Min,
+    /// 累加
+    Increment,
+}
+
+impl UpdatePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpdatePolicy::Replace => "replace",
+            UpdatePolicy::Max => "max",
+            UpdatePolicy::Min => "min",
+            UpdatePolicy::Increment => "increment",
+        }
+    }
+}
+
+impl TryFrom<String> for UpdatePolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "replace" => Ok(UpdatePolicy::Replace),
+            "max" => Ok(UpdatePolicy::Max),
+            "min" => Ok(UpdatePolicy::Min),
+            "increment" => Ok(UpdatePolicy::Increment),
+            _ => Err(format!("unknown update policy: {}", value)),
+        }
+    }
+}
+
+/// 排行榜排序方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// 分数越高排名越靠前
+    #[default]
+    Desc,
+    /// 分数越低排名越靠前（竞速、高尔夫等）
+    Asc,
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Desc => "desc",
+            SortOrder::Asc => "asc",
+        }
+    }
+}
+
+impl TryFrom<String> for SortOrder {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "desc" => Ok(SortOrder::Desc),
+            "asc" => Ok(SortOrder::Asc),
+            _ => Err(format!("unknown sort order: {}", value)),
+        }
+    }
+}
+
+/// 同分时的排序规则
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TiePolicy {
+    /// 先上榜的排名靠前
+    #[default]
+    Earliest,
+    /// 后上榜的排名靠前
+    Latest,
+    /// 同分不区分先后，是否并列由排名方式决定
+    Shared,
+}
+
+impl TiePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TiePolicy::Earliest => "earliest",
+            TiePolicy::Latest => "latest",
+            TiePolicy::Shared => "shared",
+        }
+    }
+}
+
+impl TryFrom<String> for TiePolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "earliest" => Ok(TiePolicy::Earliest),
+            "latest" => Ok(TiePolicy::Latest),
+            "shared" => Ok(TiePolicy::Shared),
+            _ => Err(format!("unknown tie policy: {}", value)),
+        }
+    }
+}
+
+/// 排名方式
+///
+/// 以redis中的分数判断是否同分，只有同分排序规则为 shared 时才会出现并列
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RankStyle {
+    /// 不并列，1-2-3-4
+    #[default]
+    Ordinal,
+    /// 并列后跳过名次，1-2-2-4
+    Competition,
+    /// 并列后不跳过名次，1-2-2-3
+    Dense,
+}
+
+impl RankStyle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RankStyle::Ordinal => "ordinal",
+            RankStyle::Competition => "competition",
+            RankStyle::Dense => "dense",
+        }
+    }
+}
+
+impl TryFrom<String> for RankStyle {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "ordinal" => Ok(RankStyle::Ordinal),
+            "competition" => Ok(RankStyle::Competition),
+            "dense" => Ok(RankStyle::Dense),
+            _ => Err(format!("unknown rank style: {}", value)),
+        }
+    }
+}
+
+/// 队伍排行榜的聚合方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TeamAggregate {
+    /// 不生成队伍排行榜
+    #[default]
+    Disabled,
+    /// 全部成员分数之和
+    Sum,
+    /// 成绩最好的 team_top_k 个成员分数之和
+    TopKSum,
+}
+
+impl TeamAggregate {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TeamAggregate::Disabled => "disabled",
+            TeamAggregate::Sum => "sum",
+            TeamAggregate::TopKSum => "top_k_sum",
+        }
+    }
+}
+
+impl TryFrom<String> for TeamAggregate {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "disabled" => Ok(TeamAggregate::Disabled),
+            "sum" => Ok(TeamAggregate::Sum),
+            "top_k_sum" => Ok(TeamAggregate::TopKSum),
+            _ => Err(format!("unknown team aggregate: {}", value)),
+        }
+    }
+}
+
+/// 排行榜奖励档位
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct RewardBracket {
+    /// 奖励标识，由游戏后端解释
+    pub reward: String,
+    /// 名次范围，包含两端
+    #[serde(default)]
+    pub min_rank: i32,
+    #[serde(default)]
+    pub max_rank: i32,
+    /// 排名在前百分之多少，填写时忽略名次范围
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_percent: Option<f64>,
+}
+
+impl RewardBracket {
+    /// 校验奖励档位
+    pub fn check(&self) -> Result<(), String> {
+        if self.reward.is_empty() || self.reward.len() > 190 {
+            return Err("reward must be between 1 and 190 characters".to_string());
+        }
+        match self.top_percent {
+            Some(top_percent) if !(top_percent > 0.0 && top_percent <= 100.0) => {
+                Err("top_percent must be between 0 and 100".to_string())
+            }
+            Some(_) => Ok(()),
+            None if self.min_rank < 1 || self.max_rank < self.min_rank => {
+                Err("min_rank must be greater than 0 and not greater than max_rank".to_string())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// 排行榜奖励档位列表，按顺序匹配，每个玩家只获得第一个匹配的奖励
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct RewardBrackets(pub Vec<RewardBracket>);
+
+impl RewardBrackets {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.0).unwrap_or("[]".to_string())
+    }
+}
+
+impl TryFrom<String> for RewardBrackets {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match serde_json::from_str(&value) {
+            Ok(brackets) => Ok(RewardBrackets(brackets)),
+            Err(err) => Err(format!("unknown reward brackets: {}, {}", value, err)),
+        }
+    }
+}
+
+/// 组合排行榜的来源排行榜
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct CompositeSource {
+    /// 同一个appid下的排行榜
+    pub rank_key: String,
+    /// 权重，按来源排行榜显示的分数计算
+    pub weight: f64,
+}
+
+/// 组合排行榜的来源排行榜列表，为空表示不是组合排行榜
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct CompositeSources(pub Vec<CompositeSource>);
+
+impl CompositeSources {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.0).unwrap_or("[]".to_string())
+    }
+}
+
+impl TryFrom<String> for CompositeSources {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match serde_json::from_str(&value) {
+            Ok(sources) => Ok(CompositeSources(sources)),
+            Err(err) => Err(format!("unknown composite sources: {}, {}", value, err)),
+        }
+    }
+}
+
+/// 排行榜奖励结算记录
+#[derive(Clone, Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct RankRewardSettlement {
+    pub id: i64,
+    pub appid: String,
+    pub rank_key: String,
+    pub season: i32,
+    pub period_id: i64,
+    pub openid: String,
+    pub nick_name: String,
+    pub ranking: i32,
+    pub score: i64,
+    pub reward: String,
+    /// 结算时间(秒)
+    pub create_time: i64,
+}
+
+/// 队伍成员
+#[derive(Clone, Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct RankTeamMember {
+    pub appid: String,
+    pub openid: String,
+    pub team_id: String,
+    /// 加入时间(秒)
+    pub update_time: i64,
+}
+
+/// 封禁方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BanType {
+    /// 拒绝提交分数，不在排行榜上显示
+    #[default]
+    Ban,
+    /// 影子封禁，接受提交分数，只有自己能看到排名
+    Shadow,
+}
+
+impl BanType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BanType::Ban => "ban",
+            BanType::Shadow => "shadow",
+        }
+    }
+}
+
+impl TryFrom<String> for BanType {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "ban" => Ok(BanType::Ban),
+            "shadow" => Ok(BanType::Shadow),
+            _ => Err(format!("unknown ban type: {}", value)),
+        }
+    }
+}
+
+/// 封禁的玩家，对appid下所有的排行榜生效
+#[derive(Clone, Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct RankBan {
+    pub appid: String,
+    pub openid: String,
+    #[sqlx(try_from = "String")]
+    pub ban_type: BanType,
+    pub reason: String,
+    /// 过期时间(秒)，0 表示永久
+    pub expire_time: i64,
+    /// 封禁时间(秒)
+    pub create_time: i64,
+}
+
+/// 防作弊规则
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AntiCheatRule {
+    /// 超过允许的最高分
+    MaxScore,
+    /// 单次提交增加的分数过多
+    MaxIncrease,
+    /// 每分钟提交次数过多
+    MaxPerMinute,
+    /// 两次提交间隔过短
+    MinInterval,
+}
+
+impl AntiCheatRule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AntiCheatRule::MaxScore => "max_score",
+            AntiCheatRule::MaxIncrease => "max_increase",
+            AntiCheatRule::MaxPerMinute => "max_per_minute",
+            AntiCheatRule::MinInterval => "min_interval",
+        }
+    }
+}
+
+impl TryFrom<String> for AntiCheatRule {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "max_score" => Ok(AntiCheatRule::MaxScore),
+            "max_increase" => Ok(AntiCheatRule::MaxIncrease),
+            "max_per_minute" => Ok(AntiCheatRule::MaxPerMinute),
+            "min_interval" => Ok(AntiCheatRule::MinInterval),
+            _ => Err(format!("unknown anti cheat rule: {}", value)),
+        }
+    }
+}
+
+/// 违反防作弊分数规则时的处理方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheatAction {
+    /// 拒绝提交的分数
+    #[default]
+    Reject,
+    /// 进入审核队列，审核通过后写入排行榜
+    Review,
+}
+
+impl CheatAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheatAction::Reject => "reject",
+            CheatAction::Review => "review",
+        }
+    }
+}
+
+impl TryFrom<String> for CheatAction {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "reject" => Ok(CheatAction::Reject),
+            "review" => Ok(CheatAction::Review),
+            _ => Err(format!("unknown cheat action: {}", value)),
+        }
+    }
+}
+
+/// 可疑分数的审核状态
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewStatus {
+    /// 等待审核
+    #[default]
+    Pending,
+    /// 审核通过，已经写入排行榜
+    Approved,
+    /// 审核拒绝
+    Rejected,
+}
+
+impl ReviewStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReviewStatus::Pending => "pending",
+            ReviewStatus::Approved => "approved",
+            ReviewStatus::Rejected => "rejected",
+        }
+    }
+}
+
+impl TryFrom<String> for ReviewStatus {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "pending" => Ok(ReviewStatus::Pending),
+            "approved" => Ok(ReviewStatus::Approved),
+            "rejected" => Ok(ReviewStatus::Rejected),
+            _ => Err(format!("unknown review status: {}", value)),
+        }
+    }
+}
+
+/// 分数超过离群阈值时进入审核的原因
+pub const REVIEW_REASON_OUTLIER: &str = "outlier";
+
+/// 等待审核的可疑分数
+#[derive(Clone, Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct RankScoreReview {
+    pub id: i64,
+    pub appid: String,
+    pub rank_key: String,
     pub openid: String,
     pub nick_name: String,
-    pub score: i32,
+    #[sqlx(rename = "rank_partition")]
+    pub partition: String,
+    /// 本次提交的分数
+    pub score: i64,
+    /// 提交时玩家在排行榜上的分数，不在榜上为 None
+    pub current_score: Option<i64>,
+    /// 提交时榜首的分数，排行榜为空时为 None
+    pub top_score: Option<i64>,
+    /// 进入审核的原因：违反的防作弊规则或 outlier
+    pub reason: String,
+    #[sqlx(try_from = "String")]
+    pub status: ReviewStatus,
+    /// 提交时排行榜的赛季
+    pub season: i32,
+    pub source_ip: String,
+    pub request_id: String,
+    /// 提交时间(秒)
+    pub create_time: i64,
+    /// 审核时间(秒)，未审核为 0
+    pub review_time: i64,
+}
+
+/// 违反防作弊规则被拒绝的提交
+#[derive(Clone, Debug, Deserialize, Serialize, sqlx::FromRow)]
+pub struct RankCheatViolation {
+    pub id: i64,
+    pub appid: String,
+    pub rank_key: String,
+    pub openid: String,
+    #[sqlx(try_from = "String")]
+    pub rule: AntiCheatRule,
+    /// 本次提交的分数
+    pub score: i64,
+    /// 提交时玩家在排行榜上的分数，不在榜上为 None
+    pub current_score: Option<i64>,
+    pub source_ip: String,
+    pub request_id: String,
+    /// 提交时间(秒)
+    pub create_time: i64,
+}
+
+/// 默认分数下限
+pub const DEFAULT_MIN_SCORE: i64 = 0;
+
+/// 默认分数上限
+pub const DEFAULT_MAX_SCORE: i64 = 100_000_000;
+
+/// 存储分数的最大绝对值
+///
+/// redis 有序集合的分数是 f64，超过 2^53 的整数无法精确表示
+pub const MAX_SAFE_SCORE: i64 = (1 << 53) - 1;
+
+/// redis 分数可以精确表示的整数位数
+const REDIS_SCORE_BITS: u32 = 53;
+
+/// 同分排序时间最多占用的位数，按秒可以表示约136年
+const MAX_TIE_BITS: u32 = 32;
+
+/// 滚动排行榜分桶的最小时长(秒)
+pub const MIN_WINDOW_BUCKET_SECONDS: i64 = 60;
+
+/// 滚动排行榜最多的分桶数量，合并时每个分桶都要参与计算
+pub const MAX_WINDOW_BUCKETS: i64 = 168;
+
+/// 排行榜最多保留人数的上限
+pub const MAX_RANK_ENTRIES: i64 = 100_000_000;
+
+/// 队伍排行榜 top_k_sum 最多统计的成员数量
+pub const MAX_TEAM_TOP_K: i32 = 100;
+
+/// 队伍最多的成员数量，成员分数变化时需要遍历队伍的全部成员重新计算队伍分数
+pub const MAX_TEAM_SIZE: i32 = 200;
+
+/// 排行榜分数类型
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreType {
+    /// 64位整数
+    #[default]
+    Integer,
+    /// 定点小数，按 score_precision 放大后以整数存储
+    Decimal,
+}
+
+impl ScoreType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScoreType::Integer => "integer",
+            ScoreType::Decimal => "decimal",
+        }
+    }
+}
+
+impl TryFrom<String> for ScoreType {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "integer" => Ok(ScoreType::Integer),
+            "decimal" => Ok(ScoreType::Decimal),
+            _ => Err(format!("unknown score type: {}", value)),
+        }
+    }
 }
 
 // 数据库存储的排行榜配置
@@ -34,6 +656,65 @@ pub struct RankTableConfig {
     pub app_secret: String,
     // 计划任务表达式
     pub cron_expression: String,
+    // 分数更新策略
+    #[sqlx(try_from = "String")]
+    pub update_policy: UpdatePolicy,
+    // 排序方式
+    #[sqlx(try_from = "String")]
+    pub sort_order: SortOrder,
+    // 单次查询最多返回的用户数
+    pub max_page_size: i32,
+    // 分数类型
+    #[sqlx(try_from = "String")]
+    pub score_type: ScoreType,
+    // 小数位数，整数类型为0
+    pub score_precision: i32,
+    // 分数下限，按精度放大后的存储值
+    pub min_score: i64,
+    // 分数上限，按精度放大后的存储值
+    pub max_score: i64,
+    // 同分排序规则
+    #[sqlx(try_from = "String")]
+    pub tie_policy: TiePolicy,
+    // 排名方式
+    #[sqlx(try_from = "String")]
+    pub rank_style: RankStyle,
+    // 当前赛季，计划任务每次清理排行榜后加1
+    pub season: i32,
+    // 奖励档位，计划任务清理排行榜时结算
+    #[sqlx(try_from = "String")]
+    pub reward_brackets: RewardBrackets,
+    // 滚动时间窗口(秒)，0 表示不是滚动排行榜
+    pub window_seconds: i64,
+    // 滚动排行榜每个分桶的时长(秒)
+    pub bucket_seconds: i64,
+    // 排行榜分组，空表示不属于任何分组
+    pub board_group: String,
+    // 队伍排行榜的聚合方式
+    #[sqlx(try_from = "String")]
+    pub team_aggregate: TeamAggregate,
+    // top_k_sum 统计的成员数量
+    pub team_top_k: i32,
+    // 组合排行榜的来源排行榜，为空表示不是组合排行榜
+    #[sqlx(try_from = "String")]
+    pub composite_sources: CompositeSources,
+    // 排行榜最多保留的人数，0 表示不限制
+    pub max_entries: i64,
+    // 裁剪排行榜时是否同时删除mysql中的分数
+    pub prune_mysql: bool,
+    // 防作弊允许的最高分，按精度放大后的存储值，None 表示不限制
+    pub cheat_max_score: Option<i64>,
+    // 防作弊单次提交最多增加的分数，按精度放大后的存储值，None 表示不限制
+    pub cheat_max_increase: Option<i64>,
+    // 防作弊每个玩家每分钟最多提交的次数，0 表示不限制
+    pub cheat_max_per_minute: i32,
+    // 防作弊两次提交的最小间隔(秒)，0 表示不限制
+    pub cheat_min_interval: i32,
+    // 违反防作弊分数规则时的处理方式
+    #[sqlx(try_from = "String")]
+    pub cheat_action: CheatAction,
+    // 离群阈值，结果分数与最差分数的差距超过榜首的这个倍数时进入审核队列，0 表示不校验
+    pub review_top_ratio: f64,
     #[sqlx(skip)]
     pub cron_uuid: String,
 }
@@ -45,7 +726,548 @@ impl Default for RankTableConfig {
             app_secret: Default::default(),
             rank_key: Default::default(),
             cron_expression: Default::default(),
+            update_policy: Default::default(),
+            sort_order: Default::default(),
+            max_page_size: DEFAULT_MAX_PAGE_SIZE,
+            score_type: Default::default(),
+            score_precision: 0,
+            min_score: DEFAULT_MIN_SCORE,
+            max_score: DEFAULT_MAX_SCORE,
+            tie_policy: Default::default(),
+            rank_style: Default::default(),
+            season: 1,
+            reward_brackets: Default::default(),
+            window_seconds: 0,
+            bucket_seconds: 0,
+            board_group: Default::default(),
+            team_aggregate: Default::default(),
+            team_top_k: 0,
+            composite_sources: Default::default(),
+            max_entries: 0,
+            prune_mysql: false,
+            cheat_max_score: None,
+            cheat_max_increase: None,
+            cheat_max_per_minute: 0,
+            cheat_min_interval: 0,
+            cheat_action: Default::default(),
+            review_top_ratio: 0.0,
             cron_uuid: Default::default(),
         }
     }
 }
+
+impl RankTableConfig {
+    /// 分数放大倍数
+    fn score_scale(&self) -> f64 {
+        10f64.powi(self.score_precision)
+    }
+
+    /// 把请求中的分数转换为存储的整数分数
+    ///
+    /// 整数排行榜不接受小数，小数排行榜按精度四舍五入，超出范围返回 None
+    pub fn to_stored_score(&self, score: f64) -> Option<i64> {
+        if !score.is_finite() {
+            return None;
+        }
+        let stored = match self.score_type {
+            ScoreType::Integer => {
+                if score.fract() != 0.0 {
+                    return None;
+                }
+                score
+            }
+            ScoreType::Decimal => (score * self.score_scale()).round(),
+        };
+        if stored.abs() > MAX_SAFE_SCORE as f64 {
+            return None;
+        }
+        Some(stored as i64)
+    }
+
+    /// 把存储的整数分数转换为返回给客户端的分数
+    pub fn to_display_score(&self, stored: i64) -> serde_json::Number {
+        match self.score_type {
+            ScoreType::Integer => serde_json::Number::from(stored),
+            ScoreType::Decimal => serde_json::Number::from_f64(stored as f64 / self.score_scale())
+                .unwrap_or_else(|| serde_json::Number::from(stored)),
+        }
+    }
+
+    /// 分数是否在排行榜配置的范围内
+    pub fn is_score_in_bounds(&self, stored: i64) -> bool {
+        stored >= self.min_score && stored <= self.max_score
+    }
+
+    /// 同分排序值占用的位数
+    ///
+    /// 分数范围越大，留给同分排序时间的位数越少，时间精度越低
+    pub fn tie_bits(&self) -> u32 {
+        // 组合排行榜的分数由来源排行榜加权计算，不保存同分排序值
+        if self.is_composite() {
+            return 0;
+        }
+        let span = self.max_score.saturating_sub(self.min_score).max(0) as u64;
+        let score_bits = u64::BITS - span.leading_zeros();
+        REDIS_SCORE_BITS
+            .saturating_sub(score_bits)
+            .min(MAX_TIE_BITS)
+    }
+
+    /// 编码写入redis的分数
+    ///
+    /// (分数 - min_score) 左移 tie_bits 位，低位保存同分排序值，
+    /// 结果是不超过 2^53 的整数，redis 中可以无损表示
+    pub fn encode_redis_score(&self, stored: i64, update_time: i64) -> f64 {
+        let tie_bits = self.tie_bits();
+        let elapsed =
+            (update_time - TIE_BREAK_BASE_TIME_STAMP).clamp(0, (1i64 << MAX_TIE_BITS) - 1);
+        // 位数不够时降低时间精度
+        let time = elapsed >> (MAX_TIE_BITS - tie_bits);
+        let tie = match (self.tie_policy, self.sort_order) {
+            (TiePolicy::Shared, _) => 0,
+            // 时间越早同分排序值越大
+            (TiePolicy::Earliest, SortOrder::Desc) | (TiePolicy::Latest, SortOrder::Asc) => {
+                ((1i64 << tie_bits) - 1) - time
+            }
+            // 时间越早同分排序值越小
+            (TiePolicy::Earliest, SortOrder::Asc) | (TiePolicy::Latest, SortOrder::Desc) => time,
+        };
+        (((stored - self.min_score) << tie_bits) + tie) as f64
+    }
+
+    /// 累加分数时redis中需要增加的值，不影响同分排序值
+    pub fn encode_redis_increment(&self, delta: i64) -> i64 {
+        delta << self.tie_bits()
+    }
+
+    /// 累加策略累加后的分数，限制在排行榜的分数范围内
+    ///
+    /// 每次提交的分数只校验自身的范围，累加后超出范围时编码后的值会超过 2^53 丢失同分排序值和分数，
+    /// 或者小于 min_score 破坏同分排序值
+    pub fn increment_total(&self, current: i64, delta: i64) -> i64 {
+        current
+            .saturating_add(delta)
+            .clamp(self.min_score, self.max_score)
+    }
+
+    /// 从redis的分数还原存储的整数分数
+    pub fn decode_redis_score(&self, score: f64) -> i64 {
+        ((score as i64) >> self.tie_bits()) + self.min_score
+    }
+
+    /// 把redis中的分数转换为返回给客户端的分数
+    pub fn to_display_redis_score(&self, score: f64) -> serde_json::Number {
+        self.to_display_score(self.decode_redis_score(score))
+    }
+
+    /// 是否配置了防作弊规则或离群阈值
+    pub fn has_anti_cheat(&self) -> bool {
+        self.cheat_max_score.is_some()
+            || self.cheat_max_increase.is_some()
+            || self.cheat_max_per_minute > 0
+            || self.cheat_min_interval > 0
+            || self.review_top_ratio > 0.0
+    }
+
+    /// 是否配置了提交频率的规则
+    pub fn has_rate_limit(&self) -> bool {
+        self.cheat_max_per_minute > 0 || self.cheat_min_interval > 0
+    }
+
+    /// 按防作弊规则校验提交的分数，返回违反的规则
+    ///
+    /// current 为玩家当前在排行榜上的分数。累加策略校验累加后的分数，增加的分数就是提交的分数；
+    /// 其他策略校验提交的分数与当前分数的差值，首次上榜不校验增加的分数
+    pub fn check_cheat_score(&self, score: i64, current: Option<i64>) -> Option<AntiCheatRule> {
+        let (new_score, increase) = match self.update_policy {
+            UpdatePolicy::Increment => (
+                self.increment_total(current.unwrap_or(0), score),
+                Some(score),
+            ),
+            _ => (score, current.map(|current| score.saturating_sub(current))),
+        };
+        if let Some(max_score) = self.cheat_max_score {
+            if new_score > max_score {
+                return Some(AntiCheatRule::MaxScore);
+            }
+        }
+        if let (Some(max_increase), Some(increase)) = (self.cheat_max_increase, increase) {
+            if increase > max_increase {
+                return Some(AntiCheatRule::MaxIncrease);
+            }
+        }
+        None
+    }
+
+    /// 提交的分数按更新策略处理后是否超过离群阈值
+    ///
+    /// 比较结果分数和榜首分数与最差分数(降序为分数下限，升序为分数上限)的差距，排行榜为空时不校验
+    pub fn is_score_outlier(&self, score: i64, current: Option<i64>, top: Option<i64>) -> bool {
+        let top = match top {
+            Some(top) if self.review_top_ratio > 0.0 => top,
+            _ => return false,
+        };
+        let new_score = match (self.update_policy, current) {
+            (UpdatePolicy::Increment, _) => self.increment_total(current.unwrap_or(0), score),
+            (UpdatePolicy::Max, Some(current)) => score.max(current),
+            (UpdatePolicy::Min, Some(current)) => score.min(current),
+            _ => score,
+        };
+        let (new_gap, top_gap) = match self.sort_order {
+            SortOrder::Desc => (new_score - self.min_score, top - self.min_score),
+            SortOrder::Asc => (self.max_score - new_score, self.max_score - top),
+        };
+        top_gap > 0 && new_gap as f64 > top_gap as f64 * self.review_top_ratio
+    }
+
+    /// 是否是滚动时间窗口排行榜
+    pub fn is_rolling_window(&self) -> bool {
+        self.window_seconds > 0 && self.bucket_seconds > 0
+    }
+
+    /// 时间(秒)所在的分桶
+    pub fn window_bucket_index(&self, time: i64) -> i64 {
+        time.div_euclid(self.bucket_seconds)
+    }
+
+    /// 时间窗口包含的分桶数量
+    pub fn window_bucket_count(&self) -> i64 {
+        self.window_seconds / self.bucket_seconds
+    }
+
+    /// 是否生成队伍排行榜
+    pub fn is_team_board(&self) -> bool {
+        self.team_aggregate != TeamAggregate::Disabled
+    }
+
+    /// 是否是组合排行榜
+    pub fn is_composite(&self) -> bool {
+        !self.composite_sources.0.is_empty()
+    }
+
+    /// 组合排行榜每个来源排行榜在redis中的权重
+    ///
+    /// 来源排行榜redis中的分数去掉同分排序值，再按两个排行榜的精度换算，
+    /// 结果是组合排行榜存储的分数(从来源排行榜的 min_score 开始计算)。找不到的来源排行榜忽略
+    pub fn composite_source_weights(&self, configs: &[RankTableConfig]) -> Vec<(String, f64)> {
+        self.composite_sources
+            .0
+            .iter()
+            .filter_map(|source| {
+                configs
+                    .iter()
+                    .find(|config| config.appid == self.appid && config.rank_key == source.rank_key)
+                    .map(|config| {
+                        let weight = source.weight / (1i64 << config.tie_bits()) as f64
+                            * 10f64.powi(self.score_precision - config.score_precision);
+                        (source.rank_key.clone(), weight)
+                    })
+            })
+            .collect()
+    }
+
+    /// 队伍排行榜统计的成员数量，0 表示统计全部成员
+    pub fn team_member_limit(&self) -> i32 {
+        match self.team_aggregate {
+            TeamAggregate::TopKSum => self.team_top_k,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(sort_order: SortOrder, tie_policy: TiePolicy) -> RankTableConfig {
+        RankTableConfig {
+            sort_order,
+            tie_policy,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn encode_redis_score_round_trip() {
+        let time = TIE_BREAK_BASE_TIME_STAMP + 86400;
+        for tie_policy in [TiePolicy::Earliest, TiePolicy::Latest, TiePolicy::Shared] {
+            for sort_order in [SortOrder::Desc, SortOrder::Asc] {
+                let config = config(sort_order, tie_policy);
+                for score in [0, 1, 12345, DEFAULT_MAX_SCORE] {
+                    let encoded = config.encode_redis_score(score, time);
+                    assert!(encoded < (1i64 << REDIS_SCORE_BITS) as f64);
+                    assert_eq!(config.decode_redis_score(encoded), score);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn encode_redis_score_with_negative_min_score() {
+        let config = RankTableConfig {
+            min_score: -1000,
+            max_score: 1000,
+            ..Default::default()
+        };
+        let encoded = config.encode_redis_score(-1000, TIE_BREAK_BASE_TIME_STAMP);
+        assert!(encoded >= 0.0);
+        assert_eq!(config.decode_redis_score(encoded), -1000);
+        assert_eq!(
+            config.decode_redis_score(config.encode_redis_score(999, TIE_BREAK_BASE_TIME_STAMP)),
+            999
+        );
+    }
+
+    #[test]
+    fn encode_redis_score_orders_ties_by_policy() {
+        let early = TIE_BREAK_BASE_TIME_STAMP + 3600;
+        let late = early + 86400;
+        // 降序排行榜分数越大排名越靠前
+        let config = config(SortOrder::Desc, TiePolicy::Earliest);
+        assert!(config.encode_redis_score(100, early) > config.encode_redis_score(100, late));
+        let config = RankTableConfig {
+            tie_policy: TiePolicy::Latest,
+            ..config
+        };
+        assert!(config.encode_redis_score(100, early) < config.encode_redis_score(100, late));
+        // 升序排行榜分数越小排名越靠前
+        let config = RankTableConfig {
+            sort_order: SortOrder::Asc,
+            tie_policy: TiePolicy::Earliest,
+            ..config
+        };
+        assert!(config.encode_redis_score(100, early) < config.encode_redis_score(100, late));
+        let config = RankTableConfig {
+            tie_policy: TiePolicy::Shared,
+            ..config
+        };
+        assert_eq!(
+            config.encode_redis_score(100, early),
+            config.encode_redis_score(100, late)
+        );
+    }
+
+    #[test]
+    fn encode_redis_score_keeps_score_order_over_tie() {
+        let config = config(SortOrder::Desc, TiePolicy::Earliest);
+        // 分数更高时无论上榜时间都排在前面
+        assert!(
+            config.encode_redis_score(101, TIE_BREAK_BASE_TIME_STAMP + 86400 * 365)
+                > config.encode_redis_score(100, TIE_BREAK_BASE_TIME_STAMP)
+        );
+        let increment = config.encode_redis_increment(5) as f64;
+        let encoded = config.encode_redis_score(100, TIE_BREAK_BASE_TIME_STAMP);
+        assert_eq!(config.decode_redis_score(encoded + increment), 105);
+    }
+
+    #[test]
+    fn tie_bits_shrink_with_score_range() {
+        let small = RankTableConfig::default();
+        let large = RankTableConfig {
+            max_score: MAX_SAFE_SCORE,
+            ..Default::default()
+        };
+        assert!(small.tie_bits() > 0);
+        assert!(small.tie_bits() <= MAX_TIE_BITS);
+        assert_eq!(large.tie_bits(), 0);
+    }
+
+    #[test]
+    fn stored_score_respects_precision() {
+        let config = RankTableConfig {
+            score_type: ScoreType::Decimal,
+            score_precision: 2,
+            ..Default::default()
+        };
+        assert_eq!(config.to_stored_score(1.234), Some(123));
+        assert_eq!(config.to_stored_score(f64::NAN), None);
+        let config = RankTableConfig::default();
+        assert_eq!(config.to_stored_score(1.5), None);
+        assert_eq!(config.to_stored_score(2.0), Some(2));
+    }
+
+    #[test]
+    fn composite_source_weights_scale_by_precision() {
+        let pvp = RankTableConfig {
+            appid: "app".to_string(),
+            rank_key: "pvp".to_string(),
+            tie_policy: TiePolicy::Shared,
+            ..Default::default()
+        };
+        let raid = RankTableConfig {
+            appid: "app".to_string(),
+            rank_key: "raid".to_string(),
+            tie_policy: TiePolicy::Shared,
+            score_type: ScoreType::Decimal,
+            score_precision: 1,
+            ..Default::default()
+        };
+        let overall = RankTableConfig {
+            appid: "app".to_string(),
+            rank_key: "overall".to_string(),
+            score_type: ScoreType::Decimal,
+            score_precision: 2,
+            composite_sources: CompositeSources(vec![
+                CompositeSource {
+                    rank_key: "pvp".to_string(),
+                    weight: 0.5,
+                },
+                CompositeSource {
+                    rank_key: "raid".to_string(),
+                    weight: 0.3,
+                },
+                CompositeSource {
+                    rank_key: "missing".to_string(),
+                    weight: 1.0,
+                },
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(overall.tie_bits(), 0);
+        let configs = vec![pvp.clone(), raid.clone(), overall.clone()];
+        let weights = overall.composite_source_weights(&configs);
+        // 找不到的来源排行榜忽略
+        assert_eq!(weights.len(), 2);
+        // 0.5 × 100 + 0.3 × 5.5 = 51.65
+        let time = TIE_BREAK_BASE_TIME_STAMP;
+        let total = pvp.encode_redis_score(100, time) * weights[0].1
+            + raid.encode_redis_score(55, time) * weights[1].1;
+        assert_eq!(overall.decode_redis_score(total.round()), 5165);
+        assert_eq!(
+            overall.to_display_redis_score(total.round()).to_string(),
+            "51.65"
+        );
+    }
+
+    #[test]
+    fn check_cheat_score_max_score() {
+        let config = RankTableConfig {
+            cheat_max_score: Some(1000),
+            ..Default::default()
+        };
+        assert_eq!(config.check_cheat_score(1000, None), None);
+        assert_eq!(
+            config.check_cheat_score(1001, None),
+            Some(AntiCheatRule::MaxScore)
+        );
+        // 累加策略校验累加后的分数
+        let config = RankTableConfig {
+            update_policy: UpdatePolicy::Increment,
+            ..config
+        };
+        assert_eq!(config.check_cheat_score(100, Some(900)), None);
+        assert_eq!(
+            config.check_cheat_score(101, Some(900)),
+            Some(AntiCheatRule::MaxScore)
+        );
+    }
+
+    #[test]
+    fn check_cheat_score_max_increase() {
+        let config = RankTableConfig {
+            cheat_max_increase: Some(50),
+            ..Default::default()
+        };
+        // 首次上榜不校验增加的分数
+        assert_eq!(config.check_cheat_score(10000, None), None);
+        assert_eq!(config.check_cheat_score(150, Some(100)), None);
+        assert_eq!(
+            config.check_cheat_score(151, Some(100)),
+            Some(AntiCheatRule::MaxIncrease)
+        );
+        // 累加策略增加的分数就是提交的分数
+        let config = RankTableConfig {
+            update_policy: UpdatePolicy::Increment,
+            ..config
+        };
+        assert_eq!(config.check_cheat_score(50, None), None);
+        assert_eq!(
+            config.check_cheat_score(51, Some(0)),
+            Some(AntiCheatRule::MaxIncrease)
+        );
+    }
+
+    #[test]
+    fn anti_cheat_rules_are_optional() {
+        let config = RankTableConfig::default();
+        assert!(!config.has_anti_cheat());
+        assert!(!config.has_rate_limit());
+        assert_eq!(config.check_cheat_score(DEFAULT_MAX_SCORE, Some(0)), None);
+        let config = RankTableConfig {
+            cheat_min_interval: 5,
+            ..Default::default()
+        };
+        assert!(config.has_anti_cheat());
+        assert!(config.has_rate_limit());
+    }
+
+    #[test]
+    fn is_score_outlier_compares_gap_to_top() {
+        let config = RankTableConfig {
+            min_score: 0,
+            max_score: 10000,
+            review_top_ratio: 2.0,
+            ..Default::default()
+        };
+        assert!(!config.is_score_outlier(200, None, Some(100)));
+        assert!(config.is_score_outlier(201, None, Some(100)));
+        // 排行榜为空时不校验
+        assert!(!config.is_score_outlier(10000, None, None));
+        // 升序比较与分数上限的差距
+        let config = RankTableConfig {
+            sort_order: SortOrder::Asc,
+            ..config
+        };
+        assert!(!config.is_score_outlier(9800, None, Some(9900)));
+        assert!(config.is_score_outlier(9799, None, Some(9900)));
+    }
+
+    #[test]
+    fn is_score_outlier_uses_update_policy() {
+        let config = RankTableConfig {
+            min_score: 0,
+            max_score: 10000,
+            review_top_ratio: 2.0,
+            update_policy: UpdatePolicy::Increment,
+            ..Default::default()
+        };
+        assert!(config.is_score_outlier(101, Some(100), Some(100)));
+        // 最高分策略按处理后的分数比较，低于当前分数的提交不会变成离群值
+        let config = RankTableConfig {
+            update_policy: UpdatePolicy::Max,
+            ..config
+        };
+        assert!(!config.is_score_outlier(150, Some(200), Some(100)));
+        // 未配置离群阈值时不校验
+        let config = RankTableConfig {
+            review_top_ratio: 0.0,
+            ..config
+        };
+        assert!(!config.is_score_outlier(10000, None, Some(1)));
+    }
+
+    #[test]
+    fn increment_total_stays_in_bounds() {
+        let config = RankTableConfig {
+            update_policy: UpdatePolicy::Increment,
+            min_score: -100,
+            max_score: 1000,
+            tie_policy: TiePolicy::Earliest,
+            ..Default::default()
+        };
+        assert_eq!(config.increment_total(990, 10), 1000);
+        assert_eq!(config.increment_total(990, 11), 1000);
+        assert_eq!(config.increment_total(-90, -10), -100);
+        assert_eq!(config.increment_total(-90, -11), -100);
+        assert_eq!(config.increment_total(0, i64::MAX), 1000);
+        // 限制后的分数编码不超过 2^53，同分排序值不变
+        let update_time = TIE_BREAK_BASE_TIME_STAMP + 1000;
+        let top = config.encode_redis_score(config.increment_total(990, 11), update_time);
+        assert!(top <= (1u64 << 53) as f64);
+        assert_eq!(config.decode_redis_score(top), 1000);
+        let bottom = config.encode_redis_score(config.increment_total(-90, -11), update_time);
+        assert_eq!(config.decode_redis_score(bottom), -100);
+        let scale = (1i64 << config.tie_bits()) as f64;
+        assert_eq!(top % scale, bottom % scale);
+    }
+}
@@ -22,6 +22,56 @@ pub struct RankTableConfig {
     pub rank_key: ::prost::alloc::string::String,
     #[prost(string, tag = "4")]
     pub cron_expression: ::prost::alloc::string::String,
+    #[prost(string, tag = "5")]
+    pub update_policy: ::prost::alloc::string::String,
+    #[prost(string, tag = "6")]
+    pub sort_order: ::prost::alloc::string::String,
+    #[prost(int32, tag = "7")]
+    pub max_page_size: i32,
+    #[prost(string, tag = "8")]
+    pub score_type: ::prost::alloc::string::String,
+    #[prost(int32, tag = "9")]
+    pub score_precision: i32,
+    #[prost(int64, tag = "10")]
+    pub min_score: i64,
+    #[prost(int64, tag = "11")]
+    pub max_score: i64,
+    #[prost(string, tag = "12")]
+    pub tie_policy: ::prost::alloc::string::String,
+    #[prost(string, tag = "13")]
+    pub rank_style: ::prost::alloc::string::String,
+    #[prost(int32, tag = "14")]
+    pub season: i32,
+    #[prost(string, tag = "15")]
+    pub reward_brackets: ::prost::alloc::string::String,
+    #[prost(int64, tag = "16")]
+    pub window_seconds: i64,
+    #[prost(int64, tag = "17")]
+    pub bucket_seconds: i64,
+    #[prost(string, tag = "18")]
+    pub board_group: ::prost::alloc::string::String,
+    #[prost(string, tag = "19")]
+    pub team_aggregate: ::prost::alloc::string::String,
+    #[prost(int32, tag = "20")]
+    pub team_top_k: i32,
+    #[prost(string, tag = "21")]
+    pub composite_sources: ::prost::alloc::string::String,
+    #[prost(int64, tag = "22")]
+    pub max_entries: i64,
+    #[prost(bool, tag = "23")]
+    pub prune_mysql: bool,
+    #[prost(int64, optional, tag = "24")]
+    pub cheat_max_score: ::core::option::Option<i64>,
+    #[prost(int64, optional, tag = "25")]
+    pub cheat_max_increase: ::core::option::Option<i64>,
+    #[prost(int32, tag = "26")]
+    pub cheat_max_per_minute: i32,
+    #[prost(int32, tag = "27")]
+    pub cheat_min_interval: i32,
+    #[prost(string, tag = "28")]
+    pub cheat_action: ::prost::alloc::string::String,
+    #[prost(double, tag = "29")]
+    pub review_top_ratio: f64,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -1,14 +1,20 @@
-use crate::config::parameter::CALC_SCORE_BASE_TIME_STAMP;
 use crate::db::database::{Database, DatabaseTrait};
 use async_trait::async_trait;
+use chrono::Utc;
 use deadpool_redis::{Pool, PoolError};
 // use sqlx::Error;
 use redis::cmd;
+use sqlx::mysql::MySqlDatabaseError;
+use sqlx::{MySql, QueryBuilder, Transaction};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use crate::dto::rank_dto::{AddRankConfigReq, UpdateScoreRequest};
-use crate::model::user::{RankTableConfig, UserScoreInfo};
-use chrono::Utc;
+use crate::dto::rank_dto::{AddRankConfigReq, ScoreRequestSource};
+use crate::model::user::{
+    BanType, RankArchiveEntry, RankArchivePeriod, RankBan, RankCheatViolation,
+    RankRewardSettlement, RankScoreHistory, RankScoreReview, RankStyle, RankTableConfig,
+    RankTeamMember, ReviewStatus, SortOrder, TiePolicy, UpdatePolicy, UserScoreInfo,
+};
 #[derive(Clone)]
 pub struct RankRepository {
     /// 主从分离
@@ -18,19 +24,54 @@ pub struct RankRepository {
 
 #[async_trait]
 pub trait RankRepositoryTrait {
-    fn new(
-        db_conn: &Arc<Database>,
-        redis_con_pool: &Pool,
-    ) -> Self;
+    fn new(db_conn: &Arc<Database>, redis_con_pool: &Pool) -> Self;
 
     async fn test_redis(&self) -> Result<(), String>;
 
     /// 更新用户分数
     async fn update_rank_score_to_mysql(
         &self,
-        payload: &UpdateScoreRequest,
+        user_score: &UserScoreInfo,
+        rank_table_config: &RankTableConfig,
+        source: &ScoreRequestSource,
+    ) -> Result<(), sqlx::Error>;
+
+    /// 批量更新同一个排行榜的用户分数
+    async fn batch_update_rank_score_to_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        user_scores: &Vec<UserScoreInfo>,
+        rank_table_config: &RankTableConfig,
+        source: &ScoreRequestSource,
     ) -> Result<(), sqlx::Error>;
 
+    /// 分页获取玩家的分数提交历史，最新的在前
+    async fn get_score_history_from_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        openid: &String,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankScoreHistory>, sqlx::Error>;
+
+    /// 删除玩家在appid下所有排行榜的分数提交历史
+    async fn delete_score_history_from_mysql(
+        &self,
+        appid: &String,
+        openid: &String,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// 在同一个事务中删除玩家在appid下的归档排名、奖励结算、违规记录和可疑分数审核
+    ///
+    /// 返回每个表删除的行数
+    async fn delete_user_records_from_mysql(
+        &self,
+        appid: &String,
+        openid: &String,
+    ) -> Result<(u64, u64, u64, u64), sqlx::Error>;
+
     /// 获取用户分数
     async fn get_user_score_info_from_mysql(
         &self,
@@ -42,15 +83,27 @@ pub trait RankRepositoryTrait {
     /// 更新用户分数
     async fn update_rank_score_to_redis(
         &self,
-        payload: &UpdateScoreRequest,
+        user_score: &UserScoreInfo,
+        update_policy: &UpdatePolicy,
+        rank_table_config: &RankTableConfig,
     ) -> Result<(), PoolError>;
 
-    /// 用户信息写入redis
-    async fn update_user_info_to_redis(
+    /// 批量更新用户分数和用户信息，一次pipeline写入
+    async fn batch_update_rank_score_to_redis(
         &self,
-        payload: &UpdateScoreRequest,
+        user_scores: &Vec<(&UserScoreInfo, &RankTableConfig)>,
     ) -> Result<(), PoolError>;
 
+    /// 用户信息写入redis
+    async fn update_user_info_to_redis(&self, user_score: &UserScoreInfo) -> Result<(), PoolError>;
+
+    /// 删除redis中的用户信息，返回是否存在
+    async fn delete_user_info_from_redis(
+        &self,
+        appid: &String,
+        openid: &String,
+    ) -> Result<bool, PoolError>;
+
     /// 从redis获取用户信息
     async fn get_user_info_from_redis(
         &self,
@@ -58,13 +111,31 @@ pub trait RankRepositoryTrait {
         openid: &String,
     ) -> Result<String, PoolError>;
 
-    /// 获取用户分数
+    /// 从redis批量获取用户信息
+    async fn get_users_info_from_redis(
+        &self,
+        appid: &String,
+        openids: &Vec<String>,
+    ) -> Result<Vec<Option<String>>, PoolError>;
+
+    /// 获取用户在redis中编码后的分数，不在榜上返回 None
     async fn get_user_score_from_redis(
         &self,
         appid: &String,
         openid: &String,
         rank_key: &String,
-    ) -> Result<i32, PoolError>;
+        partition: &Option<String>,
+    ) -> Result<Option<f64>, PoolError>;
+
+    /// 批量获取用户编码后的分数、排名(从0开始)和用户信息，一次pipeline读取
+    async fn get_users_score_ranking_from_redis(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+        openids: &Vec<String>,
+        sort_order: &SortOrder,
+    ) -> Result<(Vec<(Option<f64>, Option<i32>)>, Vec<Option<String>>), PoolError>;
 
     /// 获取用户排名
     async fn get_user_ranking(
@@ -72,17 +143,349 @@ pub trait RankRepositoryTrait {
         appid: &String,
         openid: &String,
         rank_key: &String,
+        partition: &Option<String>,
+        sort_order: &SortOrder,
     ) -> Result<i32, PoolError>;
 
-    /// 获取top用户
+    /// 按排名方式获取分数对应的排名，用于并列排名
+    ///
+    /// scores 是redis中编码后的分数，competition 为分数更好的人数加1，dense 为更好的不同分数个数加1
+    async fn get_scores_ranking(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+        scores: &Vec<f64>,
+        rank_style: &RankStyle,
+        sort_order: &SortOrder,
+    ) -> Result<Vec<i32>, PoolError>;
+
+    /// 获取上榜人数
+    async fn get_rank_user_count(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+    ) -> Result<i64, PoolError>;
+
+    /// 批量获取玩家是否因为超出保留人数被移出排行榜，再次上榜后不再是
+    async fn get_trimmed_users_from_redis(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        openids: &Vec<String>,
+    ) -> Result<Vec<bool>, PoolError>;
+
+    /// 获取上榜人数、编码后的最低分和最高分
+    async fn get_rank_stats_from_redis(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+    ) -> Result<(i64, Option<f64>, Option<f64>), PoolError>;
+
+    /// 获取排名区间内的用户和编码后的分数
+    ///
+    /// start、stop 从0开始，包含stop
+    async fn get_range_user_rank(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+        start: i32,
+        stop: i32,
+        sort_order: &SortOrder,
+    ) -> Result<Vec<(String, f64)>, PoolError>;
+
+    /// 获取排行榜的分区和每个分区的上榜人数
+    async fn get_rank_partitions_from_redis(
+        &self,
+        appid: &String,
+        rank_key: &String,
+    ) -> Result<Vec<(String, i64)>, PoolError>;
+
+    /// 更新玩家所在的队伍到mysql
+    async fn update_team_member_to_mysql(
+        &self,
+        team_member: &RankTeamMember,
+    ) -> Result<(), sqlx::Error>;
+
+    /// 从mysql删除玩家所在的队伍，返回删除的行数
+    async fn delete_team_member_from_mysql(
+        &self,
+        appid: &String,
+        openid: &String,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// 分页获取所有的队伍成员
+    async fn get_pagination_team_members_from_mysql(
+        &self,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankTeamMember>, sqlx::Error>;
+
+    /// 更新玩家所在的队伍到redis，team_id 为 None 时离开队伍
+    ///
+    /// 在一个脚本中读取原来的队伍、移动玩家并重新计算原来的队伍和新队伍在这些排行榜上的队伍分数，
+    /// max_team_size 大于0时新队伍已满则不加入。返回是否更新和原来的队伍
+    async fn update_team_member_to_redis(
+        &self,
+        appid: &String,
+        openid: &String,
+        team_id: &Option<String>,
+        max_team_size: i32,
+        rank_table_configs: &Vec<RankTableConfig>,
+    ) -> Result<(bool, Option<String>), PoolError>;
+
+    /// 批量写入队伍成员到redis，不计算队伍分数
+    async fn add_team_members_to_redis(
+        &self,
+        team_members: &Vec<RankTeamMember>,
+    ) -> Result<(), PoolError>;
+
+    /// 获取队伍的成员
+    async fn get_team_members_from_redis(
+        &self,
+        appid: &String,
+        team_id: &String,
+    ) -> Result<Vec<String>, PoolError>;
+
+    /// 获取队伍分数和排名，排名从1开始，0 未上榜
+    async fn get_team_rank_from_redis(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        team_id: &String,
+        sort_order: &SortOrder,
+    ) -> Result<(Option<f64>, i32), PoolError>;
+
+    /// 获取队伍排行榜排名区间内的队伍
+    async fn get_team_range_from_redis(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        start: i32,
+        stop: i32,
+        sort_order: &SortOrder,
+    ) -> Result<Vec<(String, f64)>, PoolError>;
+
+    /// 按来源排行榜的权重重新计算整个组合排行榜
+    async fn merge_composite_rank_to_redis(
+        &self,
+        rank_table_config: &RankTableConfig,
+        source_weights: &Vec<(String, f64)>,
+    ) -> Result<(), PoolError>;
+
+    /// 重新计算玩家在组合排行榜上的分数
+    ///
+    /// user_composites 每一项为 (openid, 组合排行榜, 来源排行榜的权重)
+    async fn update_composite_score_to_redis(
+        &self,
+        appid: &String,
+        user_composites: &Vec<(String, String, Vec<(String, f64)>)>,
+    ) -> Result<(), PoolError>;
+
+    /// 移除排行榜保留人数之外的玩家，同时从所在的分区中移除
     ///
-    async fn get_top_user_rank(
+    /// 每次最多移除 batch_size 个，返回移除的玩家
+    async fn trim_rank_entries_from_redis(
+        &self,
+        rank_table_config: &RankTableConfig,
+        batch_size: i64,
+    ) -> Result<Vec<String>, PoolError>;
+
+    /// 从mysql删除玩家的分数
+    async fn delete_users_score_info_from_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        openids: &Vec<String>,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// 从这些排行榜中移除玩家，返回玩家是否在每个排行榜上
+    async fn remove_user_from_redis(
+        &self,
+        appid: &String,
+        openid: &String,
+        rank_table_configs: &Vec<RankTableConfig>,
+        now: i64,
+    ) -> Result<Vec<bool>, PoolError>;
+
+    /// 更新玩家的封禁到mysql
+    async fn update_ban_to_mysql(&self, ban: &RankBan) -> Result<(), sqlx::Error>;
+
+    /// 从mysql删除玩家的封禁，返回删除的行数
+    async fn delete_ban_from_mysql(
+        &self,
+        appid: &String,
+        openid: &String,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// 从mysql删除已经过期的封禁，返回删除的行数
+    async fn delete_expired_ban_from_mysql(
+        &self,
+        appid: &String,
+        openid: &String,
+        now: i64,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// 获取已经过期的封禁
+    async fn get_expired_bans_from_mysql(
+        &self,
+        now: i64,
+        limit: u64,
+    ) -> Result<Vec<RankBan>, sqlx::Error>;
+
+    /// 分页获取所有的封禁
+    async fn get_pagination_bans_from_mysql(
+        &self,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankBan>, sqlx::Error>;
+
+    /// 分页获取appid下的封禁，最新的在前
+    async fn get_app_bans_from_mysql(
+        &self,
+        appid: &String,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankBan>, sqlx::Error>;
+
+    /// 批量获取玩家的封禁方式
+    async fn get_ban_types_from_redis(
+        &self,
+        appid: &String,
+        openids: &Vec<String>,
+    ) -> Result<Vec<Option<BanType>>, PoolError>;
+
+    /// 获取被封禁的玩家在排行榜上的分数
+    async fn get_banned_score_from_redis(
         &self,
         appid: &String,
+        openid: &String,
         rank_key: &String,
-        top: i32,
+    ) -> Result<Option<f64>, PoolError>;
+
+    /// 批量写入封禁到redis，不移动排行榜中的分数
+    async fn add_bans_to_redis(&self, bans: &Vec<RankBan>) -> Result<(), PoolError>;
+
+    /// 封禁玩家，把玩家从这些排行榜移到封禁的排行榜
+    async fn ban_user_to_redis(
+        &self,
+        ban: &RankBan,
+        rank_table_configs: &Vec<RankTableConfig>,
+    ) -> Result<(), PoolError>;
+
+    /// 解除封禁，把玩家从封禁的排行榜移回这些排行榜
+    async fn unban_user_to_redis(
+        &self,
+        appid: &String,
+        openid: &String,
+        rank_table_configs: &Vec<RankTableConfig>,
+    ) -> Result<(), PoolError>;
+
+    /// 把排行榜中所有被封禁的玩家移到封禁的排行榜
+    async fn apply_bans_to_redis(
+        &self,
+        rank_table_config: &RankTableConfig,
+    ) -> Result<(), PoolError>;
+
+    /// 更新影子封禁的玩家的分数，只写入封禁的排行榜
+    async fn update_shadow_score_to_redis(
+        &self,
+        user_scores: &Vec<(&UserScoreInfo, &RankTableConfig)>,
+    ) -> Result<(), PoolError>;
+
+    /// 获取玩家当前的分数和榜首的分数，用于防作弊校验，一次pipeline读取
+    ///
+    /// 每一项为 (分数, 排行榜配置, 是否影子封禁)，返回每一项玩家编码后的分数和榜首编码后的分数
+    async fn get_anti_cheat_scores_from_redis(
+        &self,
+        user_scores: &Vec<(&UserScoreInfo, &RankTableConfig, bool)>,
+    ) -> Result<Vec<(Option<f64>, Option<f64>)>, PoolError>;
+
+    /// 校验并记录提交频率，只对通过其他校验、会被接受的提交调用
+    ///
+    /// 违反频率规则的提交不计数，返回每一项违反的频率规则(没有违反为空)
+    async fn charge_anti_cheat_rate_in_redis(
+        &self,
+        user_scores: &Vec<(&UserScoreInfo, &RankTableConfig)>,
+        now: i64,
     ) -> Result<Vec<String>, PoolError>;
 
+    /// 写入失败时退还已经记录的提交频率
+    async fn refund_anti_cheat_rate_in_redis(
+        &self,
+        user_scores: &Vec<(&UserScoreInfo, &RankTableConfig)>,
+        now: i64,
+    ) -> Result<(), PoolError>;
+
+    /// 批量记录违反防作弊规则的提交
+    async fn add_cheat_violations_to_mysql(
+        &self,
+        violations: &Vec<RankCheatViolation>,
+    ) -> Result<(), sqlx::Error>;
+
+    /// 分页获取appid下违反防作弊规则的提交，openid 为空时获取所有玩家
+    async fn get_cheat_violations_from_mysql(
+        &self,
+        appid: &String,
+        openid: &Option<String>,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankCheatViolation>, sqlx::Error>;
+
+    /// 批量添加等待审核的可疑分数
+    async fn add_score_reviews_to_mysql(
+        &self,
+        reviews: &Vec<RankScoreReview>,
+    ) -> Result<(), sqlx::Error>;
+
+    /// 获取appid下的一条可疑分数审核
+    async fn get_score_review_from_mysql(
+        &self,
+        appid: &String,
+        id: i64,
+    ) -> Result<Option<RankScoreReview>, sqlx::Error>;
+
+    /// 分页获取appid下某个状态的可疑分数审核
+    async fn get_score_reviews_from_mysql(
+        &self,
+        appid: &String,
+        status: &ReviewStatus,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankScoreReview>, sqlx::Error>;
+
+    /// 修改可疑分数的审核状态，只修改状态为 from 的记录，返回修改的行数
+    async fn update_score_review_status_to_mysql(
+        &self,
+        appid: &String,
+        id: i64,
+        from: &ReviewStatus,
+        to: &ReviewStatus,
+        review_time: i64,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// 拒绝玩家在排行榜上所有等待审核的可疑分数，返回修改的行数
+    async fn reject_user_score_reviews_to_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        openid: &String,
+        review_time: i64,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// 审核通过等待审核的可疑分数，同一个事务中修改审核状态并按更新策略写入分数，
+    /// 记录已经审核过时不写入分数并返回 false
+    async fn approve_score_review_to_mysql(
+        &self,
+        review: &RankScoreReview,
+        user_score: &UserScoreInfo,
+        rank_table_config: &RankTableConfig,
+        source: &ScoreRequestSource,
+    ) -> Result<bool, sqlx::Error>;
+
     /// 获取排行榜表配置
     async fn get_rank_table_config_from_mysql(&self) -> Result<Vec<RankTableConfig>, sqlx::Error>;
 
@@ -110,9 +513,95 @@ pub trait RankRepositoryTrait {
         rank_key: &String,
     ) -> Result<(), PoolError>;
 
+    /// 重建排行榜时写入临时key
+    async fn add_rank_score_to_redis_rebuild_key(
+        &self,
+        user_scores: &Vec<UserScoreInfo>,
+        rank_table_config: &RankTableConfig,
+    ) -> Result<(), PoolError>;
+
+    /// 归档mysql排行榜数据并结算奖励，返回归档的期号
+    async fn archive_rank_to_mysql(
+        &self,
+        rank_table_config: &RankTableConfig,
+        end_time: i64,
+    ) -> Result<i64, sqlx::Error>;
+
+    /// 分页获取排行榜归档的期，最新的在前
+    async fn get_archive_periods_from_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankArchivePeriod>, sqlx::Error>;
+
+    /// 获取排行榜归档的一期
+    async fn get_archive_period_from_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        period_id: i64,
+    ) -> Result<RankArchivePeriod, sqlx::Error>;
+
+    /// 分页获取排行榜归档的玩家排名
+    async fn get_archive_entries_from_mysql(
+        &self,
+        period_id: i64,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankArchiveEntry>, sqlx::Error>;
+
+    /// 获取未确认的奖励结算
+    async fn get_pending_reward_settlements_from_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        limit: u64,
+    ) -> Result<Vec<RankRewardSettlement>, sqlx::Error>;
+
+    /// 确认奖励结算，返回本次确认的数量
+    async fn ack_reward_settlements_to_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        ids: &Vec<i64>,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// 清理重建排行榜的临时key
+    async fn clear_redis_rebuild_key(
+        &self,
+        appid: &String,
+        rank_key: &String,
+    ) -> Result<(), PoolError>;
+
+    /// 用重建好的临时key替换排行榜
+    async fn replace_rank_with_redis_rebuild_key(
+        &self,
+        appid: &String,
+        rank_key: &String,
+    ) -> Result<(), PoolError>;
+
+    /// 合并滚动排行榜时间窗口内的分桶，并删除过期的分桶
+    async fn merge_rank_window_to_redis(
+        &self,
+        rank_table_config: &RankTableConfig,
+        now: i64,
+    ) -> Result<(), PoolError>;
+
+    /// 清理滚动排行榜的分桶
+    async fn clear_rank_window_from_redis(
+        &self,
+        rank_table_config: &RankTableConfig,
+        now: i64,
+    ) -> Result<(), PoolError>;
+
     /// 添加排行榜配置到mysql
-    async fn add_rank_config_to_mysql(&self, payload: &AddRankConfigReq)
-        -> Result<(), sqlx::Error>;
+    async fn add_rank_config_to_mysql(
+        &self,
+        payload: &AddRankConfigReq,
+        rank_table_config: &RankTableConfig,
+    ) -> Result<(), sqlx::Error>;
 
     /// 动态创建rank表
     async fn create_rank_table_to_mysql(
@@ -127,14 +616,17 @@ pub trait RankRepositoryTrait {
         rank_key: &String,
     ) -> Result<(), sqlx::Error>;
 
+    /// 排行榜赛季加1
+    async fn increase_rank_season_to_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+    ) -> Result<(), sqlx::Error>;
 }
 
 #[async_trait]
 impl RankRepositoryTrait for RankRepository {
-    fn new(
-        db_conn: &Arc<Database>,
-        redis_con_pool: &Pool,
-    ) -> Self {
+    fn new(db_conn: &Arc<Database>, redis_con_pool: &Pool) -> Self {
         Self {
             db_conn: Arc::clone(db_conn),
             redis_con_pool: redis_con_pool.clone(),
@@ -169,38 +661,163 @@ impl RankRepositoryTrait for RankRepository {
         Ok(())
     }
 
-    // 更新分数到mysql
+    // 更新分数到mysql，同一个事务中写入分数提交历史
     async fn update_rank_score_to_mysql(
         &self,
-        payload: &UpdateScoreRequest,
+        user_score: &UserScoreInfo,
+        rank_table_config: &RankTableConfig,
+        source: &ScoreRequestSource,
     ) -> Result<(), sqlx::Error> {
-        let table_name = get_mysql_table_name(&payload.appid, &payload.rank_key);
-        let sql = format!(
-            "INSERT INTO {} (
-			openid,
-			nick_name,
-			score
-		)
-		VALUES('{}','{}',{}) 
-		ON DUPLICATE KEY
-		UPDATE nick_name='{}',score={};",
-            table_name,
-            payload.openid,
-            payload.nick_name,
-            payload.score,
-            payload.nick_name,
-            payload.score
-        );
-        let sql_ret = sqlx::query(&sql)
-            .execute(self.db_conn.get_master_pool())
-            .await?;
-        // 成功
-        tracing::debug!("set_user_score - rows_affected:{}", sql_ret.rows_affected());
-        Ok(())
-    }
-
-    async fn get_user_score_info_from_mysql(
-        &self,
+        let table_name = get_mysql_table_name(&user_score.appid, &user_score.rank_key);
+        let mut attempt = 1;
+        loop {
+            let mut tx = self.db_conn.get_master_pool().begin().await?;
+            match update_rank_scores_in_tx(
+                &mut tx,
+                &table_name,
+                std::slice::from_ref(user_score),
+                rank_table_config,
+                source,
+            )
+            .await
+            {
+                Ok(_) => return tx.commit().await,
+                Err(err)
+                    if attempt < MYSQL_SCORE_WRITE_ATTEMPTS && is_retryable_score_write(&err) =>
+                {
+                    tracing::warn!(
+                        "update score to mysql retry, attempt:{} | error:{}",
+                        attempt,
+                        err
+                    );
+                    tx.rollback().await?;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // 批量更新分数到mysql，同一个事务中写入分数提交历史
+    async fn batch_update_rank_score_to_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        user_scores: &Vec<UserScoreInfo>,
+        rank_table_config: &RankTableConfig,
+        source: &ScoreRequestSource,
+    ) -> Result<(), sqlx::Error> {
+        if user_scores.is_empty() {
+            return Ok(());
+        }
+        let table_name = get_mysql_table_name(appid, rank_key);
+        let mut attempt = 1;
+        loop {
+            let mut tx = self.db_conn.get_master_pool().begin().await?;
+            match update_rank_scores_in_tx(
+                &mut tx,
+                &table_name,
+                user_scores,
+                rank_table_config,
+                source,
+            )
+            .await
+            {
+                Ok(_) => return tx.commit().await,
+                Err(err)
+                    if attempt < MYSQL_SCORE_WRITE_ATTEMPTS && is_retryable_score_write(&err) =>
+                {
+                    tracing::warn!(
+                        "batch update score to mysql retry, rank_key:{} | attempt:{} | error:{}",
+                        rank_key,
+                        attempt,
+                        err
+                    );
+                    tx.rollback().await?;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// 分页获取玩家的分数提交历史，最新的在前
+    async fn get_score_history_from_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        openid: &String,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankScoreHistory>, sqlx::Error> {
+        let histories = sqlx::query_as::<_, RankScoreHistory>(
+            "SELECT * FROM rank_score_history WHERE appid = ? AND rank_key = ? AND openid = ? ORDER BY id DESC LIMIT ?,?",
+        )
+        .bind(appid)
+        .bind(rank_key)
+        .bind(openid)
+        .bind(start_index)
+        .bind(page_size)
+        .fetch_all(self.db_conn.get_slave_pool())
+        .await?;
+        Ok(histories)
+    }
+
+    /// 删除玩家在appid下所有排行榜的分数提交历史
+    async fn delete_score_history_from_mysql(
+        &self,
+        appid: &String,
+        openid: &String,
+    ) -> Result<u64, sqlx::Error> {
+        let sql_ret = sqlx::query("DELETE FROM rank_score_history WHERE appid = ? AND openid = ?")
+            .bind(appid)
+            .bind(openid)
+            .execute(self.db_conn.get_master_pool())
+            .await?;
+        Ok(sql_ret.rows_affected())
+    }
+
+    /// 在同一个事务中删除玩家在appid下的归档排名、奖励结算、违规记录和可疑分数审核
+    ///
+    /// 返回每个表删除的行数
+    async fn delete_user_records_from_mysql(
+        &self,
+        appid: &String,
+        openid: &String,
+    ) -> Result<(u64, u64, u64, u64), sqlx::Error> {
+        let mut tx = self.db_conn.get_master_pool().begin().await?;
+        // 归档排名中没有appid，按归档期数关联
+        let archive_rows = sqlx::query(
+            "DELETE e FROM rank_archive_entry e
+			JOIN rank_archive_period p ON e.period_id = p.period_id
+			WHERE p.appid = ? AND e.openid = ?",
+        )
+        .bind(appid)
+        .bind(openid)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        let mut rows = vec![];
+        for table_name in [
+            "rank_reward_settlement",
+            "rank_cheat_violation",
+            "rank_score_review",
+        ] {
+            let sql_ret = sqlx::query(&format!(
+                "DELETE FROM {table_name} WHERE appid = ? AND openid = ?"
+            ))
+            .bind(appid)
+            .bind(openid)
+            .execute(&mut *tx)
+            .await?;
+            rows.push(sql_ret.rows_affected());
+        }
+        tx.commit().await?;
+        Ok((archive_rows, rows[0], rows[1], rows[2]))
+    }
+
+    async fn get_user_score_info_from_mysql(
+        &self,
         appid: &String,
         openid: &String,
         rank_key: &String,
@@ -210,47 +827,99 @@ impl RankRepositoryTrait for RankRepository {
         let user = sqlx::query_as::<_, UserScoreInfo>(&sql)
             .bind(openid)
             .fetch_one(self.db_conn.get_slave_pool())
-            .await;
+            .await
+            .map(|mut user| {
+                user.appid = appid.clone();
+                user.rank_key = rank_key.clone();
+                user
+            });
         return user;
     }
 
     // 更新分数到redis
     async fn update_rank_score_to_redis(
         &self,
-        payload: &UpdateScoreRequest,
+        user_score: &UserScoreInfo,
+        update_policy: &UpdatePolicy,
+        rank_table_config: &RankTableConfig,
     ) -> Result<(), PoolError> {
         let mut con = self.redis_con_pool.get().await?;
-        let score = calc_score(payload.score);
 
         let mut cmd_pipe = redis::pipe();
-        let key = get_redis_rank_key(&payload.appid, &payload.rank_key);
-        cmd_pipe
-            .cmd("ZADD")
-            .arg(key)
-            .arg(score)
-            .arg(&payload.openid);
+        let key = get_redis_rank_key(&user_score.appid, &user_score.rank_key);
+        add_update_score_cmd(
+            &mut cmd_pipe,
+            &key,
+            user_score,
+            update_policy,
+            rank_table_config,
+        );
 
         let _ = cmd_pipe.query_async(&mut con).await?;
         Ok(())
     }
 
-    // 更新用户信息到redis
-    async fn update_user_info_to_redis(
+    // 批量更新分数和用户信息到redis
+    async fn batch_update_rank_score_to_redis(
         &self,
-        payload: &UpdateScoreRequest,
+        user_scores: &Vec<(&UserScoreInfo, &RankTableConfig)>,
     ) -> Result<(), PoolError> {
+        if user_scores.is_empty() {
+            return Ok(());
+        }
+        let mut con = self.redis_con_pool.get().await?;
+
+        let mut cmd_pipe = redis::pipe();
+        for (user_score, rank_table_config) in user_scores {
+            let key = get_redis_rank_key(&user_score.appid, &user_score.rank_key);
+            add_update_score_cmd(
+                &mut cmd_pipe,
+                &key,
+                user_score,
+                &rank_table_config.update_policy,
+                rank_table_config,
+            );
+            cmd_pipe
+                .cmd("HSET")
+                .arg(get_redis_user_key(&user_score.appid))
+                .arg(&user_score.openid)
+                .arg(&user_score.nick_name)
+                .ignore();
+        }
+
+        let _: () = cmd_pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    // 更新用户信息到redis
+    async fn update_user_info_to_redis(&self, user_score: &UserScoreInfo) -> Result<(), PoolError> {
         let mut con = self.redis_con_pool.get().await?;
-        let key = get_redis_user_key(&payload.appid);
+        let key = get_redis_user_key(&user_score.appid);
 
         let _ = redis::cmd("HSET")
             .arg(key)
-            .arg(&payload.openid)
-            .arg(&payload.nick_name)
+            .arg(&user_score.openid)
+            .arg(&user_score.nick_name)
             .query_async(&mut con)
             .await?;
         Ok(())
     }
 
+    /// 删除redis中的用户信息，返回是否存在
+    async fn delete_user_info_from_redis(
+        &self,
+        appid: &String,
+        openid: &String,
+    ) -> Result<bool, PoolError> {
+        let mut con = self.redis_con_pool.get().await?;
+        let count: i64 = redis::cmd("HDEL")
+            .arg(get_redis_user_key(appid))
+            .arg(openid)
+            .query_async(&mut con)
+            .await?;
+        Ok(count > 0)
+    }
+
     async fn get_user_info_from_redis(
         &self,
         appid: &String,
@@ -266,27 +935,41 @@ impl RankRepositoryTrait for RankRepository {
         Ok(nick_name)
     }
 
+    async fn get_users_info_from_redis(
+        &self,
+        appid: &String,
+        openids: &Vec<String>,
+    ) -> Result<Vec<Option<String>>, PoolError> {
+        if openids.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let key = get_redis_user_key(appid);
+        let nick_names: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(key)
+            .arg(openids)
+            .query_async(&mut con)
+            .await?;
+        Ok(nick_names)
+    }
+
     // 获取用户分数
     async fn get_user_score_from_redis(
         &self,
         appid: &String,
         openid: &String,
         rank_key: &String,
-    ) -> Result<i32, PoolError> {
+        partition: &Option<String>,
+    ) -> Result<Option<f64>, PoolError> {
         let mut con = self.redis_con_pool.get().await?;
 
-        let key = get_redis_rank_key(appid, rank_key);
-        let cur_score: Option<String> = redis::cmd("ZSCORE")
+        let key = get_redis_partition_rank_key(appid, rank_key, partition);
+        let cur_score: Option<f64> = redis::cmd("ZSCORE")
             .arg(key.clone())
             .arg(openid.clone())
             .query_async(&mut con)
             .await?;
-        if cur_score.is_some() {
-            let score_f: f64 = cur_score.unwrap().parse().unwrap();
-            Ok(score_f as i32)
-        } else {
-            Ok(-1)
-        }
+        Ok(cur_score)
     }
 
     /// 获取用户排名
@@ -295,11 +978,13 @@ impl RankRepositoryTrait for RankRepository {
         appid: &String,
         openid: &String,
         rank_key: &String,
+        partition: &Option<String>,
+        sort_order: &SortOrder,
     ) -> Result<i32, PoolError> {
         let mut con = self.redis_con_pool.get().await?;
-        let key = get_redis_rank_key(appid, rank_key);
+        let key = get_redis_partition_rank_key(appid, rank_key, partition);
 
-        let cur_ranking: Option<i32> = redis::cmd("ZREVRANK")
+        let cur_ranking: Option<i32> = redis::cmd(get_redis_rank_cmd(sort_order))
             .arg(key.clone())
             .arg(openid.clone())
             .query_async(&mut con)
@@ -311,27 +996,1232 @@ impl RankRepositoryTrait for RankRepository {
         }
     }
 
-    /// 获取top用户
-    async fn get_top_user_rank(
+    /// 批量获取用户分数、排名和用户信息
+    async fn get_users_score_ranking_from_redis(
         &self,
         appid: &String,
         rank_key: &String,
-        top: i32,
-    ) -> Result<Vec<String>, PoolError> {
-        if top <= 0 {
-            tracing::error!("top <= 0 is error!!");
+        partition: &Option<String>,
+        openids: &Vec<String>,
+        sort_order: &SortOrder,
+    ) -> Result<(Vec<(Option<f64>, Option<i32>)>, Vec<Option<String>>), PoolError> {
+        if openids.is_empty() {
+            return Ok((vec![], vec![]));
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let key = get_redis_partition_rank_key(appid, rank_key, partition);
+
+        let mut cmd_pipe = redis::pipe();
+        for openid in openids {
+            cmd_pipe
+                .cmd("ZSCORE")
+                .arg(&key)
+                .arg(openid)
+                .cmd(get_redis_rank_cmd(sort_order))
+                .arg(&key)
+                .arg(openid);
+        }
+        cmd_pipe
+            .cmd("HMGET")
+            .arg(get_redis_user_key(appid))
+            .arg(openids);
+
+        let mut values: Vec<redis::Value> = cmd_pipe.query_async(&mut con).await?;
+        let nick_names: Vec<Option<String>> =
+            redis::from_redis_value(&values.pop().unwrap_or(redis::Value::Nil))?;
+        let score_rankings: Vec<(Option<f64>, Option<i32>)> =
+            redis::from_redis_value(&redis::Value::Bulk(values))?;
+        Ok((score_rankings, nick_names))
+    }
+
+    /// 按排名方式获取分数对应的排名
+    async fn get_scores_ranking(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+        scores: &Vec<f64>,
+        rank_style: &RankStyle,
+        sort_order: &SortOrder,
+    ) -> Result<Vec<i32>, PoolError> {
+        if scores.is_empty() {
             return Ok(vec![]);
         }
         let mut con = self.redis_con_pool.get().await?;
-        let key = get_redis_rank_key(appid, rank_key);
-        let users: Vec<String> = redis::cmd("ZREVRANGE")
-            .arg(key.clone())
+        let key = get_redis_partition_rank_key(appid, rank_key, partition);
+
+        let better_counts: Vec<i32> = match rank_style {
+            RankStyle::Ordinal | RankStyle::Competition => {
+                let mut cmd_pipe = redis::pipe();
+                for score in scores {
+                    let better = format!("({}", score);
+                    match sort_order {
+                        SortOrder::Desc => cmd_pipe.cmd("ZCOUNT").arg(&key).arg(better).arg("+inf"),
+                        SortOrder::Asc => cmd_pipe.cmd("ZCOUNT").arg(&key).arg("-inf").arg(better),
+                    };
+                }
+                cmd_pipe.query_async(&mut con).await?
+            }
+            // 所有分数在一次脚本调用中计算，避免每个分数各遍历一次
+            RankStyle::Dense => {
+                redis::cmd("EVAL")
+                    .arg(DENSE_RANK_SCRIPT)
+                    .arg(1)
+                    .arg(&key)
+                    .arg(sort_order.as_str())
+                    .arg(scores)
+                    .query_async(&mut con)
+                    .await?
+            }
+        };
+        Ok(better_counts.into_iter().map(|count| count + 1).collect())
+    }
+
+    /// 获取上榜人数
+    async fn get_rank_user_count(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+    ) -> Result<i64, PoolError> {
+        let mut con = self.redis_con_pool.get().await?;
+        let key = get_redis_partition_rank_key(appid, rank_key, partition);
+        let count: i64 = redis::cmd("ZCARD").arg(key).query_async(&mut con).await?;
+        Ok(count)
+    }
+
+    /// 批量获取玩家是否因为超出保留人数被移出排行榜，再次上榜后不再是
+    async fn get_trimmed_users_from_redis(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        openids: &Vec<String>,
+    ) -> Result<Vec<bool>, PoolError> {
+        if openids.is_empty() {
+            return Ok(vec![]);
+        }
+        let key = get_redis_trimmed_user_key(appid, rank_key);
+        let mut cmd_pipe = redis::pipe();
+        for openid in openids {
+            cmd_pipe.cmd("SISMEMBER").arg(&key).arg(openid);
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let trimmed: Vec<bool> = cmd_pipe.query_async(&mut con).await?;
+        Ok(trimmed)
+    }
+
+    /// 获取上榜人数、最低分和最高分
+    async fn get_rank_stats_from_redis(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+    ) -> Result<(i64, Option<f64>, Option<f64>), PoolError> {
+        let mut con = self.redis_con_pool.get().await?;
+        let key = get_redis_partition_rank_key(appid, rank_key, partition);
+        let (count, min, max): (i64, Vec<(String, f64)>, Vec<(String, f64)>) = redis::pipe()
+            .cmd("ZCARD")
+            .arg(&key)
+            .cmd("ZRANGE")
+            .arg(&key)
             .arg(0)
-            .arg(top - 1)
+            .arg(0)
+            .arg("WITHSCORES")
+            .cmd("ZRANGE")
+            .arg(&key)
+            .arg(-1)
+            .arg(-1)
             .arg("WITHSCORES")
             .query_async(&mut con)
             .await?;
-        Ok(users)
+        Ok((
+            count,
+            min.first().map(|(_, score)| *score),
+            max.first().map(|(_, score)| *score),
+        ))
+    }
+
+    /// 获取排名区间内的用户
+    async fn get_range_user_rank(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+        start: i32,
+        stop: i32,
+        sort_order: &SortOrder,
+    ) -> Result<Vec<(String, f64)>, PoolError> {
+        if start < 0 || stop < start {
+            tracing::error!("range start:{} | stop:{} is error!!", start, stop);
+            return Ok(vec![]);
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let key = get_redis_partition_rank_key(appid, rank_key, partition);
+        let users: Vec<(String, f64)> = redis::cmd(get_redis_range_cmd(sort_order))
+            .arg(key.clone())
+            .arg(start)
+            .arg(stop)
+            .arg("WITHSCORES")
+            .query_async(&mut con)
+            .await?;
+        Ok(users)
+    }
+
+    /// 获取排行榜的分区和每个分区的上榜人数
+    async fn get_rank_partitions_from_redis(
+        &self,
+        appid: &String,
+        rank_key: &String,
+    ) -> Result<Vec<(String, i64)>, PoolError> {
+        let mut con = self.redis_con_pool.get().await?;
+        let partitions: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(get_redis_partition_list_key(appid, rank_key))
+            .query_async(&mut con)
+            .await?;
+        if partitions.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut cmd_pipe = redis::pipe();
+        for partition in &partitions {
+            cmd_pipe.cmd("ZCARD").arg(get_redis_partition_rank_key(
+                appid,
+                rank_key,
+                &Some(partition.clone()),
+            ));
+        }
+        let counts: Vec<i64> = cmd_pipe.query_async(&mut con).await?;
+        Ok(partitions.into_iter().zip(counts).collect())
+    }
+
+    /// 更新玩家所在的队伍到mysql
+    async fn update_team_member_to_mysql(
+        &self,
+        team_member: &RankTeamMember,
+    ) -> Result<(), sqlx::Error> {
+        let table_name = "rank_team_member";
+        let sql = format!(
+            "INSERT INTO {table_name} (appid,openid,team_id,update_time) VALUES(?,?,?,?)
+		ON DUPLICATE KEY UPDATE team_id=VALUES(team_id),update_time=VALUES(update_time)"
+        );
+        sqlx::query(&sql)
+            .bind(&team_member.appid)
+            .bind(&team_member.openid)
+            .bind(&team_member.team_id)
+            .bind(team_member.update_time)
+            .execute(self.db_conn.get_master_pool())
+            .await?;
+        Ok(())
+    }
+
+    /// 从mysql删除玩家所在的队伍，返回删除的行数
+    async fn delete_team_member_from_mysql(
+        &self,
+        appid: &String,
+        openid: &String,
+    ) -> Result<u64, sqlx::Error> {
+        let table_name = "rank_team_member";
+        let sql = format!("DELETE FROM {table_name} WHERE appid=? AND openid=?");
+        let sql_ret = sqlx::query(&sql)
+            .bind(appid)
+            .bind(openid)
+            .execute(self.db_conn.get_master_pool())
+            .await?;
+        Ok(sql_ret.rows_affected())
+    }
+
+    /// 分页获取所有的队伍成员
+    async fn get_pagination_team_members_from_mysql(
+        &self,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankTeamMember>, sqlx::Error> {
+        let table_name = "rank_team_member";
+        let sql = format!("SELECT * FROM {table_name} ORDER BY appid,openid LIMIT ?,?");
+        let team_members = sqlx::query_as::<_, RankTeamMember>(&sql)
+            .bind(start_index)
+            .bind(page_size)
+            .fetch_all(self.db_conn.get_slave_pool())
+            .await?;
+        Ok(team_members)
+    }
+
+    /// 更新玩家所在的队伍到redis，team_id 为 None 时离开队伍
+    ///
+    /// 在一个脚本中读取原来的队伍、移动玩家并重新计算相关队伍的分数，避免并发加入时队伍不一致
+    async fn update_team_member_to_redis(
+        &self,
+        appid: &String,
+        openid: &String,
+        team_id: &Option<String>,
+        max_team_size: i32,
+        rank_table_configs: &Vec<RankTableConfig>,
+    ) -> Result<(bool, Option<String>), PoolError> {
+        let mut con = self.redis_con_pool.get().await?;
+        let mut cmd = redis::cmd("EVAL");
+        cmd.arg(TEAM_MEMBER_SCRIPT)
+            .arg(1 + rank_table_configs.len() * 2)
+            .arg(get_redis_team_user_key(appid));
+        for rank_table_config in rank_table_configs {
+            cmd.arg(get_redis_rank_key(appid, &rank_table_config.rank_key))
+                .arg(get_redis_team_rank_key(appid, &rank_table_config.rank_key));
+        }
+        cmd.arg(openid)
+            .arg(team_id.as_deref().unwrap_or_default())
+            .arg(get_redis_team_member_key_prefix(appid))
+            .arg(max_team_size);
+        for rank_table_config in rank_table_configs {
+            cmd.arg(1i64 << rank_table_config.tie_bits())
+                .arg(rank_table_config.min_score)
+                .arg(rank_table_config.team_member_limit())
+                .arg(rank_table_config.sort_order.as_str());
+        }
+        let (updated, old_team_id): (i32, String) = cmd.query_async(&mut con).await?;
+        Ok((
+            updated == 1,
+            Some(old_team_id).filter(|team_id| !team_id.is_empty()),
+        ))
+    }
+
+    /// 批量写入队伍成员到redis，不计算队伍分数
+    async fn add_team_members_to_redis(
+        &self,
+        team_members: &Vec<RankTeamMember>,
+    ) -> Result<(), PoolError> {
+        if team_members.is_empty() {
+            return Ok(());
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let mut cmd_pipe = redis::pipe();
+        for team_member in team_members {
+            cmd_pipe
+                .cmd("HSET")
+                .arg(get_redis_team_user_key(&team_member.appid))
+                .arg(&team_member.openid)
+                .arg(&team_member.team_id)
+                .ignore()
+                .cmd("SADD")
+                .arg(get_redis_team_member_key(
+                    &team_member.appid,
+                    &team_member.team_id,
+                ))
+                .arg(&team_member.openid)
+                .ignore();
+        }
+        let _: () = cmd_pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    /// 获取队伍的成员
+    async fn get_team_members_from_redis(
+        &self,
+        appid: &String,
+        team_id: &String,
+    ) -> Result<Vec<String>, PoolError> {
+        let mut con = self.redis_con_pool.get().await?;
+        let openids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(get_redis_team_member_key(appid, team_id))
+            .query_async(&mut con)
+            .await?;
+        Ok(openids)
+    }
+
+    /// 获取队伍分数和排名，排名从1开始，0 未上榜
+    async fn get_team_rank_from_redis(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        team_id: &String,
+        sort_order: &SortOrder,
+    ) -> Result<(Option<f64>, i32), PoolError> {
+        let mut con = self.redis_con_pool.get().await?;
+        let key = get_redis_team_rank_key(appid, rank_key);
+        let (score, ranking): (Option<f64>, Option<i32>) = redis::pipe()
+            .cmd("ZSCORE")
+            .arg(&key)
+            .arg(team_id)
+            .cmd(get_redis_rank_cmd(sort_order))
+            .arg(&key)
+            .arg(team_id)
+            .query_async(&mut con)
+            .await?;
+        Ok((score, ranking.map_or(0, |ranking| ranking + 1)))
+    }
+
+    /// 获取队伍排行榜排名区间内的队伍
+    async fn get_team_range_from_redis(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        start: i32,
+        stop: i32,
+        sort_order: &SortOrder,
+    ) -> Result<Vec<(String, f64)>, PoolError> {
+        if start < 0 || stop < start {
+            tracing::error!("team range start:{} | stop:{} is error!!", start, stop);
+            return Ok(vec![]);
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let teams: Vec<(String, f64)> = redis::cmd(get_redis_range_cmd(sort_order))
+            .arg(get_redis_team_rank_key(appid, rank_key))
+            .arg(start)
+            .arg(stop)
+            .arg("WITHSCORES")
+            .query_async(&mut con)
+            .await?;
+        Ok(teams)
+    }
+
+    /// 按来源排行榜的权重重新计算整个组合排行榜
+    async fn merge_composite_rank_to_redis(
+        &self,
+        rank_table_config: &RankTableConfig,
+        source_weights: &Vec<(String, f64)>,
+    ) -> Result<(), PoolError> {
+        let appid = &rank_table_config.appid;
+        let key = get_redis_rank_key(appid, &rank_table_config.rank_key);
+        let mut con = self.redis_con_pool.get().await?;
+        if source_weights.is_empty() {
+            let _: () = redis::cmd("DEL").arg(&key).query_async(&mut con).await?;
+            return Ok(());
+        }
+        // ZUNIONSTORE 原子替换，来源排行榜都为空时组合排行榜被删除
+        let _: () = redis::cmd("ZUNIONSTORE")
+            .arg(&key)
+            .arg(source_weights.len())
+            .arg(
+                source_weights
+                    .iter()
+                    .map(|(rank_key, _)| get_redis_rank_key(appid, rank_key))
+                    .collect::<Vec<String>>(),
+            )
+            .arg("WEIGHTS")
+            .arg(
+                source_weights
+                    .iter()
+                    .map(|(_, weight)| *weight)
+                    .collect::<Vec<f64>>(),
+            )
+            .query_async(&mut con)
+            .await?;
+        Ok(())
+    }
+
+    /// 重新计算玩家在组合排行榜上的分数
+    ///
+    /// user_composites 每一项为 (openid, 组合排行榜, 来源排行榜的权重)
+    async fn update_composite_score_to_redis(
+        &self,
+        appid: &String,
+        user_composites: &Vec<(String, String, Vec<(String, f64)>)>,
+    ) -> Result<(), PoolError> {
+        if user_composites.is_empty() {
+            return Ok(());
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let mut cmd_pipe = redis::pipe();
+        for (openid, rank_key, source_weights) in user_composites {
+            cmd_pipe
+                .cmd("EVAL")
+                .arg(COMPOSITE_SCORE_SCRIPT)
+                .arg(source_weights.len() + 1)
+                .arg(get_redis_rank_key(appid, rank_key));
+            for (source_rank_key, _) in source_weights {
+                cmd_pipe.arg(get_redis_rank_key(appid, source_rank_key));
+            }
+            cmd_pipe.arg(openid);
+            for (_, weight) in source_weights {
+                cmd_pipe.arg(*weight);
+            }
+            cmd_pipe.ignore();
+        }
+        let _: () = cmd_pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    /// 移除排行榜保留人数之外的玩家，同时从所在的分区中移除
+    ///
+    /// 每次最多移除 batch_size 个，返回移除的玩家。玩家所在的分区保留，再次上榜时沿用
+    async fn trim_rank_entries_from_redis(
+        &self,
+        rank_table_config: &RankTableConfig,
+        batch_size: i64,
+    ) -> Result<Vec<String>, PoolError> {
+        let appid = &rank_table_config.appid;
+        let rank_key = &rank_table_config.rank_key;
+        let mut con = self.redis_con_pool.get().await?;
+        let openids: Vec<String> = redis::cmd("EVAL")
+            .arg(TRIM_RANK_SCRIPT)
+            .arg(3)
+            .arg(get_redis_rank_key(appid, rank_key))
+            .arg(get_redis_partition_user_key(appid, rank_key))
+            .arg(get_redis_trimmed_user_key(appid, rank_key))
+            .arg(rank_table_config.max_entries)
+            .arg(batch_size)
+            .arg(rank_table_config.sort_order.as_str())
+            .arg(get_redis_partition_rank_key_prefix(appid, rank_key))
+            .query_async(&mut con)
+            .await?;
+        Ok(openids)
+    }
+
+    /// 从mysql删除玩家的分数
+    async fn delete_users_score_info_from_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        openids: &Vec<String>,
+    ) -> Result<u64, sqlx::Error> {
+        if openids.is_empty() {
+            return Ok(0);
+        }
+        let table_name = get_mysql_table_name(appid, rank_key);
+        let mut query_builder: QueryBuilder<MySql> =
+            QueryBuilder::new(format!("DELETE FROM {table_name} WHERE openid IN ("));
+        let mut separated = query_builder.separated(",");
+        for openid in openids {
+            separated.push_bind(openid);
+        }
+        separated.push_unseparated(")");
+        let sql_ret = query_builder
+            .build()
+            .execute(self.db_conn.get_master_pool())
+            .await?;
+        Ok(sql_ret.rows_affected())
+    }
+
+    /// 从这些排行榜中移除玩家，返回玩家是否在每个排行榜上
+    ///
+    /// 同时从所在的分区、封禁的排行榜和滚动排行榜的分桶中移除，并重新计算所在队伍的分数。
+    /// 玩家所在的队伍不变，组合排行榜需要单独重新计算
+    async fn remove_user_from_redis(
+        &self,
+        appid: &String,
+        openid: &String,
+        rank_table_configs: &Vec<RankTableConfig>,
+        now: i64,
+    ) -> Result<Vec<bool>, PoolError> {
+        if rank_table_configs.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let mut cmd_pipe = redis::pipe();
+        cmd_pipe.atomic();
+        for rank_table_config in rank_table_configs {
+            let rank_key = &rank_table_config.rank_key;
+            cmd_pipe
+                .cmd("EVAL")
+                .arg(REMOVE_USER_SCRIPT)
+                .arg(2)
+                .arg(get_redis_rank_key(appid, rank_key))
+                .arg(get_redis_partition_user_key(appid, rank_key))
+                .arg(openid)
+                .arg(get_redis_partition_rank_key_prefix(appid, rank_key));
+            cmd_pipe
+                .cmd("ZREM")
+                .arg(get_redis_banned_rank_key(appid, rank_key))
+                .arg(openid)
+                .ignore()
+                .cmd("SREM")
+                .arg(get_redis_trimmed_user_key(appid, rank_key))
+                .arg(openid)
+                .ignore();
+            if rank_table_config.is_rolling_window() {
+                // 与清理分桶的范围一致，更早的分桶已经过期
+                let index = rank_table_config.window_bucket_index(now);
+                let count = rank_table_config.window_bucket_count();
+                for bucket_index in index - count..=index + 1 {
+                    cmd_pipe
+                        .cmd("ZREM")
+                        .arg(get_redis_rank_bucket_key(appid, rank_key, bucket_index))
+                        .arg(openid)
+                        .ignore();
+                }
+                cmd_pipe
+                    .cmd("ZREM")
+                    .arg(get_redis_rank_window_base_key(appid, rank_key))
+                    .arg(openid)
+                    .ignore();
+            }
+            if rank_table_config.is_team_board() {
+                add_team_score_cmd(
+                    &mut cmd_pipe,
+                    appid,
+                    rank_key,
+                    "",
+                    openid,
+                    rank_table_config,
+                );
+            }
+        }
+        let removed: Vec<i64> = cmd_pipe.query_async(&mut con).await?;
+        Ok(removed.into_iter().map(|count| count > 0).collect())
+    }
+
+    /// 更新玩家的封禁到mysql
+    async fn update_ban_to_mysql(&self, ban: &RankBan) -> Result<(), sqlx::Error> {
+        let table_name = "rank_ban";
+        let sql = format!(
+            "INSERT INTO {table_name} (appid,openid,ban_type,reason,expire_time,create_time)
+			VALUES(?,?,?,?,?,?)
+			ON DUPLICATE KEY
+			UPDATE ban_type=VALUES(ban_type),reason=VALUES(reason),expire_time=VALUES(expire_time),create_time=VALUES(create_time)"
+        );
+        sqlx::query(&sql)
+            .bind(&ban.appid)
+            .bind(&ban.openid)
+            .bind(ban.ban_type.as_str())
+            .bind(&ban.reason)
+            .bind(ban.expire_time)
+            .bind(ban.create_time)
+            .execute(self.db_conn.get_master_pool())
+            .await?;
+        Ok(())
+    }
+
+    /// 从mysql删除玩家的封禁，返回删除的行数
+    async fn delete_ban_from_mysql(
+        &self,
+        appid: &String,
+        openid: &String,
+    ) -> Result<u64, sqlx::Error> {
+        let table_name = "rank_ban";
+        let sql = format!("DELETE FROM {table_name} WHERE appid=? AND openid=?");
+        let sql_ret = sqlx::query(&sql)
+            .bind(appid)
+            .bind(openid)
+            .execute(self.db_conn.get_master_pool())
+            .await?;
+        Ok(sql_ret.rows_affected())
+    }
+
+    /// 从mysql删除已经过期的封禁，返回删除的行数
+    ///
+    /// 查询过期后重新封禁的玩家不会被删除
+    async fn delete_expired_ban_from_mysql(
+        &self,
+        appid: &String,
+        openid: &String,
+        now: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let table_name = "rank_ban";
+        let sql = format!(
+            "DELETE FROM {table_name} WHERE appid=? AND openid=? AND expire_time>0 AND expire_time<=?"
+        );
+        let sql_ret = sqlx::query(&sql)
+            .bind(appid)
+            .bind(openid)
+            .bind(now)
+            .execute(self.db_conn.get_master_pool())
+            .await?;
+        Ok(sql_ret.rows_affected())
+    }
+
+    /// 获取已经过期的封禁
+    async fn get_expired_bans_from_mysql(
+        &self,
+        now: i64,
+        limit: u64,
+    ) -> Result<Vec<RankBan>, sqlx::Error> {
+        let table_name = "rank_ban";
+        let sql = format!(
+            "SELECT * FROM {table_name} WHERE expire_time>0 AND expire_time<=? ORDER BY expire_time LIMIT ?"
+        );
+        let bans = sqlx::query_as::<_, RankBan>(&sql)
+            .bind(now)
+            .bind(limit)
+            .fetch_all(self.db_conn.get_master_pool())
+            .await?;
+        Ok(bans)
+    }
+
+    /// 分页获取所有的封禁
+    async fn get_pagination_bans_from_mysql(
+        &self,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankBan>, sqlx::Error> {
+        let table_name = "rank_ban";
+        let sql = format!("SELECT * FROM {table_name} ORDER BY appid,openid LIMIT ?,?");
+        let bans = sqlx::query_as::<_, RankBan>(&sql)
+            .bind(start_index)
+            .bind(page_size)
+            .fetch_all(self.db_conn.get_slave_pool())
+            .await?;
+        Ok(bans)
+    }
+
+    /// 分页获取appid下的封禁，最新的在前
+    async fn get_app_bans_from_mysql(
+        &self,
+        appid: &String,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankBan>, sqlx::Error> {
+        let table_name = "rank_ban";
+        let sql = format!(
+            "SELECT * FROM {table_name} WHERE appid=? ORDER BY create_time DESC,openid LIMIT ?,?"
+        );
+        let bans = sqlx::query_as::<_, RankBan>(&sql)
+            .bind(appid)
+            .bind(start_index)
+            .bind(page_size)
+            .fetch_all(self.db_conn.get_slave_pool())
+            .await?;
+        Ok(bans)
+    }
+
+    /// 批量获取玩家的封禁方式
+    async fn get_ban_types_from_redis(
+        &self,
+        appid: &String,
+        openids: &Vec<String>,
+    ) -> Result<Vec<Option<BanType>>, PoolError> {
+        if openids.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let ban_types: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(get_redis_ban_key(appid))
+            .arg(openids)
+            .query_async(&mut con)
+            .await?;
+        Ok(ban_types
+            .into_iter()
+            .map(|ban_type| ban_type.and_then(|ban_type| BanType::try_from(ban_type).ok()))
+            .collect())
+    }
+
+    /// 获取被封禁的玩家在排行榜上的分数
+    async fn get_banned_score_from_redis(
+        &self,
+        appid: &String,
+        openid: &String,
+        rank_key: &String,
+    ) -> Result<Option<f64>, PoolError> {
+        let mut con = self.redis_con_pool.get().await?;
+        let score: Option<f64> = redis::cmd("ZSCORE")
+            .arg(get_redis_banned_rank_key(appid, rank_key))
+            .arg(openid)
+            .query_async(&mut con)
+            .await?;
+        Ok(score)
+    }
+
+    /// 批量写入封禁到redis，不移动排行榜中的分数
+    async fn add_bans_to_redis(&self, bans: &Vec<RankBan>) -> Result<(), PoolError> {
+        if bans.is_empty() {
+            return Ok(());
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let mut cmd_pipe = redis::pipe();
+        for ban in bans {
+            cmd_pipe
+                .cmd("HSET")
+                .arg(get_redis_ban_key(&ban.appid))
+                .arg(&ban.openid)
+                .arg(ban.ban_type.as_str())
+                .ignore();
+        }
+        let _: () = cmd_pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    /// 封禁玩家，把玩家从这些排行榜移到封禁的排行榜
+    ///
+    /// 同时从所在的分区移除并重新计算所在队伍的分数，玩家所在的分区保留，解除封禁时恢复。
+    /// 组合排行榜需要单独重新计算
+    async fn ban_user_to_redis(
+        &self,
+        ban: &RankBan,
+        rank_table_configs: &Vec<RankTableConfig>,
+    ) -> Result<(), PoolError> {
+        let appid = &ban.appid;
+        let mut con = self.redis_con_pool.get().await?;
+        let mut cmd_pipe = redis::pipe();
+        cmd_pipe.atomic();
+        cmd_pipe
+            .cmd("HSET")
+            .arg(get_redis_ban_key(appid))
+            .arg(&ban.openid)
+            .arg(ban.ban_type.as_str())
+            .ignore();
+        for rank_table_config in rank_table_configs {
+            let rank_key = &rank_table_config.rank_key;
+            cmd_pipe
+                .cmd("EVAL")
+                .arg(BAN_USER_SCRIPT)
+                .arg(3)
+                .arg(get_redis_rank_key(appid, rank_key))
+                .arg(get_redis_banned_rank_key(appid, rank_key))
+                .arg(get_redis_partition_user_key(appid, rank_key))
+                .arg(get_redis_partition_rank_key_prefix(appid, rank_key))
+                .arg(&ban.openid)
+                .ignore();
+            if rank_table_config.is_team_board() {
+                add_team_score_cmd(
+                    &mut cmd_pipe,
+                    appid,
+                    rank_key,
+                    "",
+                    &ban.openid,
+                    rank_table_config,
+                );
+            }
+        }
+        let _: () = cmd_pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    /// 解除封禁，把玩家从封禁的排行榜移回这些排行榜
+    ///
+    /// 同时恢复所在的分区并重新计算所在队伍的分数，组合排行榜需要单独重新计算
+    async fn unban_user_to_redis(
+        &self,
+        appid: &String,
+        openid: &String,
+        rank_table_configs: &Vec<RankTableConfig>,
+    ) -> Result<(), PoolError> {
+        let mut con = self.redis_con_pool.get().await?;
+        let mut cmd_pipe = redis::pipe();
+        cmd_pipe.atomic();
+        cmd_pipe
+            .cmd("HDEL")
+            .arg(get_redis_ban_key(appid))
+            .arg(openid)
+            .ignore();
+        for rank_table_config in rank_table_configs {
+            let rank_key = &rank_table_config.rank_key;
+            cmd_pipe
+                .cmd("EVAL")
+                .arg(UNBAN_USER_SCRIPT)
+                .arg(3)
+                .arg(get_redis_rank_key(appid, rank_key))
+                .arg(get_redis_banned_rank_key(appid, rank_key))
+                .arg(get_redis_partition_user_key(appid, rank_key))
+                .arg(openid)
+                .arg(get_redis_partition_rank_key_prefix(appid, rank_key))
+                .ignore();
+            if rank_table_config.is_team_board() {
+                add_team_score_cmd(
+                    &mut cmd_pipe,
+                    appid,
+                    rank_key,
+                    "",
+                    openid,
+                    rank_table_config,
+                );
+            }
+        }
+        let _: () = cmd_pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    /// 把排行榜中所有被封禁的玩家移到封禁的排行榜
+    ///
+    /// 从mysql重建排行榜后调用，滚动排行榜合并分桶时在同一个事务中处理
+    async fn apply_bans_to_redis(
+        &self,
+        rank_table_config: &RankTableConfig,
+    ) -> Result<(), PoolError> {
+        const BATCH_SIZE: usize = 1000;
+        let appid = &rank_table_config.appid;
+        let rank_key = &rank_table_config.rank_key;
+        let mut con = self.redis_con_pool.get().await?;
+        let openids: Vec<String> = redis::cmd("HKEYS")
+            .arg(get_redis_ban_key(appid))
+            .query_async(&mut con)
+            .await?;
+        if openids.is_empty() {
+            return Ok(());
+        }
+
+        let banned_key = get_redis_banned_rank_key(appid, rank_key);
+        let mut cmd_pipe = redis::pipe();
+        cmd_pipe.atomic();
+        for chunk in openids.chunks(BATCH_SIZE) {
+            cmd_pipe
+                .cmd("EVAL")
+                .arg(BAN_USER_SCRIPT)
+                .arg(3)
+                .arg(get_redis_rank_key(appid, rank_key))
+                .arg(&banned_key)
+                .arg(get_redis_partition_user_key(appid, rank_key))
+                .arg(get_redis_partition_rank_key_prefix(appid, rank_key))
+                .arg(chunk)
+                .ignore();
+        }
+        let _: () = cmd_pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    /// 更新影子封禁的玩家的分数，只写入封禁的排行榜
+    ///
+    /// 滚动排行榜同时写入分桶，合并分桶后再移到封禁的排行榜；提交的分区会记录下来，解除封禁时恢复
+    async fn update_shadow_score_to_redis(
+        &self,
+        user_scores: &Vec<(&UserScoreInfo, &RankTableConfig)>,
+    ) -> Result<(), PoolError> {
+        if user_scores.is_empty() {
+            return Ok(());
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let mut cmd_pipe = redis::pipe();
+        for (user_score, rank_table_config) in user_scores {
+            let appid = &user_score.appid;
+            let rank_key = &user_score.rank_key;
+            let update_policy = &rank_table_config.update_policy;
+            add_zset_score_cmd(
+                &mut cmd_pipe,
+                &get_redis_banned_rank_key(appid, rank_key),
+                user_score,
+                update_policy,
+                rank_table_config,
+            );
+            if rank_table_config.is_rolling_window() {
+                add_window_bucket_cmd(&mut cmd_pipe, user_score, update_policy, rank_table_config);
+            } else {
+                // 玩家不在排行榜上，只记录分区
+                cmd_pipe
+                    .cmd("EVAL")
+                    .arg(PARTITION_SCORE_SCRIPT)
+                    .arg(3)
+                    .arg(get_redis_rank_key(appid, rank_key))
+                    .arg(get_redis_partition_user_key(appid, rank_key))
+                    .arg(get_redis_partition_list_key(appid, rank_key))
+                    .arg(&user_score.openid)
+                    .arg(&user_score.partition)
+                    .arg(get_redis_partition_rank_key_prefix(appid, rank_key))
+                    .ignore();
+            }
+            cmd_pipe
+                .cmd("HSET")
+                .arg(get_redis_user_key(appid))
+                .arg(&user_score.openid)
+                .arg(&user_score.nick_name)
+                .ignore();
+        }
+        let _: () = cmd_pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    async fn get_anti_cheat_scores_from_redis(
+        &self,
+        user_scores: &Vec<(&UserScoreInfo, &RankTableConfig, bool)>,
+    ) -> Result<Vec<(Option<f64>, Option<f64>)>, PoolError> {
+        if user_scores.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let mut cmd_pipe = redis::pipe();
+        for (user_score, rank_table_config, shadow) in user_scores {
+            let appid = &user_score.appid;
+            let rank_key = &user_score.rank_key;
+            // 影子封禁的玩家的分数在封禁的排行榜上
+            let rank_redis_key = get_redis_rank_key(appid, rank_key);
+            let key = match shadow {
+                true => get_redis_banned_rank_key(appid, rank_key),
+                false => rank_redis_key.clone(),
+            };
+            cmd_pipe
+                .cmd("ZSCORE")
+                .arg(key)
+                .arg(&user_score.openid)
+                .cmd(get_redis_range_cmd(&rank_table_config.sort_order))
+                .arg(rank_redis_key)
+                .arg(0)
+                .arg(0)
+                .arg("WITHSCORES");
+        }
+        let results: Vec<(Option<f64>, Vec<(String, f64)>)> =
+            cmd_pipe.query_async(&mut con).await?;
+        Ok(results
+            .into_iter()
+            .map(|(score, top)| (score, top.first().map(|(_, top_score)| *top_score)))
+            .collect())
+    }
+
+    async fn charge_anti_cheat_rate_in_redis(
+        &self,
+        user_scores: &Vec<(&UserScoreInfo, &RankTableConfig)>,
+        now: i64,
+    ) -> Result<Vec<String>, PoolError> {
+        if user_scores.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let minute = now.div_euclid(60);
+        let mut cmd_pipe = redis::pipe();
+        for (user_score, rank_table_config) in user_scores {
+            let appid = &user_score.appid;
+            let rank_key = &user_score.rank_key;
+            let openid = &user_score.openid;
+            cmd_pipe
+                .cmd("EVAL")
+                .arg(ANTI_CHEAT_RATE_SCRIPT)
+                .arg(2)
+                .arg(get_redis_cheat_last_key(appid, rank_key, openid))
+                .arg(get_redis_cheat_count_key(appid, rank_key, openid, minute))
+                .arg(rank_table_config.cheat_min_interval)
+                .arg(rank_table_config.cheat_max_per_minute);
+        }
+        let rate_rules: Vec<String> = cmd_pipe.query_async(&mut con).await?;
+        Ok(rate_rules)
+    }
+
+    async fn refund_anti_cheat_rate_in_redis(
+        &self,
+        user_scores: &Vec<(&UserScoreInfo, &RankTableConfig)>,
+        now: i64,
+    ) -> Result<(), PoolError> {
+        if user_scores.is_empty() {
+            return Ok(());
+        }
+        let mut con = self.redis_con_pool.get().await?;
+        let minute = now.div_euclid(60);
+        let mut cmd_pipe = redis::pipe();
+        for (user_score, rank_table_config) in user_scores {
+            let appid = &user_score.appid;
+            let rank_key = &user_score.rank_key;
+            let openid = &user_score.openid;
+            cmd_pipe
+                .cmd("EVAL")
+                .arg(ANTI_CHEAT_REFUND_SCRIPT)
+                .arg(2)
+                .arg(get_redis_cheat_last_key(appid, rank_key, openid))
+                .arg(get_redis_cheat_count_key(appid, rank_key, openid, minute))
+                .arg(rank_table_config.cheat_min_interval)
+                .arg(rank_table_config.cheat_max_per_minute)
+                .ignore();
+        }
+        let _: () = cmd_pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    async fn add_cheat_violations_to_mysql(
+        &self,
+        violations: &Vec<RankCheatViolation>,
+    ) -> Result<(), sqlx::Error> {
+        if violations.is_empty() {
+            return Ok(());
+        }
+        let mut query_builder: QueryBuilder<MySql> = QueryBuilder::new(
+            "INSERT INTO rank_cheat_violation (appid,rank_key,openid,rule,score,current_score,source_ip,request_id,create_time) ",
+        );
+        query_builder.push_values(violations, |mut b, violation| {
+            b.push_bind(&violation.appid)
+                .push_bind(&violation.rank_key)
+                .push_bind(&violation.openid)
+                .push_bind(violation.rule.as_str())
+                .push_bind(violation.score)
+                .push_bind(violation.current_score)
+                .push_bind(&violation.source_ip)
+                .push_bind(&violation.request_id)
+                .push_bind(violation.create_time);
+        });
+        query_builder
+            .build()
+            .execute(self.db_conn.get_master_pool())
+            .await?;
+        Ok(())
+    }
+
+    /// 分页获取违反防作弊规则的提交，最新的在前
+    async fn get_cheat_violations_from_mysql(
+        &self,
+        appid: &String,
+        openid: &Option<String>,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankCheatViolation>, sqlx::Error> {
+        let violations = match openid {
+            Some(openid) => {
+                sqlx::query_as::<_, RankCheatViolation>(
+                    "SELECT * FROM rank_cheat_violation WHERE appid = ? AND openid = ? ORDER BY id DESC LIMIT ?,?",
+                )
+                .bind(appid)
+                .bind(openid)
+                .bind(start_index)
+                .bind(page_size)
+                .fetch_all(self.db_conn.get_slave_pool())
+                .await?
+            }
+            None => {
+                sqlx::query_as::<_, RankCheatViolation>(
+                    "SELECT * FROM rank_cheat_violation WHERE appid = ? ORDER BY id DESC LIMIT ?,?",
+                )
+                .bind(appid)
+                .bind(start_index)
+                .bind(page_size)
+                .fetch_all(self.db_conn.get_slave_pool())
+                .await?
+            }
+        };
+        Ok(violations)
+    }
+
+    async fn add_score_reviews_to_mysql(
+        &self,
+        reviews: &Vec<RankScoreReview>,
+    ) -> Result<(), sqlx::Error> {
+        if reviews.is_empty() {
+            return Ok(());
+        }
+        let mut query_builder: QueryBuilder<MySql> = QueryBuilder::new(
+            "INSERT INTO rank_score_review (appid,rank_key,openid,nick_name,rank_partition,score,current_score,top_score,reason,status,season,source_ip,request_id,create_time) ",
+        );
+        query_builder.push_values(reviews, |mut b, review| {
+            b.push_bind(&review.appid)
+                .push_bind(&review.rank_key)
+                .push_bind(&review.openid)
+                .push_bind(&review.nick_name)
+                .push_bind(&review.partition)
+                .push_bind(review.score)
+                .push_bind(review.current_score)
+                .push_bind(review.top_score)
+                .push_bind(&review.reason)
+                .push_bind(review.status.as_str())
+                .push_bind(review.season)
+                .push_bind(&review.source_ip)
+                .push_bind(&review.request_id)
+                .push_bind(review.create_time);
+        });
+        query_builder
+            .build()
+            .execute(self.db_conn.get_master_pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn get_score_review_from_mysql(
+        &self,
+        appid: &String,
+        id: i64,
+    ) -> Result<Option<RankScoreReview>, sqlx::Error> {
+        let review = sqlx::query_as::<_, RankScoreReview>(
+            "SELECT * FROM rank_score_review WHERE appid = ? AND id = ?",
+        )
+        .bind(appid)
+        .bind(id)
+        .fetch_optional(self.db_conn.get_master_pool())
+        .await?;
+        Ok(review)
+    }
+
+    /// 分页获取可疑分数审核，先提交的在前
+    async fn get_score_reviews_from_mysql(
+        &self,
+        appid: &String,
+        status: &ReviewStatus,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankScoreReview>, sqlx::Error> {
+        let reviews = sqlx::query_as::<_, RankScoreReview>(
+            "SELECT * FROM rank_score_review WHERE appid = ? AND status = ? ORDER BY id LIMIT ?,?",
+        )
+        .bind(appid)
+        .bind(status.as_str())
+        .bind(start_index)
+        .bind(page_size)
+        .fetch_all(self.db_conn.get_slave_pool())
+        .await?;
+        Ok(reviews)
+    }
+
+    async fn update_score_review_status_to_mysql(
+        &self,
+        appid: &String,
+        id: i64,
+        from: &ReviewStatus,
+        to: &ReviewStatus,
+        review_time: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE rank_score_review SET status = ?, review_time = ? WHERE appid = ? AND id = ? AND status = ?",
+        )
+        .bind(to.as_str())
+        .bind(review_time)
+        .bind(appid)
+        .bind(id)
+        .bind(from.as_str())
+        .execute(self.db_conn.get_master_pool())
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn reject_user_score_reviews_to_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        openid: &String,
+        review_time: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE rank_score_review SET status = ?, review_time = ? WHERE appid = ? AND openid = ? AND rank_key = ? AND status = ?",
+        )
+        .bind(ReviewStatus::Rejected.as_str())
+        .bind(review_time)
+        .bind(appid)
+        .bind(openid)
+        .bind(rank_key)
+        .bind(ReviewStatus::Pending.as_str())
+        .execute(self.db_conn.get_master_pool())
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn approve_score_review_to_mysql(
+        &self,
+        review: &RankScoreReview,
+        user_score: &UserScoreInfo,
+        rank_table_config: &RankTableConfig,
+        source: &ScoreRequestSource,
+    ) -> Result<bool, sqlx::Error> {
+        let table_name = get_mysql_table_name(&user_score.appid, &user_score.rank_key);
+        let mut attempt = 1;
+        loop {
+            let mut tx = self.db_conn.get_master_pool().begin().await?;
+            // 按状态条件修改，并发审核同一条记录时只有一个事务能修改成功，累加策略的分数只写入一次
+            let result = sqlx::query(
+                "UPDATE rank_score_review SET status = ?, review_time = ? WHERE appid = ? AND id = ? AND status = ?",
+            )
+            .bind(ReviewStatus::Approved.as_str())
+            .bind(user_score.update_time)
+            .bind(&review.appid)
+            .bind(review.id)
+            .bind(ReviewStatus::Pending.as_str())
+            .execute(&mut *tx)
+            .await?;
+            if result.rows_affected() == 0 {
+                tx.rollback().await?;
+                return Ok(false);
+            }
+            match update_rank_scores_in_tx(
+                &mut tx,
+                &table_name,
+                std::slice::from_ref(user_score),
+                rank_table_config,
+                source,
+            )
+            .await
+            {
+                Ok(_) => {
+                    tx.commit().await?;
+                    return Ok(true);
+                }
+                Err(err)
+                    if attempt < MYSQL_SCORE_WRITE_ATTEMPTS && is_retryable_score_write(&err) =>
+                {
+                    tracing::warn!(
+                        "approve score review to mysql retry, attempt:{} | error:{}",
+                        attempt,
+                        err
+                    );
+                    tx.rollback().await?;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
     }
 
     /// 获取排行榜表配置
@@ -354,11 +2244,15 @@ impl RankRepositoryTrait for RankRepository {
     ) -> Result<Vec<UserScoreInfo>, sqlx::Error> {
         let table_name = get_mysql_table_name(appid, rank_key);
         let sql = format!("SELECT * FROM {table_name} LIMIT ?,?");
-        let user = sqlx::query_as::<_, UserScoreInfo>(&sql)
+        let mut user = sqlx::query_as::<_, UserScoreInfo>(&sql)
             .bind(start_index)
             .bind(page_size)
             .fetch_all(self.db_conn.get_slave_pool())
             .await?;
+        for user_info in &mut user {
+            user_info.appid = appid.clone();
+            user_info.rank_key = rank_key.clone();
+        }
         Ok(user)
     }
 
@@ -376,26 +2270,473 @@ impl RankRepositoryTrait for RankRepository {
         Ok(())
     }
 
-    /// 清理redis排行榜数据
-    ///
+    /// 清理redis排行榜数据，包括所有的分区
     async fn clear_all_users_score_info_from_redis(
         &self,
         appid: &String,
         rank_key: &String,
     ) -> Result<(), PoolError> {
         let mut con = self.redis_con_pool.get().await?;
+        let partition_list_key = get_redis_partition_list_key(appid, rank_key);
+        let partitions: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(&partition_list_key)
+            .query_async(&mut con)
+            .await?;
 
         let mut cmd_pipe = redis::pipe();
         let key = get_redis_rank_key(appid, rank_key);
         cmd_pipe.cmd("DEL").arg(key);
+        // 分区排行榜和玩家所在的分区一起清理，与mysql保持一致
+        for partition in partitions {
+            cmd_pipe.cmd("DEL").arg(get_redis_partition_rank_key(
+                appid,
+                rank_key,
+                &Some(partition),
+            ));
+        }
+        cmd_pipe
+            .cmd("DEL")
+            .arg(get_redis_partition_user_key(appid, rank_key))
+            .arg(&partition_list_key);
+        // 队伍排行榜由成员分数计算，成员分数清理后一起清理，队伍成员保留
+        cmd_pipe
+            .cmd("DEL")
+            .arg(get_redis_team_rank_key(appid, rank_key));
+        // 被封禁的玩家的分数一起清理，封禁保留
+        cmd_pipe
+            .cmd("DEL")
+            .arg(get_redis_banned_rank_key(appid, rank_key))
+            .arg(get_redis_trimmed_user_key(appid, rank_key));
         let _ = cmd_pipe.query_async(&mut con).await?;
         Ok(())
     }
 
+    /// 重建排行榜时写入临时key
+    async fn add_rank_score_to_redis_rebuild_key(
+        &self,
+        user_scores: &Vec<UserScoreInfo>,
+        rank_table_config: &RankTableConfig,
+    ) -> Result<(), PoolError> {
+        if user_scores.is_empty() {
+            return Ok(());
+        }
+        let mut con = self.redis_con_pool.get().await?;
+
+        let key = get_redis_rank_rebuild_key(&rank_table_config.appid, &rank_table_config.rank_key);
+        let mut cmd_pipe = redis::pipe();
+        for user_score in user_scores {
+            cmd_pipe
+                .cmd("ZADD")
+                .arg(&key)
+                .arg(rank_table_config.encode_redis_score(user_score.score, user_score.update_time))
+                .arg(&user_score.openid)
+                .ignore()
+                .cmd("HSET")
+                .arg(get_redis_user_key(&user_score.appid))
+                .arg(&user_score.openid)
+                .arg(&user_score.nick_name)
+                .ignore();
+            // 分区排行榜直接按新的编码覆盖
+            if !user_score.partition.is_empty() {
+                let appid = &rank_table_config.appid;
+                let rank_key = &rank_table_config.rank_key;
+                cmd_pipe
+                    .cmd("ZADD")
+                    .arg(get_redis_partition_rank_key(
+                        appid,
+                        rank_key,
+                        &Some(user_score.partition.clone()),
+                    ))
+                    .arg(
+                        rank_table_config
+                            .encode_redis_score(user_score.score, user_score.update_time),
+                    )
+                    .arg(&user_score.openid)
+                    .ignore()
+                    .cmd("HSET")
+                    .arg(get_redis_partition_user_key(appid, rank_key))
+                    .arg(&user_score.openid)
+                    .arg(&user_score.partition)
+                    .ignore()
+                    .cmd("SADD")
+                    .arg(get_redis_partition_list_key(appid, rank_key))
+                    .arg(&user_score.partition)
+                    .ignore();
+            }
+        }
+        let _: () = cmd_pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    /// 归档mysql排行榜数据并结算奖励
+    ///
+    /// 排名按排行榜配置的排序方式、同分排序规则和排名方式在mysql中计算，
+    /// 归档和结算在同一个事务中，失败时都不会写入
+    async fn archive_rank_to_mysql(
+        &self,
+        rank_table_config: &RankTableConfig,
+        end_time: i64,
+    ) -> Result<i64, sqlx::Error> {
+        let appid = &rank_table_config.appid;
+        let rank_key = &rank_table_config.rank_key;
+        let table_name = get_mysql_table_name(appid, rank_key);
+        let mut tx = self.db_conn.get_master_pool().begin().await?;
+
+        // 开始时间为上一期的结束时间，第一期为最早上榜时间
+        let start_time: i64 = sqlx::query_scalar(&format!(
+            "SELECT COALESCE(
+				(SELECT MAX(end_time) FROM rank_archive_period WHERE appid = ? AND rank_key = ?),
+				(SELECT MIN(update_time) FROM {table_name}),
+				0)"
+        ))
+        .bind(appid)
+        .bind(rank_key)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let period_id = sqlx::query(
+            "INSERT INTO rank_archive_period (appid,rank_key,season,start_time,end_time) VALUES(?,?,?,?,?)",
+        )
+        .bind(appid)
+        .bind(rank_key)
+        .bind(rank_table_config.season)
+        .bind(start_time)
+        .bind(end_time)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_id() as i64;
+
+        let (position_order, ranking_order) = get_mysql_rank_order_by(rank_table_config);
+        let sql = format!(
+            "INSERT INTO rank_archive_entry (period_id,position,ranking,openid,nick_name,score)
+			SELECT {period_id},
+				ROW_NUMBER() OVER (ORDER BY {position_order}),
+				{}() OVER (ORDER BY {ranking_order}),
+				openid,nick_name,score
+			FROM {table_name}
+			WHERE openid NOT IN (SELECT openid FROM rank_ban WHERE appid = ?)",
+            get_mysql_rank_window_func(&rank_table_config.rank_style)
+        );
+        // 被封禁的玩家不参与归档和奖励结算
+        let total = sqlx::query(&sql)
+            .bind(appid)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        sqlx::query("UPDATE rank_archive_period SET total = ? WHERE period_id = ?")
+            .bind(total)
+            .bind(period_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // 按顺序结算奖励，同一个玩家已经有奖励时忽略后面的档位
+        for bracket in &rank_table_config.reward_brackets.0 {
+            let (min_rank, max_rank) = match bracket.top_percent {
+                Some(top_percent) => (1, (total as f64 * top_percent / 100.0).ceil() as i64),
+                None => (bracket.min_rank as i64, bracket.max_rank as i64),
+            };
+            sqlx::query(
+                "INSERT INTO rank_reward_settlement
+				(appid,rank_key,season,period_id,openid,nick_name,ranking,score,reward,create_time)
+				SELECT ?,?,?,period_id,openid,nick_name,ranking,score,?,?
+				FROM rank_archive_entry WHERE period_id = ? AND ranking BETWEEN ? AND ?
+				ON DUPLICATE KEY UPDATE id=id",
+            )
+            .bind(appid)
+            .bind(rank_key)
+            .bind(rank_table_config.season)
+            .bind(&bracket.reward)
+            .bind(end_time)
+            .bind(period_id)
+            .bind(min_rank)
+            .bind(max_rank)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(period_id)
+    }
+
+    /// 获取未确认的奖励结算
+    ///
+    /// 从主库读取，避免确认后从库延迟导致重复拉取
+    async fn get_pending_reward_settlements_from_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        limit: u64,
+    ) -> Result<Vec<RankRewardSettlement>, sqlx::Error> {
+        let settlements = sqlx::query_as::<_, RankRewardSettlement>(
+            "SELECT id,appid,rank_key,season,period_id,openid,nick_name,ranking,score,reward,create_time
+			FROM rank_reward_settlement WHERE appid = ? AND rank_key = ? AND status = 0 ORDER BY id LIMIT ?",
+        )
+        .bind(appid)
+        .bind(rank_key)
+        .bind(limit)
+        .fetch_all(self.db_conn.get_master_pool())
+        .await?;
+        Ok(settlements)
+    }
+
+    /// 确认奖励结算
+    async fn ack_reward_settlements_to_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        ids: &Vec<i64>,
+    ) -> Result<u64, sqlx::Error> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let mut query_builder: QueryBuilder<MySql> =
+            QueryBuilder::new("UPDATE rank_reward_settlement SET status = 1, ack_time = ");
+        query_builder
+            .push_bind(Utc::now().timestamp())
+            .push(" WHERE appid = ")
+            .push_bind(appid)
+            .push(" AND rank_key = ")
+            .push_bind(rank_key)
+            .push(" AND status = 0 AND id IN (");
+        let mut separated = query_builder.separated(",");
+        for id in ids {
+            separated.push_bind(id);
+        }
+        separated.push_unseparated(")");
+        let sql_ret = query_builder
+            .build()
+            .execute(self.db_conn.get_master_pool())
+            .await?;
+        Ok(sql_ret.rows_affected())
+    }
+
+    /// 分页获取排行榜归档的期
+    async fn get_archive_periods_from_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankArchivePeriod>, sqlx::Error> {
+        let periods = sqlx::query_as::<_, RankArchivePeriod>(
+            "SELECT * FROM rank_archive_period WHERE appid = ? AND rank_key = ? ORDER BY period_id DESC LIMIT ?,?",
+        )
+        .bind(appid)
+        .bind(rank_key)
+        .bind(start_index)
+        .bind(page_size)
+        .fetch_all(self.db_conn.get_slave_pool())
+        .await?;
+        Ok(periods)
+    }
+
+    /// 获取排行榜归档的一期
+    async fn get_archive_period_from_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        period_id: i64,
+    ) -> Result<RankArchivePeriod, sqlx::Error> {
+        sqlx::query_as::<_, RankArchivePeriod>(
+            "SELECT * FROM rank_archive_period WHERE period_id = ? AND appid = ? AND rank_key = ?",
+        )
+        .bind(period_id)
+        .bind(appid)
+        .bind(rank_key)
+        .fetch_one(self.db_conn.get_slave_pool())
+        .await
+    }
+
+    /// 分页获取排行榜归档的玩家排名
+    async fn get_archive_entries_from_mysql(
+        &self,
+        period_id: i64,
+        start_index: u64,
+        page_size: u64,
+    ) -> Result<Vec<RankArchiveEntry>, sqlx::Error> {
+        let entries = sqlx::query_as::<_, RankArchiveEntry>(
+            "SELECT position,ranking,openid,nick_name,score FROM rank_archive_entry WHERE period_id = ? ORDER BY position LIMIT ?,?",
+        )
+        .bind(period_id)
+        .bind(start_index)
+        .bind(page_size)
+        .fetch_all(self.db_conn.get_slave_pool())
+        .await?;
+        Ok(entries)
+    }
+
+    /// 清理重建排行榜的临时key
+    async fn clear_redis_rebuild_key(
+        &self,
+        appid: &String,
+        rank_key: &String,
+    ) -> Result<(), PoolError> {
+        let mut con = self.redis_con_pool.get().await?;
+        let _: () = redis::cmd("DEL")
+            .arg(get_redis_rank_rebuild_key(appid, rank_key))
+            .query_async(&mut con)
+            .await?;
+        Ok(())
+    }
+
+    /// 用重建好的临时key替换排行榜
+    ///
+    /// RENAME 会原子的覆盖旧的排行榜，临时key不存在说明排行榜为空
+    async fn replace_rank_with_redis_rebuild_key(
+        &self,
+        appid: &String,
+        rank_key: &String,
+    ) -> Result<(), PoolError> {
+        let mut con = self.redis_con_pool.get().await?;
+        let rebuild_key = get_redis_rank_rebuild_key(appid, rank_key);
+        let exists: bool = redis::cmd("EXISTS")
+            .arg(&rebuild_key)
+            .query_async(&mut con)
+            .await?;
+        if exists {
+            let _: () = redis::cmd("RENAME")
+                .arg(&rebuild_key)
+                .arg(get_redis_rank_key(appid, rank_key))
+                .query_async(&mut con)
+                .await?;
+        } else {
+            let _: () = redis::cmd("DEL")
+                .arg(get_redis_rank_key(appid, rank_key))
+                .query_async(&mut con)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// 合并滚动排行榜时间窗口内的分桶，并删除过期的分桶
+    ///
+    /// 最高分、最低分策略直接按 MAX、MIN 合并分桶；
+    /// 累加策略的分桶只保存累加值，合并后再加上基础分(首次上榜的0分和同分排序值)，
+    /// 不在任何分桶中的玩家同时从基础分中移除。合并后把被封禁的玩家移到封禁的排行榜，所有命令在一个事务中执行
+    async fn merge_rank_window_to_redis(
+        &self,
+        rank_table_config: &RankTableConfig,
+        now: i64,
+    ) -> Result<(), PoolError> {
+        let appid = &rank_table_config.appid;
+        let rank_key = &rank_table_config.rank_key;
+        let index = rank_table_config.window_bucket_index(now);
+        let count = rank_table_config.window_bucket_count();
+        let bucket_keys: Vec<String> = (index - count + 1..=index)
+            .map(|bucket_index| get_redis_rank_bucket_key(appid, rank_key, bucket_index))
+            .collect();
+        let expired_keys: Vec<String> = (index - 2 * count + 1..=index - count)
+            .map(|bucket_index| get_redis_rank_bucket_key(appid, rank_key, bucket_index))
+            .collect();
+        let key = get_redis_rank_key(appid, rank_key);
+
+        let mut cmd_pipe = redis::pipe();
+        cmd_pipe.atomic();
+        match rank_table_config.update_policy {
+            UpdatePolicy::Max | UpdatePolicy::Min => {
+                let aggregate = match rank_table_config.update_policy {
+                    UpdatePolicy::Max => "MAX",
+                    _ => "MIN",
+                };
+                cmd_pipe
+                    .cmd("ZUNIONSTORE")
+                    .arg(&key)
+                    .arg(bucket_keys.len())
+                    .arg(&bucket_keys)
+                    .arg("AGGREGATE")
+                    .arg(aggregate)
+                    .ignore();
+            }
+            UpdatePolicy::Increment => {
+                let base_key = get_redis_rank_window_base_key(appid, rank_key);
+                let merge_key = get_redis_rank_window_merge_key(appid, rank_key);
+                cmd_pipe
+                    .cmd("ZUNIONSTORE")
+                    .arg(&merge_key)
+                    .arg(bucket_keys.len())
+                    .arg(&bucket_keys)
+                    .arg("AGGREGATE")
+                    .arg("SUM")
+                    .ignore()
+                    .cmd("ZINTERSTORE")
+                    .arg(&key)
+                    .arg(2)
+                    .arg(&base_key)
+                    .arg(&merge_key)
+                    .arg("AGGREGATE")
+                    .arg("SUM")
+                    .ignore()
+                    .cmd("ZINTERSTORE")
+                    .arg(&base_key)
+                    .arg(2)
+                    .arg(&base_key)
+                    .arg(&key)
+                    .arg("WEIGHTS")
+                    .arg(1)
+                    .arg(0)
+                    .ignore()
+                    .cmd("DEL")
+                    .arg(&merge_key)
+                    .ignore();
+            }
+            // 滚动排行榜不支持覆盖策略
+            UpdatePolicy::Replace => return Ok(()),
+        }
+        // 累加策略合并分桶后的分数可能超出分数范围
+        if rank_table_config.update_policy == UpdatePolicy::Increment {
+            cmd_pipe
+                .cmd("EVAL")
+                .arg(WINDOW_CLAMP_SCRIPT)
+                .arg(1)
+                .arg(&key)
+                .arg(1i64 << rank_table_config.tie_bits())
+                .arg(rank_table_config.max_score - rank_table_config.min_score)
+                .ignore();
+        }
+        cmd_pipe.cmd("DEL").arg(&expired_keys).ignore();
+        // 分桶中有被封禁的玩家的分数，在同一个事务中重新移出排行榜
+        cmd_pipe
+            .cmd("EVAL")
+            .arg(WINDOW_BAN_SCRIPT)
+            .arg(3)
+            .arg(&key)
+            .arg(get_redis_banned_rank_key(appid, rank_key))
+            .arg(get_redis_ban_key(appid))
+            .ignore();
+
+        let mut con = self.redis_con_pool.get().await?;
+        let _: () = cmd_pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    /// 清理滚动排行榜的分桶
+    ///
+    /// 更早的分桶已经过期，不需要删除
+    async fn clear_rank_window_from_redis(
+        &self,
+        rank_table_config: &RankTableConfig,
+        now: i64,
+    ) -> Result<(), PoolError> {
+        let appid = &rank_table_config.appid;
+        let rank_key = &rank_table_config.rank_key;
+        let index = rank_table_config.window_bucket_index(now);
+        let count = rank_table_config.window_bucket_count();
+        let mut keys: Vec<String> = (index - count..=index + 1)
+            .map(|bucket_index| get_redis_rank_bucket_key(appid, rank_key, bucket_index))
+            .collect();
+        keys.push(get_redis_rank_window_base_key(appid, rank_key));
+        keys.push(get_redis_rank_window_merge_key(appid, rank_key));
+
+        let mut con = self.redis_con_pool.get().await?;
+        let _: () = redis::cmd("DEL").arg(&keys).query_async(&mut con).await?;
+        Ok(())
+    }
+
     // 添加排行榜配置到mysql
     async fn add_rank_config_to_mysql(
         &self,
         payload: &AddRankConfigReq,
+        rank_table_config: &RankTableConfig,
     ) -> Result<(), sqlx::Error> {
         // 开启事务
         sqlx::raw_sql("START TRANSACTION;")
@@ -404,16 +2745,71 @@ impl RankRepositoryTrait for RankRepository {
 
         let table_name = "rank_table_config";
         let sql = format!(
-            "INSERT INTO {} 
-			VALUES('{}','{}','{}','{}','{}')",
+            "INSERT INTO {} (
+			appid,
+			app_secret,
+			rank_key,
+			cron_expression,
+			remark,
+			update_policy,
+			sort_order,
+			max_page_size,
+			score_type,
+			score_precision,
+			min_score,
+			max_score,
+			tie_policy,
+			rank_style,
+			reward_brackets,
+			window_seconds,
+			bucket_seconds,
+			board_group,
+			team_aggregate,
+			team_top_k,
+			composite_sources,
+			max_entries,
+			prune_mysql,
+			cheat_max_score,
+			cheat_max_increase,
+			cheat_max_per_minute,
+			cheat_min_interval,
+			cheat_action,
+			review_top_ratio
+		)
+		VALUES('{}','{}','{}','{}','{}','{}','{}',{},'{}',{},{},{},'{}','{}',?,{},{},?,'{}',{},?,{},{},?,?,{},{},'{}',?)",
             table_name,
-            payload.appid,
-            payload.app_secret,
-            payload.rank_key,
-            payload.cron_expression,
-            payload.remark
+            rank_table_config.appid,
+            rank_table_config.app_secret,
+            rank_table_config.rank_key,
+            rank_table_config.cron_expression,
+            payload.remark,
+            rank_table_config.update_policy.as_str(),
+            rank_table_config.sort_order.as_str(),
+            rank_table_config.max_page_size,
+            rank_table_config.score_type.as_str(),
+            rank_table_config.score_precision,
+            rank_table_config.min_score,
+            rank_table_config.max_score,
+            rank_table_config.tie_policy.as_str(),
+            rank_table_config.rank_style.as_str(),
+            rank_table_config.window_seconds,
+            rank_table_config.bucket_seconds,
+            rank_table_config.team_aggregate.as_str(),
+            rank_table_config.team_top_k,
+            rank_table_config.max_entries,
+            rank_table_config.prune_mysql,
+            rank_table_config.cheat_max_per_minute,
+            rank_table_config.cheat_min_interval,
+            rank_table_config.cheat_action.as_str()
         );
+        // 奖励档位、组合排行榜的来源由玩家配置，绑定参数写入
         match sqlx::query(&sql)
+            .bind(rank_table_config.reward_brackets.to_json())
+            .bind(&rank_table_config.board_group)
+            .bind(rank_table_config.composite_sources.to_json())
+            .bind(rank_table_config.cheat_max_score)
+            .bind(rank_table_config.cheat_max_increase)
+            .bind(rank_table_config.review_top_ratio)
             .execute(self.db_conn.get_master_pool())
             .await
         {
@@ -480,6 +2876,22 @@ impl RankRepositoryTrait for RankRepository {
         }
     }
 
+    /// 排行榜赛季加1
+    async fn increase_rank_season_to_mysql(
+        &self,
+        appid: &String,
+        rank_key: &String,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE rank_table_config SET season = season + 1 WHERE appid = ? AND rank_key = ?",
+        )
+        .bind(appid)
+        .bind(rank_key)
+        .execute(self.db_conn.get_master_pool())
+        .await?;
+        Ok(())
+    }
+
     // async fn set_master_rank_service_flag_to_redis(
     //     &self,
     // ) -> Result<(), PoolError>{
@@ -500,14 +2912,871 @@ impl RankRepositoryTrait for RankRepository {
     // }
 }
 
-// 计算分数
-fn calc_score(origin_score: i32) -> f64 {
-    let cur_time_seconds = Utc::now().timestamp();
-    let score = origin_score as f64
-        + (CALC_SCORE_BASE_TIME_STAMP - cur_time_seconds as f64) / CALC_SCORE_BASE_TIME_STAMP;
+/// 统计比每个指定分数更好的不同分数个数
+///
+/// 指定分数从好到差排序后，从最好的分数开始逐个跳到下一个更差的不同分数，
+/// 所有指定分数共用一次遍历，复杂度与比最差的指定分数更好的不同分数个数相关。
+/// ARGV[1] 排序方式，ARGV[2..] 分数；按分数的顺序返回个数
+const DENSE_RANK_SCRIPT: &str = r#"
+local key = KEYS[1]
+local desc = ARGV[1] == 'desc'
+local items = {}
+for i = 2, #ARGV do
+    items[#items + 1] = {tonumber(ARGV[i]), ARGV[i], i - 1}
+end
+table.sort(items, function(a, b)
+    if desc then
+        return a[1] > b[1]
+    end
+    return a[1] < b[1]
+end)
+local res = {}
+local count = 0
+local cur = desc and '+inf' or '-inf'
+for _, item in ipairs(items) do
+    while true do
+        local next
+        if desc then
+            next = redis.call('ZREVRANGEBYSCORE', key, cur, '(' .. item[2], 'WITHSCORES', 'LIMIT', 0, 1)
+        else
+            next = redis.call('ZRANGEBYSCORE', key, cur, '(' .. item[2], 'WITHSCORES', 'LIMIT', 0, 1)
+        end
+        if #next == 0 then
+            break
+        end
+        count = count + 1
+        cur = '(' .. next[2]
+    end
+    res[item[3]] = count
+end
+return res
+"#;
+
+/// 按更新策略保留更好的分数，比较还原后的分数而不是编码后的值
+///
+/// 编码后的值包含同分排序值，直接用 ZADD GT/LT 比较时相同的分数也可能覆盖，
+/// 改变同分排序时间，与mysql中保留原来时间的规则不一致。
+/// KEYS[1] 排行榜；ARGV[1] 编码后的分数，ARGV[2] 2^tie_bits，ARGV[3] gt 或 lt，ARGV[4] openid。
+/// 返回是否写入
+const BEST_SCORE_SCRIPT: &str = r#"
+local old = redis.call('ZSCORE', KEYS[1], ARGV[4])
+if old then
+    local scale = tonumber(ARGV[2])
+    local old_score = math.floor(tonumber(old) / scale)
+    local new_score = math.floor(tonumber(ARGV[1]) / scale)
+    if ARGV[3] == 'gt' and new_score <= old_score then
+        return 0
+    end
+    if ARGV[3] == 'lt' and new_score >= old_score then
+        return 0
+    end
+end
+redis.call('ZADD', KEYS[1], ARGV[1], ARGV[4])
+return 1
+"#;
+
+/// 累加分数，累加后的分数限制在排行榜的分数范围内，同分排序值不变
+///
+/// 超出范围时编码后的值会超过 2^53 丢失精度，或者小于 min_score 破坏同分排序值。
+/// 首次上榜时从0分开始累加，与mysql一致。
+/// KEYS[1] 排行榜；ARGV[1] openid，ARGV[2] 首次上榜的同分排序值，ARGV[3] 累加的分数，
+/// ARGV[4] 2^tie_bits，ARGV[5] 0 - min_score，ARGV[6] max_score - min_score
+const INCREMENT_SCORE_SCRIPT: &str = r#"
+local scale = tonumber(ARGV[4])
+local old = redis.call('ZSCORE', KEYS[1], ARGV[1])
+local tie, score
+if old then
+    old = tonumber(old)
+    tie = old % scale
+    score = (old - tie) / scale
+else
+    tie = tonumber(ARGV[2])
+    score = tonumber(ARGV[5])
+end
+score = math.max(0, math.min(score + tonumber(ARGV[3]), tonumber(ARGV[6])))
+redis.call('ZADD', KEYS[1], string.format('%.17g', score * scale + tie), ARGV[1])
+return 1
+"#;
+
+/// 把滚动排行榜合并后超出分数范围的累加分数限制在范围内，同分排序值不变
+///
+/// KEYS[1] 排行榜；ARGV[1] 2^tie_bits，ARGV[2] max_score - min_score
+const WINDOW_CLAMP_SCRIPT: &str = r#"
+local scale = tonumber(ARGV[1])
+local max = tonumber(ARGV[2])
+local function clamp(members, score)
+    for i = 1, #members, 2 do
+        local tie = tonumber(members[i + 1]) % scale
+        redis.call('ZADD', KEYS[1], string.format('%.17g', score * scale + tie), members[i])
+    end
+end
+clamp(redis.call('ZRANGEBYSCORE', KEYS[1], string.format('%.17g', (max + 1) * scale), '+inf', 'WITHSCORES'), max)
+clamp(redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', '(0', 'WITHSCORES'), 0)
+return 1
+"#;
+
+/// 把玩家在排行榜中的分数同步到所在的分区排行榜
+///
+/// KEYS[1] 排行榜，KEYS[2] 玩家所在的分区，KEYS[3] 分区列表；
+/// ARGV[1] openid，ARGV[2] 本次提交的分区(空表示沿用之前的分区)，ARGV[3] 分区排行榜key的前缀。
+/// 玩家换分区时从原来的分区中移除
+const PARTITION_SCORE_SCRIPT: &str = r#"
+local partition = ARGV[2]
+local old = redis.call('HGET', KEYS[2], ARGV[1])
+if partition == '' then
+    if not old then
+        return 0
+    end
+    partition = old
+elseif old and old ~= partition then
+    redis.call('ZREM', ARGV[3] .. old, ARGV[1])
+end
+if old ~= partition then
+    redis.call('HSET', KEYS[2], ARGV[1], partition)
+    redis.call('SADD', KEYS[3], partition)
+end
+local score = redis.call('ZSCORE', KEYS[1], ARGV[1])
+if score then
+    redis.call('ZADD', ARGV[3] .. partition, score, ARGV[1])
+end
+return 0
+"#;
+
+/// 重新计算队伍在排行榜上的分数的lua函数，队伍分数脚本和队伍成员脚本共用
+///
+/// 队伍分数是成员存储分数之和，与分数编码无关，队伍没有成员上榜时从队伍排行榜移除。
+/// 队伍成员数量不超过 MAX_TEAM_SIZE，遍历成员的开销有上限
+macro_rules! team_score_lua_fn {
+    () => {
+        r#"
+local function update_team_score(rank, team_rank, member_prefix, team, scale, min_score, top_k, sort_order)
+    local scores = {}
+    for _, member in ipairs(redis.call('SMEMBERS', member_prefix .. team)) do
+        local score = redis.call('ZSCORE', rank, member)
+        if score then
+            table.insert(scores, math.floor(tonumber(score) / scale) + min_score)
+        end
+    end
+    if #scores == 0 then
+        redis.call('ZREM', team_rank, team)
+        return
+    end
+    if top_k > 0 and #scores > top_k then
+        if sort_order == 'asc' then
+            table.sort(scores)
+        else
+            table.sort(scores, function(a, b) return a > b end)
+        end
+    end
+    local total = 0
+    for i, score in ipairs(scores) do
+        if top_k > 0 and i > top_k then
+            break
+        end
+        total = total + score
+    end
+    redis.call('ZADD', team_rank, total, team)
+end
+"#
+    };
+}
+
+/// 重新计算队伍在排行榜上的分数
+///
+/// KEYS[1] 排行榜，KEYS[2] 玩家所在的队伍，KEYS[3] 队伍排行榜；
+/// ARGV[1] 队伍id(空表示玩家当前所在的队伍)，ARGV[2] openid，ARGV[3] 队伍成员key的前缀，
+/// ARGV[4] 2^tie_bits，ARGV[5] min_score，ARGV[6] 统计的成员数量(0 全部)，ARGV[7] 排序方式。
+const TEAM_SCORE_SCRIPT: &str = concat!(
+    team_score_lua_fn!(),
+    r#"
+local team = ARGV[1]
+if team == '' then
+    team = redis.call('HGET', KEYS[2], ARGV[2])
+    if not team then
+        return 0
+    end
+end
+update_team_score(KEYS[1], KEYS[3], ARGV[3], team, tonumber(ARGV[4]), tonumber(ARGV[5]), tonumber(ARGV[6]), ARGV[7])
+return 0
+"#
+);
+
+/// 更新玩家所在的队伍并重新计算原来的队伍和新队伍的分数
+///
+/// KEYS[1] 玩家所在的队伍，KEYS[2..] 每个队伍排行榜的排行榜和队伍排行榜；
+/// ARGV[1] openid，ARGV[2] 新队伍id(空表示离开队伍)，ARGV[3] 队伍成员key的前缀，ARGV[4] 队伍最多的成员数量(0 不限制)，
+/// ARGV[5..] 每个队伍排行榜的 2^tie_bits、min_score、统计的成员数量、排序方式。
+/// 返回 {是否更新, 原来的队伍id}，新队伍已满时不更新
+const TEAM_MEMBER_SCRIPT: &str = concat!(
+    team_score_lua_fn!(),
+    r#"
+local old = redis.call('HGET', KEYS[1], ARGV[1]) or ''
+local team = ARGV[2]
+if old == team then
+    return {1, old}
+end
+local max_size = tonumber(ARGV[4])
+if team ~= '' and max_size > 0 and redis.call('SCARD', ARGV[3] .. team) >= max_size then
+    return {0, old}
+end
+if old ~= '' then
+    redis.call('SREM', ARGV[3] .. old, ARGV[1])
+end
+if team ~= '' then
+    redis.call('HSET', KEYS[1], ARGV[1], team)
+    redis.call('SADD', ARGV[3] .. team, ARGV[1])
+else
+    redis.call('HDEL', KEYS[1], ARGV[1])
+end
+for i = 2, #KEYS, 2 do
+    local arg = 5 + (i - 2) * 2
+    for _, changed in ipairs({old, team}) do
+        if changed ~= '' then
+            update_team_score(KEYS[i], KEYS[i + 1], ARGV[3], changed, tonumber(ARGV[arg]), tonumber(ARGV[arg + 1]), tonumber(ARGV[arg + 2]), ARGV[arg + 3])
+        end
+    end
+end
+return {1, old}
+"#
+);
+
+/// 重新计算玩家在组合排行榜上的分数，与 ZUNIONSTORE WEIGHTS 的结果一致
+///
+/// KEYS[1] 组合排行榜，KEYS[2..] 来源排行榜；ARGV[1] openid，ARGV[2..] 来源排行榜的权重。
+/// 玩家不在任何来源排行榜上时从组合排行榜移除
+const COMPOSITE_SCORE_SCRIPT: &str = r#"
+local total = 0
+local found = false
+for i = 2, #KEYS do
+    local score = redis.call('ZSCORE', KEYS[i], ARGV[1])
+    if score then
+        found = true
+        total = total + tonumber(score) * tonumber(ARGV[i])
+    end
+end
+if found then
+    redis.call('ZADD', KEYS[1], total, ARGV[1])
+else
+    redis.call('ZREM', KEYS[1], ARGV[1])
+end
+return 0
+"#;
+
+/// 移除排行榜保留人数之外排名最靠后的玩家
+///
+/// KEYS[1] 排行榜，KEYS[2] 玩家所在的分区，KEYS[3] 被移除的玩家；ARGV[1] 保留的人数，ARGV[2] 每次最多移除的人数，
+/// ARGV[3] 排序方式，ARGV[4] 分区排行榜key的前缀。返回移除的玩家
+const TRIM_RANK_SCRIPT: &str = r#"
+local count = redis.call('ZCARD', KEYS[1]) - tonumber(ARGV[1])
+if count <= 0 then
+    return {}
+end
+count = math.min(count, tonumber(ARGV[2]))
+local start = 0
+local stop = count - 1
+if ARGV[3] == 'asc' then
+    start = -count
+    stop = -1
+end
+local members = redis.call('ZRANGE', KEYS[1], start, stop)
+for _, member in ipairs(members) do
+    local partition = redis.call('HGET', KEYS[2], member)
+    if partition then
+        redis.call('ZREM', ARGV[4] .. partition, member)
+    end
+    redis.call('SADD', KEYS[3], member)
+end
+redis.call('ZREMRANGEBYRANK', KEYS[1], start, stop)
+return members
+"#;
+
+/// 从排行榜和所在的分区中移除玩家
+///
+/// KEYS[1] 排行榜，KEYS[2] 玩家所在的分区；ARGV[1] openid，ARGV[2] 分区排行榜key的前缀。
+/// 返回玩家是否在排行榜上
+const REMOVE_USER_SCRIPT: &str = r#"
+local partition = redis.call('HGET', KEYS[2], ARGV[1])
+if partition then
+    redis.call('ZREM', ARGV[2] .. partition, ARGV[1])
+    redis.call('HDEL', KEYS[2], ARGV[1])
+end
+return redis.call('ZREM', KEYS[1], ARGV[1])
+"#;
+
+/// 把被封禁的玩家从排行榜移到封禁的排行榜，同时从所在的分区移除
+///
+/// KEYS[1] 排行榜，KEYS[2] 封禁的排行榜，KEYS[3] 玩家所在的分区；
+/// ARGV[1] 分区排行榜key的前缀，ARGV[2..] openid。玩家所在的分区保留
+const BAN_USER_SCRIPT: &str = r#"
+for i = 2, #ARGV do
+    local score = redis.call('ZSCORE', KEYS[1], ARGV[i])
+    if score then
+        redis.call('ZADD', KEYS[2], score, ARGV[i])
+        redis.call('ZREM', KEYS[1], ARGV[i])
+        local partition = redis.call('HGET', KEYS[3], ARGV[i])
+        if partition then
+            redis.call('ZREM', ARGV[1] .. partition, ARGV[i])
+        end
+    end
+end
+return 0
+"#;
+
+/// 合并滚动排行榜的分桶后把被封禁的玩家移到封禁的排行榜
+///
+/// 滚动排行榜的分数以分桶为准，先清空封禁的排行榜再移动，滚动排行榜不支持分区。
+/// KEYS[1] 排行榜，KEYS[2] 封禁的排行榜，KEYS[3] appid下的封禁
+const WINDOW_BAN_SCRIPT: &str = r#"
+redis.call('DEL', KEYS[2])
+for _, openid in ipairs(redis.call('HKEYS', KEYS[3])) do
+    local score = redis.call('ZSCORE', KEYS[1], openid)
+    if score then
+        redis.call('ZADD', KEYS[2], score, openid)
+        redis.call('ZREM', KEYS[1], openid)
+    end
+end
+return 0
+"#;
+
+/// 把解除封禁的玩家从封禁的排行榜移回排行榜和所在的分区
+///
+/// KEYS[1] 排行榜，KEYS[2] 封禁的排行榜，KEYS[3] 玩家所在的分区；
+/// ARGV[1] openid，ARGV[2] 分区排行榜key的前缀
+const UNBAN_USER_SCRIPT: &str = r#"
+local score = redis.call('ZSCORE', KEYS[2], ARGV[1])
+if not score then
+    return 0
+end
+redis.call('ZREM', KEYS[2], ARGV[1])
+redis.call('ZADD', KEYS[1], score, ARGV[1])
+local partition = redis.call('HGET', KEYS[3], ARGV[1])
+if partition then
+    redis.call('ZADD', ARGV[2] .. partition, score, ARGV[1])
+end
+return 1
+"#;
+
+/// 校验并记录玩家的提交频率，返回违反的频率规则
+///
+/// 两个规则都通过时才记录，被拒绝的提交不计数。每分钟的次数按自然分钟计数，
+/// 在分钟交界处前后各提交一次上限，短时间内最多可以提交两倍的次数。
+/// KEYS[1] 玩家上次提交的标记，KEYS[2] 玩家当前这一分钟的提交次数；
+/// ARGV[1] 两次提交的最小间隔(秒)，ARGV[2] 每分钟最多提交的次数，为 0 时不限制。没有违反返回空字符串
+const ANTI_CHEAT_RATE_SCRIPT: &str = r#"
+local interval = tonumber(ARGV[1])
+local max_count = tonumber(ARGV[2])
+if interval > 0 and redis.call('EXISTS', KEYS[1]) == 1 then
+    return 'min_interval'
+end
+if max_count > 0 and tonumber(redis.call('GET', KEYS[2]) or '0') >= max_count then
+    return 'max_per_minute'
+end
+if interval > 0 then
+    redis.call('SET', KEYS[1], 1, 'EX', interval)
+end
+if max_count > 0 then
+    if redis.call('INCR', KEYS[2]) == 1 then
+        redis.call('EXPIRE', KEYS[2], 120)
+    end
+end
+return ''
+"#;
+
+/// 退还记录的提交频率
+///
+/// KEYS 和 ARGV 与 ANTI_CHEAT_RATE_SCRIPT 相同
+const ANTI_CHEAT_REFUND_SCRIPT: &str = r#"
+if tonumber(ARGV[1]) > 0 then
+    redis.call('DEL', KEYS[1])
+end
+if tonumber(ARGV[2]) > 0 and redis.call('DECR', KEYS[2]) <= 0 then
+    redis.call('DEL', KEYS[2])
+end
+return 0
+"#;
+
+/// 生成重新计算队伍分数的命令，team_id 为空时计算玩家当前所在的队伍
+fn add_team_score_cmd(
+    cmd_pipe: &mut redis::Pipeline,
+    appid: &String,
+    rank_key: &String,
+    team_id: &str,
+    openid: &String,
+    rank_table_config: &RankTableConfig,
+) {
+    cmd_pipe
+        .cmd("EVAL")
+        .arg(TEAM_SCORE_SCRIPT)
+        .arg(3)
+        .arg(get_redis_rank_key(appid, rank_key))
+        .arg(get_redis_team_user_key(appid))
+        .arg(get_redis_team_rank_key(appid, rank_key))
+        .arg(team_id)
+        .arg(openid)
+        .arg(get_redis_team_member_key_prefix(appid))
+        .arg(1i64 << rank_table_config.tie_bits())
+        .arg(rank_table_config.min_score)
+        .arg(rank_table_config.team_member_limit())
+        .arg(rank_table_config.sort_order.as_str())
+        .ignore();
+}
+
+/// 写入分数的事务最多尝试的次数
+const MYSQL_SCORE_WRITE_ATTEMPTS: usize = 3;
+
+/// 写入分数的事务是否可以整个重试：死锁，或者首次上榜的玩家被其他事务同时插入
+fn is_retryable_score_write(err: &sqlx::Error) -> bool {
+    match err
+        .as_database_error()
+        .and_then(|err| err.try_downcast_ref::<MySqlDatabaseError>())
+    {
+        // 1213 死锁，1062 主键冲突
+        Some(err) => err.number() == 1213 || err.number() == 1062,
+        None => false,
+    }
+}
+
+/// 在事务中按更新策略写入同一个排行榜的分数，并写入分数提交历史
+///
+/// 已经上榜的玩家先按主键加锁读取提交前的分数，历史中提交前后的分数总是准确的。没有上榜的玩家不加锁，
+/// 对不存在的行加锁会锁住间隙，并发首次提交时互相死锁；直接插入，其他事务已经插入时返回主键冲突，
+/// 由调用方重试整个事务，重试时玩家已经上榜
+async fn update_rank_scores_in_tx(
+    tx: &mut Transaction<'_, MySql>,
+    table_name: &String,
+    user_scores: &[UserScoreInfo],
+    rank_table_config: &RankTableConfig,
+    source: &ScoreRequestSource,
+) -> Result<(), sqlx::Error> {
+    let mut openids: Vec<&String> = user_scores
+        .iter()
+        .map(|user_score| &user_score.openid)
+        .collect();
+    openids.sort();
+    openids.dedup();
+    let mut select_builder: QueryBuilder<MySql> =
+        QueryBuilder::new(format!("SELECT openid FROM {table_name} WHERE openid IN ("));
+    let mut separated = select_builder.separated(",");
+    for openid in &openids {
+        separated.push_bind(*openid);
+    }
+    separated.push_unseparated(")");
+    let existing: Vec<String> = select_builder
+        .build_query_scalar()
+        .fetch_all(&mut **tx)
+        .await?;
+
+    // 只对已经存在的行按主键顺序加锁，并发批量提交时不会因为加锁顺序不同而死锁
+    let old_scores: HashMap<String, i64> = if existing.is_empty() {
+        HashMap::new()
+    } else {
+        let mut lock_builder: QueryBuilder<MySql> = QueryBuilder::new(format!(
+            "SELECT openid,score FROM {table_name} WHERE openid IN ("
+        ));
+        let mut separated = lock_builder.separated(",");
+        for openid in &existing {
+            separated.push_bind(openid);
+        }
+        separated.push_unseparated(") ORDER BY openid FOR UPDATE");
+        lock_builder
+            .build_query_as::<(String, i64)>()
+            .fetch_all(&mut **tx)
+            .await?
+            .into_iter()
+            .collect()
+    };
+
+    // 没有上榜的玩家第一次提交直接插入，同一批次中再次提交和已经上榜的玩家按更新策略写入
+    let mut inserted = HashSet::new();
+    let (inserts, upserts): (Vec<&UserScoreInfo>, Vec<&UserScoreInfo>) =
+        user_scores.iter().partition(|user_score| {
+            !old_scores.contains_key(&user_score.openid)
+                && inserted.insert(user_score.openid.clone())
+        });
+    if !inserts.is_empty() {
+        build_score_write(table_name, inserts, None)
+            .build()
+            .execute(&mut **tx)
+            .await?;
+    }
+    if !upserts.is_empty() {
+        build_score_write(table_name, upserts, Some(rank_table_config))
+            .build()
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    let mut select_builder: QueryBuilder<MySql> = QueryBuilder::new(format!(
+        "SELECT openid,score FROM {table_name} WHERE openid IN ("
+    ));
+    let mut separated = select_builder.separated(",");
+    for openid in &openids {
+        separated.push_bind(*openid);
+    }
+    separated.push_unseparated(")");
+    let new_scores: HashMap<String, i64> = select_builder
+        .build_query_as::<(String, i64)>()
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .collect();
+    // 同一批次中同一个玩家提交多次时，每条历史记录的都是整批提交前后的分数
+    let histories: Vec<RankScoreHistory> = user_scores
+        .iter()
+        .map(|user_score| {
+            to_score_history(
+                user_score,
+                old_scores.get(&user_score.openid).copied(),
+                new_scores
+                    .get(&user_score.openid)
+                    .copied()
+                    .unwrap_or(user_score.score),
+                source,
+            )
+        })
+        .collect();
+    build_score_history_insert(&histories)
+        .build()
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// 生成分数提交历史
+fn to_score_history(
+    user_score: &UserScoreInfo,
+    old_score: Option<i64>,
+    new_score: i64,
+    source: &ScoreRequestSource,
+) -> RankScoreHistory {
+    RankScoreHistory {
+        id: 0,
+        appid: user_score.appid.clone(),
+        rank_key: user_score.rank_key.clone(),
+        openid: user_score.openid.clone(),
+        old_score,
+        new_score,
+        submitted_score: user_score.score,
+        source_ip: source.source_ip.clone(),
+        request_id: source.request_id.clone(),
+        create_time: user_score.update_time,
+    }
+}
+
+/// 生成写入分数的sql，玩家提交的字段都使用参数绑定
+///
+/// rank_table_config 为 None 时只插入，玩家已经存在时返回主键冲突；否则玩家已经存在时按更新策略更新
+fn build_score_write<'a>(
+    table_name: &String,
+    user_scores: Vec<&'a UserScoreInfo>,
+    rank_table_config: Option<&RankTableConfig>,
+) -> QueryBuilder<'a, MySql> {
+    let mut query_builder: QueryBuilder<MySql> = QueryBuilder::new(format!(
+        "INSERT INTO {table_name} (openid,nick_name,score,update_time,rank_partition) "
+    ));
+    query_builder.push_values(user_scores, |mut b, user_score| {
+        b.push_bind(&user_score.openid)
+            .push_bind(&user_score.nick_name)
+            .push_bind(user_score.score)
+            .push_bind(user_score.update_time)
+            .push_bind(&user_score.partition);
+    });
+    let rank_table_config = match rank_table_config {
+        Some(rank_table_config) => rank_table_config,
+        None => return query_builder,
+    };
+    // update_time 需要在 score 之前更新，否则比较的是更新后的分数
+    query_builder.push(format!(
+        " ON DUPLICATE KEY UPDATE nick_name=VALUES(nick_name),update_time={},score={},rank_partition={}",
+        get_mysql_update_time_expr(&rank_table_config.update_policy),
+        get_mysql_update_score_expr(rank_table_config),
+        MYSQL_UPDATE_PARTITION_EXPR
+    ));
+    query_builder
+}
+
+/// 生成批量写入分数提交历史的sql
+fn build_score_history_insert(histories: &Vec<RankScoreHistory>) -> QueryBuilder<'_, MySql> {
+    let mut query_builder: QueryBuilder<MySql> = QueryBuilder::new(
+        "INSERT INTO rank_score_history (appid,rank_key,openid,old_score,new_score,submitted_score,source_ip,request_id,create_time) ",
+    );
+    query_builder.push_values(histories, |mut b, history| {
+        b.push_bind(&history.appid)
+            .push_bind(&history.rank_key)
+            .push_bind(&history.openid)
+            .push_bind(history.old_score)
+            .push_bind(history.new_score)
+            .push_bind(history.submitted_score)
+            .push_bind(&history.source_ip)
+            .push_bind(&history.request_id)
+            .push_bind(history.create_time);
+    });
+    query_builder
+}
+
+/// 根据更新策略生成redis更新分数的命令
+fn add_update_score_cmd(
+    cmd_pipe: &mut redis::Pipeline,
+    key: &String,
+    user_score: &UserScoreInfo,
+    update_policy: &UpdatePolicy,
+    rank_table_config: &RankTableConfig,
+) {
+    add_zset_score_cmd(cmd_pipe, key, user_score, update_policy, rank_table_config);
+    // 再次上榜后不再是被裁剪的玩家，从mysql重新加载时不再跳过
+    if rank_table_config.max_entries > 0 {
+        cmd_pipe
+            .cmd("SREM")
+            .arg(get_redis_trimmed_user_key(
+                &user_score.appid,
+                &user_score.rank_key,
+            ))
+            .arg(&user_score.openid)
+            .ignore();
+    }
+    if rank_table_config.is_rolling_window() {
+        add_window_bucket_cmd(cmd_pipe, user_score, update_policy, rank_table_config);
+    } else {
+        // 玩家没有提交分区时也需要同步到之前所在的分区
+        let appid = &user_score.appid;
+        let rank_key = &user_score.rank_key;
+        cmd_pipe
+            .cmd("EVAL")
+            .arg(PARTITION_SCORE_SCRIPT)
+            .arg(3)
+            .arg(key)
+            .arg(get_redis_partition_user_key(appid, rank_key))
+            .arg(get_redis_partition_list_key(appid, rank_key))
+            .arg(&user_score.openid)
+            .arg(&user_score.partition)
+            .arg(get_redis_partition_rank_key_prefix(appid, rank_key))
+            .ignore();
+        // 成员分数变化后重新计算所在队伍的分数
+        if rank_table_config.is_team_board() {
+            add_team_score_cmd(
+                cmd_pipe,
+                appid,
+                rank_key,
+                "",
+                &user_score.openid,
+                rank_table_config,
+            );
+        }
+    }
+}
+
+/// 按更新策略生成写入排行榜分数的命令
+fn add_zset_score_cmd(
+    cmd_pipe: &mut redis::Pipeline,
+    key: &String,
+    user_score: &UserScoreInfo,
+    update_policy: &UpdatePolicy,
+    rank_table_config: &RankTableConfig,
+) {
+    let score = rank_table_config.encode_redis_score(user_score.score, user_score.update_time);
+    match update_policy {
+        UpdatePolicy::Replace => {
+            cmd_pipe
+                .cmd("ZADD")
+                .arg(key)
+                .arg(score)
+                .arg(&user_score.openid)
+                .ignore();
+        }
+        UpdatePolicy::Max | UpdatePolicy::Min => {
+            add_best_score_cmd(
+                cmd_pipe,
+                key,
+                score,
+                user_score,
+                update_policy,
+                rank_table_config,
+            );
+        }
+        UpdatePolicy::Increment => {
+            // 首次上榜时从0分和同分排序值开始累加，保证同分时仍按上榜时间排序
+            cmd_pipe
+                .cmd("EVAL")
+                .arg(INCREMENT_SCORE_SCRIPT)
+                .arg(1)
+                .arg(key)
+                .arg(&user_score.openid)
+                .arg(
+                    rank_table_config
+                        .encode_redis_score(rank_table_config.min_score, user_score.update_time),
+                )
+                .arg(user_score.score)
+                .arg(1i64 << rank_table_config.tie_bits())
+                .arg(-rank_table_config.min_score)
+                .arg(rank_table_config.max_score - rank_table_config.min_score)
+                .ignore();
+        }
+    }
+}
+
+/// 保留最高分、最低分策略写入分数的命令，分数相同时保留原来的同分排序值
+fn add_best_score_cmd(
+    cmd_pipe: &mut redis::Pipeline,
+    key: &String,
+    score: f64,
+    user_score: &UserScoreInfo,
+    update_policy: &UpdatePolicy,
+    rank_table_config: &RankTableConfig,
+) {
+    let compare = match update_policy {
+        UpdatePolicy::Min => "lt",
+        _ => "gt",
+    };
+    cmd_pipe
+        .cmd("EVAL")
+        .arg(BEST_SCORE_SCRIPT)
+        .arg(1)
+        .arg(key)
+        .arg(score)
+        .arg(1i64 << rank_table_config.tie_bits())
+        .arg(compare)
+        .arg(&user_score.openid)
+        .ignore();
+}
+
+/// 滚动排行榜同时把分数写入当前的分桶
+///
+/// 与排行榜在同一个事务中写入，避免合并任务覆盖掉新写入的分数
+fn add_window_bucket_cmd(
+    cmd_pipe: &mut redis::Pipeline,
+    user_score: &UserScoreInfo,
+    update_policy: &UpdatePolicy,
+    rank_table_config: &RankTableConfig,
+) {
+    let appid = &user_score.appid;
+    let rank_key = &user_score.rank_key;
+    let index = rank_table_config.window_bucket_index(user_score.update_time);
+    let bucket_key = get_redis_rank_bucket_key(appid, rank_key, index);
+    let score = rank_table_config.encode_redis_score(user_score.score, user_score.update_time);
+    cmd_pipe.atomic();
+    match update_policy {
+        UpdatePolicy::Max | UpdatePolicy::Min => {
+            add_best_score_cmd(
+                cmd_pipe,
+                &bucket_key,
+                score,
+                user_score,
+                update_policy,
+                rank_table_config,
+            );
+        }
+        UpdatePolicy::Increment => {
+            // 分桶只保存累加值，基础分单独保存，合并时只加一次
+            let base_key = get_redis_rank_window_base_key(appid, rank_key);
+            cmd_pipe
+                .cmd("ZADD")
+                .arg(&base_key)
+                .arg("NX")
+                .arg(rank_table_config.encode_redis_score(0, user_score.update_time))
+                .arg(&user_score.openid)
+                .ignore()
+                .cmd("EXPIRE")
+                .arg(&base_key)
+                .arg(rank_table_config.window_seconds + rank_table_config.bucket_seconds)
+                .ignore()
+                .cmd("ZINCRBY")
+                .arg(&bucket_key)
+                .arg(rank_table_config.encode_redis_increment(user_score.score))
+                .arg(&user_score.openid)
+                .ignore();
+        }
+        // 滚动排行榜不支持覆盖策略
+        UpdatePolicy::Replace => return,
+    }
+    // 分桶移出时间窗口后自动过期
+    cmd_pipe
+        .cmd("EXPIREAT")
+        .arg(&bucket_key)
+        .arg(
+            (index + rank_table_config.window_bucket_count() + 1)
+                * rank_table_config.bucket_seconds,
+        )
+        .ignore();
+}
+
+/// 根据排序方式获取redis排名命令
+fn get_redis_rank_cmd(sort_order: &SortOrder) -> &'static str {
+    match sort_order {
+        SortOrder::Desc => "ZREVRANK",
+        SortOrder::Asc => "ZRANK",
+    }
+}
+
+/// 根据排序方式获取redis范围查询命令
+fn get_redis_range_cmd(sort_order: &SortOrder) -> &'static str {
+    match sort_order {
+        SortOrder::Desc => "ZREVRANGE",
+        SortOrder::Asc => "ZRANGE",
+    }
+}
+
+/// 根据更新策略生成mysql更新分数的表达式
+///
+/// 累加后的分数限制在排行榜的分数范围内，与redis保持一致
+fn get_mysql_update_score_expr(rank_table_config: &RankTableConfig) -> String {
+    match rank_table_config.update_policy {
+        UpdatePolicy::Replace => "VALUES(score)".to_string(),
+        UpdatePolicy::Max => "GREATEST(score,VALUES(score))".to_string(),
+        UpdatePolicy::Min => "LEAST(score,VALUES(score))".to_string(),
+        UpdatePolicy::Increment => format!(
+            "LEAST(GREATEST(score+VALUES(score),{}),{})",
+            rank_table_config.min_score, rank_table_config.max_score
+        ),
+    }
+}
+
+/// 根据更新策略生成mysql更新分数时间的表达式
+///
+/// 与redis保持一致，分数没有变化时保留原来的时间，累加时保留首次上榜时间
+fn get_mysql_update_time_expr(update_policy: &UpdatePolicy) -> &'static str {
+    match update_policy {
+        UpdatePolicy::Replace => "VALUES(update_time)",
+        UpdatePolicy::Max => "IF(VALUES(score)>score,VALUES(update_time),update_time)",
+        UpdatePolicy::Min => "IF(VALUES(score)<score,VALUES(update_time),update_time)",
+        UpdatePolicy::Increment => "update_time",
+    }
+}
+
+/// mysql更新分区的表达式，没有提交分区时保留原来的分区
+const MYSQL_UPDATE_PARTITION_EXPR: &str =
+    "IF(VALUES(rank_partition)='',rank_partition,VALUES(rank_partition))";
 
-    tracing::debug!("socre:{}", score);
-    score
+/// 根据排名方式获取mysql窗口函数
+fn get_mysql_rank_window_func(rank_style: &RankStyle) -> &'static str {
+    match rank_style {
+        RankStyle::Ordinal => "ROW_NUMBER",
+        RankStyle::Competition => "RANK",
+        RankStyle::Dense => "DENSE_RANK",
+    }
+}
+
+/// 获取mysql计算排名的排序，返回 (位置的排序, 排名的排序)
+///
+/// 排名的排序中分数和同分排序规则都相同的玩家并列，位置的排序再按 openid 区分，与redis同分时按成员排序一致
+fn get_mysql_rank_order_by(rank_table_config: &RankTableConfig) -> (String, String) {
+    let score_order = match rank_table_config.sort_order {
+        SortOrder::Desc => "score DESC",
+        SortOrder::Asc => "score ASC",
+    };
+    let ranking_order = match rank_table_config.tie_policy {
+        TiePolicy::Earliest => format!("{score_order},update_time ASC"),
+        TiePolicy::Latest => format!("{score_order},update_time DESC"),
+        TiePolicy::Shared => score_order.to_string(),
+    };
+    let openid_order = match rank_table_config.sort_order {
+        SortOrder::Desc => "openid DESC",
+        SortOrder::Asc => "openid ASC",
+    };
+    let position_order = format!("{ranking_order},{openid_order}");
+    match rank_table_config.rank_style {
+        RankStyle::Ordinal => (position_order.clone(), position_order),
+        _ => (position_order, ranking_order),
+    }
 }
 
 /// 获取mysql表名
@@ -520,7 +3789,142 @@ fn get_redis_rank_key(appid: &String, rank_key: &str) -> String {
     format!("rank:{appid}:{rank_key}")
 }
 
+/// 获取redis 分区排行榜key的前缀
+fn get_redis_partition_rank_key_prefix(appid: &String, rank_key: &str) -> String {
+    format!("rank_part:{appid}:{rank_key}:")
+}
+
+/// 获取redis 排行榜或者分区排行榜的key
+fn get_redis_partition_rank_key(
+    appid: &String,
+    rank_key: &str,
+    partition: &Option<String>,
+) -> String {
+    match partition {
+        Some(partition) => format!(
+            "{}{partition}",
+            get_redis_partition_rank_key_prefix(appid, rank_key)
+        ),
+        None => get_redis_rank_key(appid, rank_key),
+    }
+}
+
+/// 获取redis 玩家所在分区的key
+fn get_redis_partition_user_key(appid: &String, rank_key: &str) -> String {
+    format!("rank_part_user:{appid}:{rank_key}")
+}
+
+/// 获取redis 排行榜分区列表的key
+fn get_redis_partition_list_key(appid: &String, rank_key: &str) -> String {
+    format!("rank_parts:{appid}:{rank_key}")
+}
+
+/// 获取redis 重建排行榜时临时的key
+fn get_redis_rank_rebuild_key(appid: &String, rank_key: &str) -> String {
+    format!("rank_rebuild:{appid}:{rank_key}")
+}
+
+/// 获取redis 滚动排行榜分桶的key
+fn get_redis_rank_bucket_key(appid: &String, rank_key: &str, bucket_index: i64) -> String {
+    format!("rank_bucket:{appid}:{rank_key}:{bucket_index}")
+}
+
+/// 获取redis 滚动排行榜累加策略基础分的key
+fn get_redis_rank_window_base_key(appid: &String, rank_key: &str) -> String {
+    format!("rank_window_base:{appid}:{rank_key}")
+}
+
+/// 获取redis 滚动排行榜合并时临时的key
+fn get_redis_rank_window_merge_key(appid: &String, rank_key: &str) -> String {
+    format!("rank_window_merge:{appid}:{rank_key}")
+}
+
+/// 获取redis 队伍排行榜的key
+fn get_redis_team_rank_key(appid: &String, rank_key: &str) -> String {
+    format!("rank_team:{appid}:{rank_key}")
+}
+
+/// 获取redis 玩家所在队伍的key
+fn get_redis_team_user_key(appid: &String) -> String {
+    format!("team_user:{appid}")
+}
+
+/// 获取redis 队伍成员key的前缀
+fn get_redis_team_member_key_prefix(appid: &String) -> String {
+    format!("team_member:{appid}:")
+}
+
+/// 获取redis 队伍成员的key
+fn get_redis_team_member_key(appid: &String, team_id: &str) -> String {
+    format!("{}{team_id}", get_redis_team_member_key_prefix(appid))
+}
+
 /// 获取redis 用户信息的key
 fn get_redis_user_key(appid: &String) -> String {
     format!("userinfo:{appid}")
 }
+
+/// 获取redis 封禁玩家的key
+fn get_redis_ban_key(appid: &String) -> String {
+    format!("rank_ban:{appid}")
+}
+
+/// 获取redis 被封禁的玩家所在排行榜的key
+fn get_redis_banned_rank_key(appid: &String, rank_key: &str) -> String {
+    format!("rank_banned:{appid}:{rank_key}")
+}
+
+/// 获取redis 超出保留人数被移出排行榜的玩家的key
+fn get_redis_trimmed_user_key(appid: &String, rank_key: &str) -> String {
+    format!("rank_trimmed:{appid}:{rank_key}")
+}
+
+/// 获取redis 玩家上次提交分数标记的key，过期时间为最小提交间隔
+fn get_redis_cheat_last_key(appid: &String, rank_key: &str, openid: &str) -> String {
+    format!("rank_cheat_last:{appid}:{rank_key}:{openid}")
+}
+
+/// 获取redis 玩家每分钟提交次数的key
+fn get_redis_cheat_count_key(appid: &String, rank_key: &str, openid: &str, minute: i64) -> String {
+    format!("rank_cheat_count:{appid}:{rank_key}:{openid}:{minute}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn score_upsert_binds_user_fields() {
+        let user_score = UserScoreInfo {
+            appid: "app".to_string(),
+            rank_key: "level".to_string(),
+            openid: "o'penid".to_string(),
+            nick_name: "it's me".to_string(),
+            score: 100,
+            update_time: 1,
+            partition: "eu'); DROP TABLE rank_score_history; --".to_string(),
+        };
+        let rank_table_config = RankTableConfig {
+            update_policy: UpdatePolicy::Increment,
+            min_score: -10,
+            max_score: 1000,
+            ..Default::default()
+        };
+        let query_builder = build_score_write(
+            &"rank_app_level".to_string(),
+            vec![&user_score],
+            Some(&rank_table_config),
+        );
+        let sql = query_builder.sql();
+        assert!(sql.contains("VALUES (?, ?, ?, ?, ?)"));
+        assert!(!sql.contains("o'penid"));
+        assert!(!sql.contains("it's me"));
+        assert!(!sql.contains("DROP TABLE"));
+        // 累加后的分数限制在分数范围内
+        assert!(sql.contains("score=LEAST(GREATEST(score+VALUES(score),-10),1000)"));
+        // 首次上榜只插入，其他事务同时插入时返回主键冲突
+        let query_builder =
+            build_score_write(&"rank_app_level".to_string(), vec![&user_score], None);
+        assert!(!query_builder.sql().contains("ON DUPLICATE KEY"));
+    }
+}
@@ -9,6 +9,9 @@ pub struct ApiSuccessResponse<T: Serialize> {
     msg: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     data: Option<T>,
+    /// 排行榜当前的赛季
+    #[serde(skip_serializing_if = "Option::is_none")]
+    season: Option<i32>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize)]
@@ -28,6 +31,7 @@ where
             code: 0,
             msg: "success".to_string(),
             data: Some(data),
+            season: None,
         };
     }
 
@@ -36,8 +40,14 @@ where
             code: 0,
             msg: "success".to_string(),
             data: None,
+            season: None,
         };
     }
+
+    pub fn with_season(mut self, season: i32) -> Self {
+        self.season = Some(season);
+        self
+    }
 }
 
 impl ApiErrorResponse {
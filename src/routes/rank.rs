@@ -1,4 +1,7 @@
-use crate::{handler::rank_handler, state::{rank_state::RankState,rank_config_state::RankConfigState}};
+use crate::{
+    handler::rank_handler,
+    state::{rank_config_state::RankConfigState, rank_state::RankState},
+};
 use axum::{
     routing::{delete, post},
     Router,
@@ -10,9 +13,51 @@ pub fn routes() -> Router<RankState> {
         "/rank",
         Router::new()
             .route("/update_score", post(rank_handler::update_rank_score))
+            .route(
+                "/batch_update_score",
+                post(rank_handler::batch_update_rank_score),
+            )
+            .route(
+                "/update_group_score",
+                post(rank_handler::update_group_score),
+            )
             .route("/get_user_rank", post(rank_handler::get_user_rank))
+            .route("/get_rank_stats", post(rank_handler::get_rank_stats))
             .route("/get_user_score", post(rank_handler::get_user_score)) // .layer(middleware::from_fn(body_signature_verify));
-            .route("/get_top_user_rank", post(rank_handler::get_top_user_rank)),
+            .route(
+                "/get_bulk_user_rank",
+                post(rank_handler::get_bulk_user_rank),
+            )
+            .route(
+                "/get_friend_user_rank",
+                post(rank_handler::get_friend_user_rank),
+            )
+            .route("/get_top_user_rank", post(rank_handler::get_top_user_rank))
+            .route(
+                "/get_range_user_rank",
+                post(rank_handler::get_range_user_rank),
+            )
+            .route(
+                "/get_around_user_rank",
+                post(rank_handler::get_around_user_rank),
+            )
+            .route(
+                "/get_rank_partitions",
+                post(rank_handler::get_rank_partitions),
+            )
+            .route("/join_team", post(rank_handler::join_team))
+            .route("/leave_team", post(rank_handler::leave_team))
+            .route("/get_team_top_rank", post(rank_handler::get_team_top_rank))
+            .route("/get_team_rank", post(rank_handler::get_team_rank))
+            .route("/get_team_members", post(rank_handler::get_team_members))
+            .route(
+                "/get_archive_periods",
+                post(rank_handler::get_archive_periods),
+            )
+            .route(
+                "/get_archive_range_rank",
+                post(rank_handler::get_archive_range_rank),
+            ),
     );
     return router;
 }
@@ -25,6 +70,33 @@ pub fn rank_config_routes() -> Router<RankConfigState> {
             .route(
                 "/delete_rank_config",
                 delete(rank_handler::delete_rank_config),
+            )
+            .route("/get_score_history", post(rank_handler::get_score_history))
+            .route("/remove_user", post(rank_handler::remove_user))
+            .route("/erase_user", post(rank_handler::erase_user))
+            .route("/ban_user", post(rank_handler::ban_user))
+            .route("/unban_user", post(rank_handler::unban_user))
+            .route("/get_bans", post(rank_handler::get_bans))
+            .route(
+                "/get_cheat_violations",
+                post(rank_handler::get_cheat_violations),
+            )
+            .route("/get_score_reviews", post(rank_handler::get_score_reviews))
+            .route(
+                "/approve_score_review",
+                post(rank_handler::approve_score_review),
+            )
+            .route(
+                "/reject_score_review",
+                post(rank_handler::reject_score_review),
+            )
+            .route(
+                "/get_reward_settlements",
+                post(rank_handler::get_reward_settlements),
+            )
+            .route(
+                "/ack_reward_settlements",
+                post(rank_handler::ack_reward_settlements),
             ),
     );
     return router;
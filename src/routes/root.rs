@@ -11,8 +11,10 @@ use crate::state::rank_config_state::RankConfigState;
 use crate::state::rank_state::RankState;
 use crate::state::token_state::TokenState;
 use crate::state::user_state::UserState;
-use axum::routing::{get, IntoMakeService};
+use axum::extract::connect_info::IntoMakeServiceWithConnectInfo;
+use axum::routing::get;
 use axum::{extract::State, middleware, Router};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
@@ -20,7 +22,7 @@ use tower_http::trace::TraceLayer;
 pub fn routes(
     db_conn: Arc<Database>,
     rank_config_service: Arc<RankConfigService>,
-) -> IntoMakeService<Router> {
+) -> IntoMakeServiceWithConnectInfo<Router, SocketAddr> {
     let merged_router = {
         let auth_state = AuthState::new(&db_conn);
         let user_state = UserState::new(&db_conn);
@@ -62,5 +64,6 @@ pub fn routes(
         .nest("/api", merged_router)
         .layer(TraceLayer::new_for_http());
 
-    app_router.into_make_service()
+    // 提交分数时记录来源地址
+    app_router.into_make_service_with_connect_info::<SocketAddr>()
 }
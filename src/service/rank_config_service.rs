@@ -1,17 +1,30 @@
 use crate::config::parameter;
 use crate::db::database::Database;
-use crate::dto::rank_dto::{AddRankConfigReq, UpdateScoreRequest};
+use crate::dto::rank_dto::{
+    AddRankConfigReq, BanListReq, BanRes, BanUserReq, CheatViolationListReq, CheatViolationRes,
+    EraseUserReq, EraseUserRes, RemoveUserReq, RemovedRankRes, ScoreHistoryReq, ScoreHistoryRes,
+    UnbanUserReq,
+};
 use crate::error::api_error::ApiError;
 use crate::error::db_error::DbError;
+use crate::error::rank_error::RankError;
 use crate::error::request_error::RequestError;
-use crate::model::user::RankTableConfig;
-use crate::repository::rank_repository::{RankRepository, RankRepositoryTrait};
+use crate::model::user::{
+    CheatAction, CompositeSources, RankBan, RankStyle, RankTableConfig, RewardBrackets, ScoreType,
+    SortOrder, TeamAggregate, TiePolicy, UpdatePolicy, UserScoreInfo, DEFAULT_MAX_SCORE,
+    DEFAULT_MIN_SCORE, MAX_RANK_ENTRIES, MAX_SAFE_SCORE, MAX_TEAM_TOP_K, MAX_WINDOW_BUCKETS,
+    MIN_WINDOW_BUCKET_SECONDS,
+};
 use crate::pb::update_rank_config;
+use crate::repository::rank_repository::{RankRepository, RankRepositoryTrait};
 use deadpool_redis::Pool;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}};
-use tokio::sync::RwLock;
+use std::sync::{
+    atomic::{AtomicI64, AtomicU64, Ordering},
+    Arc, Mutex,
+};
 use std::time::Duration;
+use tokio::sync::RwLock;
 use tokio_cron_scheduler::{Job, JobScheduler};
 use uuid::Uuid;
 
@@ -65,27 +78,44 @@ impl RankConfigService {
             let mut guard = self.rank_table_configs.lock().unwrap();
             *guard = rank_table_configs;
         }
-		self.config_update_time.store(chrono::Utc::now().timestamp_millis() as u64,Ordering::Relaxed);
+        self.config_update_time.store(
+            chrono::Utc::now().timestamp_millis() as u64,
+            Ordering::Relaxed,
+        );
 
-		// 非master节点无需 开启计划任务，管理排行配置
-		if !self.master_node {
-			return true;
-		}
+        // 非master节点无需 开启计划任务，管理排行配置
+        if !self.master_node {
+            return true;
+        }
         // 保存定时任务启动的uuid
         let uuids = Vec::<(String, String, String)>::new();
         let arc_uuids = Arc::new(Mutex::new(uuids));
 
         {
-            let rank_table_configs: &Vec<RankTableConfig>;
-            // 最小锁使用范围
-            let guard = self.rank_table_configs.lock().unwrap();
-            rank_table_configs = guard.as_ref();
+            // 最小锁使用范围，复制配置后立即释放锁，避免跨await持有锁
+            let rank_table_configs: Vec<RankTableConfig> =
+                self.rank_table_configs.lock().unwrap().clone();
 
             // 根据启动参数，判断是否需要把所有mysql排行榜数据加载到redis
             if parameter::CMD_ARGS.get().is_some()
                 && parameter::CMD_ARGS.get().unwrap().contains("--sync_redis")
             {
-                for rank_tbl_cfg in rank_table_configs {
+                // 先加载队伍成员，写入成员分数时同时计算队伍分数
+                if self.read_mysql_team_member_write_to_redis().await == false {
+                    tracing::error!("init_rank sync team member err");
+                    return false;
+                }
+                // 先加载封禁，写入分数后把被封禁的玩家移出排行榜
+                if self.read_mysql_ban_write_to_redis().await == false {
+                    tracing::error!("init_rank sync ban err");
+                    return false;
+                }
+                for rank_tbl_cfg in &rank_table_configs {
+                    // 滚动排行榜只保存在redis的分桶中，mysql中是全部时间的分数
+                    // 组合排行榜由来源排行榜计算，mysql中没有分数
+                    if rank_tbl_cfg.is_rolling_window() || rank_tbl_cfg.is_composite() {
+                        continue;
+                    }
                     if self.read_mysql_rank_write_to_redis(rank_tbl_cfg).await == false {
                         tracing::error!(
                             "init_rank err appid:{} | rank_key:{}",
@@ -97,8 +127,43 @@ impl RankConfigService {
                 }
             }
 
+            // 根据启动参数，判断是否需要按新的分数编码从mysql重建redis排行榜
+            // 升级分数编码后需要停服执行一次
+            if parameter::CMD_ARGS.get().is_some()
+                && parameter::CMD_ARGS
+                    .get()
+                    .unwrap()
+                    .contains("--reencode_redis")
+            {
+                for rank_tbl_cfg in &rank_table_configs {
+                    if rank_tbl_cfg.is_rolling_window() {
+                        tracing::warn!(
+                            "init_rank reencode skip rolling window rank appid:{} | rank_key:{}",
+                            rank_tbl_cfg.appid,
+                            rank_tbl_cfg.rank_key
+                        );
+                        continue;
+                    }
+                    if rank_tbl_cfg.is_composite() {
+                        continue;
+                    }
+                    if self.rebuild_redis_rank_from_mysql(rank_tbl_cfg).await == false {
+                        tracing::error!(
+                            "init_rank reencode err appid:{} | rank_key:{}",
+                            rank_tbl_cfg.appid,
+                            rank_tbl_cfg.rank_key
+                        );
+                        return false;
+                    }
+                }
+            }
+
+            // 组合排行榜按来源排行榜重新计算，同步、重建来源排行榜后也保持一致
+            self.merge_composite_rank(get_composite_source_weights(&rank_table_configs, |_| true))
+                .await;
+
             // 启动定时任务
-            for rank_tbl_cfg in rank_table_configs {
+            for rank_tbl_cfg in &rank_table_configs {
                 let arc_uuids_clone = Arc::clone(&arc_uuids);
                 if let Some(uuid) = self.start_rank_cron_job(rank_tbl_cfg).await {
                     if !uuid.is_nil() {
@@ -115,6 +180,19 @@ impl RankConfigService {
             }
         }
 
+        // 启动裁剪排行榜人数的任务，所有排行榜共用，新添加的排行榜不需要单独启动
+        if self.start_rank_trim_job().await.is_none() {
+            return false;
+        }
+        // 启动解除过期封禁的任务
+        if self.start_rank_ban_expire_job().await.is_none() {
+            return false;
+        }
+        // 启动定期重新计算组合排行榜的任务
+        if self.start_rank_composite_merge_job().await.is_none() {
+            return false;
+        }
+
         // 更新定时任务的uuid到rank_service
         let guard = arc_uuids.lock().unwrap();
         for (appid, rank_key, uuid) in &(*guard) {
@@ -131,7 +209,7 @@ impl RankConfigService {
         return true;
     }
 
-	/// 初始化grpc服务
+    /// 初始化grpc服务
     pub async fn init_update_rank_config_grpc_service(
         &self,
         rank_config_service_arc: Arc<RankConfigService>,
@@ -154,7 +232,7 @@ impl RankConfigService {
         } else {
             tokio::spawn(async move {
                 let addr = parameter::get("GRPC_SERVER_URL");
-				let client = 
+                let client = 
 				match update_rank_config::update_rank_config_client::UpdateRankConfigClient::connect(addr).await {
 					Ok(client)=>{
 						client
@@ -163,15 +241,14 @@ impl RankConfigService {
 						panic!("update rank config grpc client connect failed! error:{}",e.to_string());
 					}
 				};
-				let mut service = UpdateRankConfigGrpcClient::new(rank_config_service_arc,client);
+                let mut service = UpdateRankConfigGrpcClient::new(rank_config_service_arc, client);
 
-				loop {
-					service.send_update_config_request().await;
-					tokio::time::sleep(Duration::from_secs(30)).await;
-				}
+                loop {
+                    service.send_update_config_request().await;
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                }
             });
         }
-
     }
 
     /// 启动加载排行榜表配置
@@ -186,6 +263,80 @@ impl RankConfigService {
         return rank;
     }
 
+    /// 从数据库读取排行榜，按当前的分数编码重建redis排行榜
+    ///
+    /// 先写入临时key，全部写入后再替换原来的排行榜
+    pub async fn rebuild_redis_rank_from_mysql(&self, table_config: &RankTableConfig) -> bool {
+        const PAGE_SIZE: u64 = 500;
+        if let Err(err) = self
+            .rank_repo
+            .clear_redis_rebuild_key(&table_config.appid, &table_config.rank_key)
+            .await
+        {
+            tracing::error!("clear redis rebuild key error :{}", err.to_string());
+            return false;
+        }
+        let mut start_index = 0;
+        loop {
+            match self
+                .rank_repo
+                .get_pagination_all_users_score_info_from_mysql(
+                    &table_config.appid,
+                    &table_config.rank_key,
+                    start_index,
+                    PAGE_SIZE,
+                )
+                .await
+            {
+                Ok(user_list) => {
+                    let page_len = user_list.len();
+                    let user_list = match self.skip_trimmed_users(table_config, user_list).await {
+                        Some(user_list) => user_list,
+                        None => return false,
+                    };
+                    if let Err(err) = self
+                        .rank_repo
+                        .add_rank_score_to_redis_rebuild_key(&user_list, table_config)
+                        .await
+                    {
+                        tracing::error!("rebuild score to redis error :{}", err.to_string());
+                        return false;
+                    }
+                    if (page_len as u64) < PAGE_SIZE {
+                        break;
+                    } else {
+                        start_index += PAGE_SIZE;
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "get_pagination_all_users_score_info_from_mysql error :{}",
+                        err.to_string()
+                    );
+                    return false;
+                }
+            }
+        }
+        match self
+            .rank_repo
+            .replace_rank_with_redis_rebuild_key(&table_config.appid, &table_config.rank_key)
+            .await
+        {
+            Ok(_) => {
+                tracing::info!(
+                    "rebuild redis rank success, appid:{} | rank_key:{}",
+                    table_config.appid,
+                    table_config.rank_key
+                );
+                self.apply_bans(table_config).await
+            }
+            Err(err) => {
+                tracing::error!("replace redis rank error :{}", err.to_string());
+                false
+            }
+        }
+    }
+
     /// 从数据库读取排行榜然后写入redis
     pub async fn read_mysql_rank_write_to_redis(&self, table_config: &RankTableConfig) -> bool {
         const PAGE_SIZE: u64 = 100;
@@ -202,23 +353,25 @@ impl RankConfigService {
                 .await
             {
                 Ok(user_list) => {
-                    for user_info in &user_list {
-                        let user_score_info = UpdateScoreRequest {
-                            appid: table_config.appid.clone(),
-                            openid: user_info.openid.clone(),
-                            rank_key: table_config.rank_key.clone(),
-                            nick_name: user_info.nick_name.clone(),
-                            score: user_info.score,
-                        };
+                    let page_len = user_list.len();
+                    let user_list = match self.skip_trimmed_users(table_config, user_list).await {
+                        Some(user_list) => user_list,
+                        None => return false,
+                    };
+                    for user_score_info in &user_list {
                         match self
                             .rank_repo
-                            .update_rank_score_to_redis(&user_score_info)
+                            .update_rank_score_to_redis(
+                                user_score_info,
+                                &UpdatePolicy::Replace,
+                                table_config,
+                            )
                             .await
                         {
                             Ok(_) => {
                                 match self
                                     .rank_repo
-                                    .update_user_info_to_redis(&user_score_info)
+                                    .update_user_info_to_redis(user_score_info)
                                     .await
                                 {
                                     Ok(_) => {}
@@ -238,7 +391,7 @@ impl RankConfigService {
                             }
                         }
                     }
-                    if (user_list.len() as u64) < PAGE_SIZE {
+                    if (page_len as u64) < PAGE_SIZE {
                         break;
                     } else {
                         start_index += PAGE_SIZE;
@@ -253,11 +406,137 @@ impl RankConfigService {
                 }
             }
         }
+        return self.apply_bans(table_config).await;
+    }
+
+    /// 从mysql加载排行榜时跳过被裁剪的玩家，没有开启 prune_mysql 时他们的分数仍然保留在mysql中。
+    /// 查询失败时返回 None
+    async fn skip_trimmed_users(
+        &self,
+        table_config: &RankTableConfig,
+        user_list: Vec<UserScoreInfo>,
+    ) -> Option<Vec<UserScoreInfo>> {
+        if table_config.max_entries <= 0 || user_list.is_empty() {
+            return Some(user_list);
+        }
+        let openids = user_list
+            .iter()
+            .map(|user_score| user_score.openid.clone())
+            .collect();
+        match self
+            .rank_repo
+            .get_trimmed_users_from_redis(&table_config.appid, &table_config.rank_key, &openids)
+            .await
+        {
+            Ok(trimmed) => Some(
+                user_list
+                    .into_iter()
+                    .zip(trimmed)
+                    .filter(|(_, trimmed)| !*trimmed)
+                    .map(|(user_score, _)| user_score)
+                    .collect(),
+            ),
+            Err(err) => {
+                tracing::error!(
+                    "get trimmed users from redis error, appid:{} | rank_key:{} | error:{}",
+                    table_config.appid,
+                    table_config.rank_key,
+                    err.to_string()
+                );
+                None
+            }
+        }
+    }
+
+    /// 把排行榜中被封禁的玩家移到封禁的排行榜
+    async fn apply_bans(&self, table_config: &RankTableConfig) -> bool {
+        match self.rank_repo.apply_bans_to_redis(table_config).await {
+            Ok(_) => true,
+            Err(err) => {
+                tracing::error!(
+                    "apply bans to redis error, appid:{} | rank_key:{} | error:{}",
+                    table_config.appid,
+                    table_config.rank_key,
+                    err.to_string()
+                );
+                false
+            }
+        }
+    }
+
+    /// 从数据库读取封禁然后写入redis
+    pub async fn read_mysql_ban_write_to_redis(&self) -> bool {
+        const PAGE_SIZE: u64 = 500;
+        let mut start_index = 0;
+        loop {
+            match self
+                .rank_repo
+                .get_pagination_bans_from_mysql(start_index, PAGE_SIZE)
+                .await
+            {
+                Ok(bans) => {
+                    if let Err(err) = self.rank_repo.add_bans_to_redis(&bans).await {
+                        tracing::error!("add bans to redis error :{}", err.to_string());
+                        return false;
+                    }
+                    if (bans.len() as u64) < PAGE_SIZE {
+                        break;
+                    } else {
+                        start_index += PAGE_SIZE;
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("get_pagination_bans_from_mysql error :{}", err.to_string());
+                    return false;
+                }
+            }
+        }
+        return true;
+    }
+
+    /// 从数据库读取队伍成员然后写入redis
+    pub async fn read_mysql_team_member_write_to_redis(&self) -> bool {
+        const PAGE_SIZE: u64 = 500;
+        let mut start_index = 0;
+        loop {
+            match self
+                .rank_repo
+                .get_pagination_team_members_from_mysql(start_index, PAGE_SIZE)
+                .await
+            {
+                Ok(team_members) => {
+                    if let Err(err) = self
+                        .rank_repo
+                        .add_team_members_to_redis(&team_members)
+                        .await
+                    {
+                        tracing::error!("add team members to redis error :{}", err.to_string());
+                        return false;
+                    }
+                    if (team_members.len() as u64) < PAGE_SIZE {
+                        break;
+                    } else {
+                        start_index += PAGE_SIZE;
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "get_pagination_team_members_from_mysql error :{}",
+                        err.to_string()
+                    );
+                    return false;
+                }
+            }
+        }
         return true;
     }
 
     // 开启排行榜定时任务
     pub async fn start_rank_cron_job(&self, rank_table_config: &RankTableConfig) -> Option<Uuid> {
+        // 滚动排行榜
+        if rank_table_config.is_rolling_window() {
+            return self.start_rank_window_job(rank_table_config).await;
+        }
         // 永久
         if rank_table_config.cron_expression.len() == 0 {
             return Some(Uuid::default());
@@ -305,108 +584,675 @@ impl RankConfigService {
         };
     }
 
-    // 清理排行榜
-    pub async fn clear_rank_data(&self, appid: String, rank_key: String) {
-        match self
-            .rank_repo
-            .clear_all_users_score_info_from_mysql(&appid, &rank_key)
-            .await
-        {
-            Ok(_) => {
-                tracing::info!(
-                    "clear_rank_data mysql success, appid:{} | rank_key:{}",
-                    appid,
-                    rank_key
-                );
-            }
+    /// 开启滚动排行榜的合并任务
+    ///
+    /// 每分钟检查一次，进入新的分桶时合并时间窗口内的分桶并删除过期的分桶
+    pub async fn start_rank_window_job(&self, rank_table_config: &RankTableConfig) -> Option<Uuid> {
+        let n_rank_service = self.clone();
+        let appid = rank_table_config.appid.clone();
+        let rank_key = rank_table_config.rank_key.clone();
+        // 上次合并的分桶
+        let merged_index = Arc::new(AtomicI64::new(i64::MIN));
+        let job = match Job::new_repeated_async(Duration::from_secs(60), move |_uuid, mut _l| {
+            let n_n_rank_service = n_rank_service.clone();
+            let n_appid = appid.clone();
+            let n_rank_key = rank_key.clone();
+            let n_merged_index = Arc::clone(&merged_index);
+            Box::pin(async move {
+                n_n_rank_service
+                    .merge_rank_window(n_appid, n_rank_key, n_merged_index)
+                    .await;
+                ()
+            })
+        }) {
+            Ok(job) => job,
             Err(err) => {
-                tracing::info!(
-                    "clear_rank_data mysql error, appid:{} | rank_key:{} | error:{}",
-                    appid,
-                    rank_key,
+                tracing::error!(
+                    "start_rank_window_job job, appid:{} | rank_key:{} | error:{}",
+                    rank_table_config.appid,
+                    rank_table_config.rank_key,
                     err.to_string()
                 );
+                return Option::None;
             }
-        }
-        match self
-            .rank_repo
-            .clear_all_users_score_info_from_redis(&appid, &rank_key)
-            .await
-        {
-            Ok(_) => {
-                tracing::info!(
-                    "clear_rank_data redis success, appid:{} | rank_key{}",
-                    appid,
-                    rank_key
-                );
+        };
+        match self.sched.add(job).await {
+            Ok(uuid) => {
+                return Some(uuid);
             }
             Err(err) => {
-                tracing::info!(
-                    "clear_rank_data redis error, appid:{} | rank_key{} | error:{}",
-                    appid,
-                    rank_key,
+                tracing::error!(
+                    "start_rank_window_job add job, appid:{} | rank_key:{} | error:{}",
+                    rank_table_config.appid,
+                    rank_table_config.rank_key,
                     err.to_string()
                 );
+                return Option::None;
             }
-        }
+        };
     }
 
-	/// 添加排行榜配置
-    pub async fn add_rank_config(&self, payload: &AddRankConfigReq) -> Result<(), ApiError> {
-        // 校验计划任务表达式是否正确
-        use cron::Schedule;
-        use std::str::FromStr;
-        if let Err(_) = Schedule::from_str(&payload.cron_expression) {
-            tracing::error!(
-                "add_rank_config - cron expression is not valid:{}",
-                payload.cron_expression
-            );
-            Err(RequestError::CommonError(
-                "cron expression is not valid".to_string(),
-            ))?
-        }
-
-        // 校验秘钥是否与现在的 appid 秘钥对应
-        {
-            let secret_map = self.rank_config_secret_map.read().await;
-            if secret_map.contains_key(&payload.appid) {
-                if secret_map[&payload.appid] != payload.app_secret {
-                    return Err(RequestError::CommonError(
-                        "appid and app secret is mismatching ".to_string(),
-                    ))?;
-                }
+    /// 开启裁剪排行榜人数的任务，每分钟检查一次所有限制了人数的排行榜
+    pub async fn start_rank_trim_job(&self) -> Option<Uuid> {
+        let n_rank_service = self.clone();
+        let job = match Job::new_repeated_async(Duration::from_secs(60), move |_uuid, mut _l| {
+            let n_n_rank_service = n_rank_service.clone();
+            Box::pin(async move {
+                n_n_rank_service.trim_rank_entries().await;
+                ()
+            })
+        }) {
+            Ok(job) => job,
+            Err(err) => {
+                tracing::error!("start_rank_trim_job job error:{}", err.to_string());
+                return Option::None;
             }
-        }
-
-        // 校验配置是否已经存在
-        {
-            let guard = self.rank_table_configs.lock().unwrap();
-            for rank_tbl_config in &(*guard) {
-                if rank_tbl_config.appid == payload.appid
-                    && rank_tbl_config.rank_key == payload.rank_key
-                {
-                    return Err(RequestError::CommonError(
-                        "rank config has exist!! ".to_string(),
-                    ))?;
-                }
+        };
+        match self.sched.add(job).await {
+            Ok(uuid) => {
+                return Some(uuid);
             }
-        }
-
-        match self.rank_repo.add_rank_config_to_mysql(payload).await {
-            Ok(_) => {}
-            Err(sqlx::Error::Database(err)) => match err.code() {
-                Some(code) => {
-                    if code == "1062" {
-                        Err(DbError::UniqueConstraintViolation(err.to_string()))?
-                    } else {
-                        Err(DbError::SomethingWentWrong(err.to_string()))?
-                    }
-                }
-                _ => Err(DbError::SomethingWentWrong(err.to_string()))?,
-            },
             Err(err) => {
-                tracing::error!("add_rank_config mysql error :{}", err.to_string());
-                Err(DbError::SomethingWentWrong(err.to_string()))?
+                tracing::error!("start_rank_trim_job add job error:{}", err.to_string());
+                return Option::None;
+            }
+        };
+    }
+
+    /// 开启解除过期封禁的任务，每分钟检查一次
+    pub async fn start_rank_ban_expire_job(&self) -> Option<Uuid> {
+        let n_rank_service = self.clone();
+        let job = match Job::new_repeated_async(Duration::from_secs(60), move |_uuid, mut _l| {
+            let n_n_rank_service = n_rank_service.clone();
+            Box::pin(async move {
+                n_n_rank_service.expire_bans().await;
+                ()
+            })
+        }) {
+            Ok(job) => job,
+            Err(err) => {
+                tracing::error!("start_rank_ban_expire_job job error:{}", err.to_string());
+                return Option::None;
+            }
+        };
+        match self.sched.add(job).await {
+            Ok(uuid) => {
+                return Some(uuid);
+            }
+            Err(err) => {
+                tracing::error!(
+                    "start_rank_ban_expire_job add job error:{}",
+                    err.to_string()
+                );
+                return Option::None;
+            }
+        };
+    }
+
+    /// 开启重新计算组合排行榜的任务，每10分钟用来源排行榜重新计算一次所有组合排行榜
+    ///
+    /// 修正增量更新组合分数时失败或遗漏(如来源排行榜裁剪人数)造成的不一致
+    pub async fn start_rank_composite_merge_job(&self) -> Option<Uuid> {
+        let n_rank_service = self.clone();
+        let job = match Job::new_repeated_async(Duration::from_secs(600), move |_uuid, mut _l| {
+            let n_n_rank_service = n_rank_service.clone();
+            Box::pin(async move {
+                let composites = {
+                    let guard = n_n_rank_service.rank_table_configs.lock().unwrap();
+                    get_composite_source_weights(&guard, |_| true)
+                };
+                n_n_rank_service.merge_composite_rank(composites).await;
+                ()
+            })
+        }) {
+            Ok(job) => job,
+            Err(err) => {
+                tracing::error!(
+                    "start_rank_composite_merge_job job error:{}",
+                    err.to_string()
+                );
+                return Option::None;
+            }
+        };
+        match self.sched.add(job).await {
+            Ok(uuid) => {
+                return Some(uuid);
+            }
+            Err(err) => {
+                tracing::error!(
+                    "start_rank_composite_merge_job add job error:{}",
+                    err.to_string()
+                );
+                return Option::None;
+            }
+        };
+    }
+
+    /// 解除过期的封禁，每次最多处理 BATCH_SIZE 个，剩下的下次处理
+    pub async fn expire_bans(&self) {
+        const BATCH_SIZE: u64 = 1000;
+        let now = chrono::Utc::now().timestamp();
+        let bans = match self
+            .rank_repo
+            .get_expired_bans_from_mysql(now, BATCH_SIZE)
+            .await
+        {
+            Ok(bans) => bans,
+            Err(err) => {
+                tracing::error!("expire_bans mysql error:{}", err.to_string());
+                return;
+            }
+        };
+        for ban in bans {
+            match self
+                .rank_repo
+                .delete_expired_ban_from_mysql(&ban.appid, &ban.openid, now)
+                .await
+            {
+                // 已经被重新封禁
+                Ok(0) => continue,
+                Ok(_) => {}
+                Err(err) => {
+                    tracing::error!(
+                        "expire_bans mysql error, appid:{} | openid:{} | error:{}",
+                        ban.appid,
+                        ban.openid,
+                        err.to_string()
+                    );
+                    continue;
+                }
+            }
+            match self.unban_user_in_redis(&ban.appid, &ban.openid).await {
+                Ok(_) => {
+                    tracing::info!(
+                        "expire_bans success, appid:{} | openid:{}",
+                        ban.appid,
+                        ban.openid
+                    );
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "expire_bans redis error, appid:{} | openid:{} | error:{}",
+                        ban.appid,
+                        ban.openid,
+                        err.to_string()
+                    );
+                }
+            }
+        }
+    }
+
+    /// 裁剪所有限制了人数的排行榜
+    ///
+    /// 分批从redis中移除排名最靠后的玩家，避免长时间阻塞redis，开启 prune_mysql 时同时删除mysql中的分数
+    pub async fn trim_rank_entries(&self) {
+        const BATCH_SIZE: i64 = 1000;
+        let rank_table_configs: Vec<RankTableConfig> = {
+            let guard = self.rank_table_configs.lock().unwrap();
+            guard
+                .iter()
+                .filter(|config| config.max_entries > 0)
+                .cloned()
+                .collect()
+        };
+        for rank_table_config in rank_table_configs {
+            let composites = {
+                let guard = self.rank_table_configs.lock().unwrap();
+                get_composite_source_weights(&guard, |config| {
+                    config.appid == rank_table_config.appid
+                        && config
+                            .composite_sources
+                            .0
+                            .iter()
+                            .any(|source| source.rank_key == rank_table_config.rank_key)
+                })
+            };
+            let mut trimmed = 0;
+            loop {
+                let openids = match self
+                    .rank_repo
+                    .trim_rank_entries_from_redis(&rank_table_config, BATCH_SIZE)
+                    .await
+                {
+                    Ok(openids) => openids,
+                    Err(err) => {
+                        tracing::error!(
+                            "trim_rank_entries redis error, appid:{} | rank_key:{} | error:{}",
+                            rank_table_config.appid,
+                            rank_table_config.rank_key,
+                            err.to_string()
+                        );
+                        break;
+                    }
+                };
+                if rank_table_config.prune_mysql {
+                    if let Err(err) = self
+                        .rank_repo
+                        .delete_users_score_info_from_mysql(
+                            &rank_table_config.appid,
+                            &rank_table_config.rank_key,
+                            &openids,
+                        )
+                        .await
+                    {
+                        tracing::error!(
+                            "trim_rank_entries mysql error, appid:{} | rank_key:{} | error:{}",
+                            rank_table_config.appid,
+                            rank_table_config.rank_key,
+                            err.to_string()
+                        );
+                    }
+                }
+                // 重新计算被移除的玩家在引用这个排行榜的组合排行榜上的分数
+                let user_composites: Vec<(String, String, Vec<(String, f64)>)> = composites
+                    .iter()
+                    .flat_map(|(composite, source_weights)| {
+                        openids.iter().map(|openid| {
+                            (
+                                openid.clone(),
+                                composite.rank_key.clone(),
+                                source_weights.clone(),
+                            )
+                        })
+                    })
+                    .collect();
+                if let Err(err) = self
+                    .rank_repo
+                    .update_composite_score_to_redis(&rank_table_config.appid, &user_composites)
+                    .await
+                {
+                    tracing::error!(
+                        "trim_rank_entries composite error, appid:{} | rank_key:{} | error:{}",
+                        rank_table_config.appid,
+                        rank_table_config.rank_key,
+                        err.to_string()
+                    );
+                }
+                trimmed += openids.len();
+                if (openids.len() as i64) < BATCH_SIZE {
+                    break;
+                }
+            }
+            if trimmed > 0 {
+                tracing::info!(
+                    "trim_rank_entries success, appid:{} | rank_key:{} | trimmed:{}",
+                    rank_table_config.appid,
+                    rank_table_config.rank_key,
+                    trimmed
+                );
+            }
+        }
+    }
+
+    /// 合并滚动排行榜，当前分桶已经合并过时跳过
+    pub async fn merge_rank_window(
+        &self,
+        appid: String,
+        rank_key: String,
+        merged_index: Arc<AtomicI64>,
+    ) {
+        let rank_table_config = {
+            let guard = self.rank_table_configs.lock().unwrap();
+            guard
+                .iter()
+                .find(|config| config.appid == appid && config.rank_key == rank_key)
+                .cloned()
+        };
+        let rank_table_config = match rank_table_config {
+            Some(config) => config,
+            None => return,
+        };
+        let now = chrono::Utc::now().timestamp();
+        let index = rank_table_config.window_bucket_index(now);
+        if merged_index.load(Ordering::Relaxed) == index {
+            return;
+        }
+        match self
+            .rank_repo
+            .merge_rank_window_to_redis(&rank_table_config, now)
+            .await
+        {
+            Ok(_) => {
+                merged_index.store(index, Ordering::Relaxed);
+                tracing::info!(
+                    "merge_rank_window success, appid:{} | rank_key:{} | bucket:{}",
+                    appid,
+                    rank_key,
+                    index
+                );
+            }
+            Err(err) => {
+                tracing::error!(
+                    "merge_rank_window redis error, appid:{} | rank_key:{} | error:{}",
+                    appid,
+                    rank_key,
+                    err.to_string()
+                );
+            }
+        }
+    }
+
+    /// 按来源排行榜的权重重新计算组合排行榜，失败时只记录日志
+    async fn merge_composite_rank(&self, composites: Vec<(RankTableConfig, Vec<(String, f64)>)>) {
+        for (rank_table_config, source_weights) in composites {
+            match self
+                .rank_repo
+                .merge_composite_rank_to_redis(&rank_table_config, &source_weights)
+                .await
+            {
+                Ok(_) => {
+                    tracing::info!(
+                        "merge_composite_rank success, appid:{} | rank_key:{}",
+                        rank_table_config.appid,
+                        rank_table_config.rank_key
+                    );
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "merge_composite_rank redis error, appid:{} | rank_key:{} | error:{}",
+                        rank_table_config.appid,
+                        rank_table_config.rank_key,
+                        err.to_string()
+                    );
+                }
+            }
+        }
+    }
+
+    // 清理排行榜
+    pub async fn clear_rank_data(&self, appid: String, rank_key: String) {
+        // 清理前先归档，归档失败时不清理，避免上一期的排名丢失
+        let rank_table_config = {
+            let guard = self.rank_table_configs.lock().unwrap();
+            guard
+                .iter()
+                .find(|config| config.appid == appid && config.rank_key == rank_key)
+                .cloned()
+        };
+        if let Some(rank_table_config) = &rank_table_config {
+            match self
+                .rank_repo
+                .archive_rank_to_mysql(&rank_table_config, chrono::Utc::now().timestamp())
+                .await
+            {
+                Ok(period_id) => {
+                    tracing::info!(
+                        "clear_rank_data archive success, appid:{} | rank_key:{} | period_id:{}",
+                        appid,
+                        rank_key,
+                        period_id
+                    );
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "clear_rank_data archive error, appid:{} | rank_key:{} | error:{}",
+                        appid,
+                        rank_key,
+                        err.to_string()
+                    );
+                    return;
+                }
+            }
+        }
+        match self
+            .rank_repo
+            .clear_all_users_score_info_from_mysql(&appid, &rank_key)
+            .await
+        {
+            Ok(_) => {
+                tracing::info!(
+                    "clear_rank_data mysql success, appid:{} | rank_key:{}",
+                    appid,
+                    rank_key
+                );
+            }
+            Err(err) => {
+                tracing::info!(
+                    "clear_rank_data mysql error, appid:{} | rank_key:{} | error:{}",
+                    appid,
+                    rank_key,
+                    err.to_string()
+                );
+            }
+        }
+        match self
+            .rank_repo
+            .clear_all_users_score_info_from_redis(&appid, &rank_key)
+            .await
+        {
+            Ok(_) => {
+                tracing::info!(
+                    "clear_rank_data redis success, appid:{} | rank_key{}",
+                    appid,
+                    rank_key
+                );
+            }
+            Err(err) => {
+                tracing::info!(
+                    "clear_rank_data redis error, appid:{} | rank_key{} | error:{}",
+                    appid,
+                    rank_key,
+                    err.to_string()
+                );
+            }
+        }
+        // 清理完成后再进入下一个赛季，避免新赛季的分数被清理
+        if rank_table_config.is_some() {
+            self.increase_rank_season(&appid, &rank_key).await;
+        }
+        // 来源排行榜清理后重新计算引用它的组合排行榜
+        let composites = {
+            let guard = self.rank_table_configs.lock().unwrap();
+            get_composite_source_weights(&guard, |config| {
+                config.appid == appid
+                    && config
+                        .composite_sources
+                        .0
+                        .iter()
+                        .any(|source| source.rank_key == rank_key)
+            })
+        };
+        self.merge_composite_rank(composites).await;
+    }
+
+    /// 排行榜进入下一个赛季
+    ///
+    /// 在清理完成后调用，先更新内存中的赛季，尽快拒绝上一个赛季的分数，从节点通过配置更新时间同步
+    async fn increase_rank_season(&self, appid: &String, rank_key: &String) {
+        {
+            let mut guard = self.rank_table_configs.lock().unwrap();
+            if let Some(config) = guard
+                .iter_mut()
+                .find(|config| config.appid == *appid && config.rank_key == *rank_key)
+            {
+                config.season += 1;
+            }
+        }
+        self.config_update_time.store(
+            chrono::Utc::now().timestamp_millis() as u64,
+            Ordering::Relaxed,
+        );
+        match self
+            .rank_repo
+            .increase_rank_season_to_mysql(appid, rank_key)
+            .await
+        {
+            Ok(_) => {}
+            Err(err) => {
+                tracing::error!(
+                    "increase_rank_season mysql error, appid:{} | rank_key:{} | error:{}",
+                    appid,
+                    rank_key,
+                    err.to_string()
+                );
+            }
+        }
+    }
+
+    /// 添加排行榜配置
+    pub async fn add_rank_config(&self, payload: &AddRankConfigReq) -> Result<(), ApiError> {
+        // 校验计划任务表达式是否正确
+        use cron::Schedule;
+        use std::str::FromStr;
+        if payload.window_seconds > 0 {
+            // 滚动排行榜按分桶过期，不需要计划任务清理
+            if !payload.cron_expression.is_empty() {
+                Err(RequestError::CommonError(
+                    "rolling window rank does not support cron_expression".to_string(),
+                ))?
+            }
+            if !payload.reward_brackets.is_empty() {
+                Err(RequestError::CommonError(
+                    "rolling window rank does not support reward_brackets".to_string(),
+                ))?
+            }
+            if matches!(payload.update_policy, UpdatePolicy::Replace) {
+                Err(RequestError::CommonError(
+                    "rolling window rank does not support replace update_policy".to_string(),
+                ))?
+            }
+            // 合并分桶时不会重新计算队伍分数
+            if payload.team_aggregate != TeamAggregate::Disabled {
+                Err(RequestError::CommonError(
+                    "rolling window rank does not support team_aggregate".to_string(),
+                ))?
+            }
+            if payload.bucket_seconds < MIN_WINDOW_BUCKET_SECONDS
+                || payload.window_seconds % payload.bucket_seconds != 0
+                || payload.window_seconds / payload.bucket_seconds > MAX_WINDOW_BUCKETS
+            {
+                Err(RequestError::CommonError(format!(
+                    "bucket_seconds must be at least {} and divide window_seconds into at most {} buckets",
+                    MIN_WINDOW_BUCKET_SECONDS, MAX_WINDOW_BUCKETS
+                )))?
+            }
+        } else if payload.composite_sources.is_empty()
+            && Schedule::from_str(&payload.cron_expression).is_err()
+        {
+            tracing::error!(
+                "add_rank_config - cron expression is not valid:{}",
+                payload.cron_expression
+            );
+            Err(RequestError::CommonError(
+                "cron expression is not valid".to_string(),
+            ))?
+        }
+
+        // 校验秘钥是否与现在的 appid 秘钥对应
+        {
+            let secret_map = self.rank_config_secret_map.read().await;
+            if secret_map.contains_key(&payload.appid) {
+                if secret_map[&payload.appid] != payload.app_secret {
+                    return Err(RequestError::CommonError(
+                        "appid and app secret is mismatching ".to_string(),
+                    ))?;
+                }
+            }
+        }
+
+        // 组合排行榜由来源排行榜计算，不接受提交分数
+        if !payload.composite_sources.is_empty() {
+            if !payload.cron_expression.is_empty()
+                || payload.window_seconds > 0
+                || !payload.reward_brackets.is_empty()
+                || payload.team_aggregate != TeamAggregate::Disabled
+                || !payload.board_group.is_empty()
+            {
+                Err(RequestError::CommonError(
+                    "composite rank does not support cron_expression, window_seconds, reward_brackets, team_aggregate or board_group".to_string(),
+                ))?
+            }
+            if payload.cheat_max_score.is_some()
+                || payload.cheat_max_increase.is_some()
+                || payload.cheat_max_per_minute > 0
+                || payload.cheat_min_interval > 0
+                || payload.cheat_action != CheatAction::Reject
+                || payload.review_top_ratio != 0.0
+            {
+                Err(RequestError::CommonError(
+                    "composite rank does not support anti cheat rules".to_string(),
+                ))?
+            }
+            let guard = self.rank_table_configs.lock().unwrap();
+            for (index, source) in payload.composite_sources.iter().enumerate() {
+                if !source.weight.is_finite() || source.weight <= 0.0 || source.weight > 1_000_000.0
+                {
+                    Err(RequestError::CommonError(
+                        "composite source weight must be between 0 and 1000000".to_string(),
+                    ))?
+                }
+                if payload.composite_sources[..index]
+                    .iter()
+                    .any(|prev| prev.rank_key == source.rank_key)
+                {
+                    Err(RequestError::CommonError(format!(
+                        "composite source {} is duplicated",
+                        source.rank_key
+                    )))?
+                }
+                // 来源排行榜需要已经存在，滚动排行榜和组合排行榜不能作为来源
+                match guard.iter().find(|config| {
+                    config.appid == payload.appid && config.rank_key == source.rank_key
+                }) {
+                    // 权重都是正数，排序方式不同的来源排行榜相加后好的分数会互相抵消
+                    Some(config) if config.sort_order != payload.sort_order => {
+                        Err(RequestError::CommonError(format!(
+                            "composite source {} must have the same sort_order",
+                            source.rank_key
+                        )))?
+                    }
+                    Some(config) if !config.is_rolling_window() && !config.is_composite() => {}
+                    _ => Err(RequestError::CommonError(format!(
+                        "composite source {} is not exist or is not supported",
+                        source.rank_key
+                    )))?,
+                }
+            }
+        }
+
+        // 限制人数的排行榜由后台任务裁剪
+        if payload.max_entries > 0 {
+            if payload.window_seconds > 0 || payload.team_aggregate != TeamAggregate::Disabled {
+                Err(RequestError::CommonError(
+                    "rolling window rank and team rank do not support max_entries".to_string(),
+                ))?
+            }
+            if payload.max_entries > MAX_RANK_ENTRIES {
+                Err(RequestError::CommonError(format!(
+                    "max_entries must not exceed {}",
+                    MAX_RANK_ENTRIES
+                )))?
+            }
+        }
+
+        // 校验队伍排行榜统计的成员数量
+        if payload.team_aggregate == TeamAggregate::TopKSum
+            && (payload.team_top_k < 1 || payload.team_top_k > MAX_TEAM_TOP_K)
+        {
+            Err(RequestError::CommonError(format!(
+                "team_top_k must be between 1 and {} for top_k_sum team_aggregate",
+                MAX_TEAM_TOP_K
+            )))?
+        }
+
+        // 同分排序规则区分先后时redis中不会出现相同的分数，并列排名没有意义
+        if payload.rank_style != RankStyle::Ordinal && payload.tie_policy != TiePolicy::Shared {
+            Err(RequestError::CommonError(
+                "competition and dense rank_style require shared tie_policy".to_string(),
+            ))?
+        }
+
+        // 校验配置是否已经存在
+        {
+            let guard = self.rank_table_configs.lock().unwrap();
+            for rank_tbl_config in &(*guard) {
+                if rank_tbl_config.appid == payload.appid
+                    && rank_tbl_config.rank_key == payload.rank_key
+                {
+                    return Err(RequestError::CommonError(
+                        "rank config has exist!! ".to_string(),
+                    ))?;
+                }
             }
         }
 
@@ -415,30 +1261,172 @@ impl RankConfigService {
             app_secret: payload.app_secret.clone(),
             rank_key: payload.rank_key.clone(),
             cron_expression: payload.cron_expression.clone(),
+            update_policy: payload.update_policy,
+            sort_order: payload.sort_order,
+            max_page_size: payload.max_page_size,
+            score_type: payload.score_type,
+            score_precision: match payload.score_type {
+                ScoreType::Integer => 0,
+                ScoreType::Decimal => payload.score_precision,
+            },
+            min_score: DEFAULT_MIN_SCORE,
+            max_score: DEFAULT_MAX_SCORE,
+            tie_policy: payload.tie_policy,
+            rank_style: payload.rank_style,
+            season: 1,
+            reward_brackets: RewardBrackets(payload.reward_brackets.clone()),
+            board_group: payload.board_group.clone(),
+            window_seconds: payload.window_seconds,
+            bucket_seconds: match payload.window_seconds {
+                0 => 0,
+                _ => payload.bucket_seconds,
+            },
+            team_aggregate: payload.team_aggregate,
+            team_top_k: match payload.team_aggregate {
+                TeamAggregate::TopKSum => payload.team_top_k,
+                _ => 0,
+            },
+            composite_sources: CompositeSources(payload.composite_sources.clone()),
+            max_entries: payload.max_entries,
+            prune_mysql: payload.max_entries > 0 && payload.prune_mysql,
+            cheat_max_score: None,
+            cheat_max_increase: None,
+            cheat_max_per_minute: payload.cheat_max_per_minute,
+            cheat_min_interval: payload.cheat_min_interval,
+            cheat_action: payload.cheat_action,
+            review_top_ratio: payload.review_top_ratio,
             cron_uuid: String::new(),
         };
 
+        // 校验奖励档位
+        for bracket in &rank_table_config.reward_brackets.0 {
+            if let Err(err) = bracket.check() {
+                Err(RequestError::CommonError(err))?
+            }
+        }
+        if rank_table_config.reward_brackets.to_json().len() > 2048 {
+            Err(RequestError::CommonError(
+                "reward_brackets is too long".to_string(),
+            ))?
+        }
+
+        // 校验分数范围，按精度放大后存储
+        if let Some(min_score) = payload.min_score {
+            match rank_table_config.to_stored_score(min_score) {
+                Some(min_score) => rank_table_config.min_score = min_score,
+                None => Err(RequestError::CommonError(
+                    "min_score is not valid".to_string(),
+                ))?,
+            }
+        }
+        match payload.max_score {
+            Some(max_score) => match rank_table_config.to_stored_score(max_score) {
+                Some(max_score) => rank_table_config.max_score = max_score,
+                None => Err(RequestError::CommonError(
+                    "max_score is not valid".to_string(),
+                ))?,
+            },
+            None => {
+                rank_table_config.max_score = rank_table_config
+                    .to_stored_score(DEFAULT_MAX_SCORE as f64)
+                    .unwrap_or(DEFAULT_MAX_SCORE)
+            }
+        }
+        if rank_table_config.min_score > rank_table_config.max_score {
+            Err(RequestError::CommonError(
+                "min_score must not be greater than max_score".to_string(),
+            ))?
+        }
+        // 分数范围需要能在redis中无损编码
+        if rank_table_config.max_score - rank_table_config.min_score > MAX_SAFE_SCORE {
+            Err(RequestError::CommonError(
+                "score range is too large".to_string(),
+            ))?
+        }
+        // 校验防作弊规则，按精度放大后存储
+        if let Some(max_score) = payload.cheat_max_score {
+            match rank_table_config.to_stored_score(max_score) {
+                Some(max_score) => rank_table_config.cheat_max_score = Some(max_score),
+                None => Err(RequestError::CommonError(
+                    "cheat_max_score is not valid".to_string(),
+                ))?,
+            }
+        }
+        if let Some(max_increase) = payload.cheat_max_increase {
+            match rank_table_config.to_stored_score(max_increase) {
+                Some(max_increase) if max_increase > 0 => {
+                    rank_table_config.cheat_max_increase = Some(max_increase)
+                }
+                _ => Err(RequestError::CommonError(
+                    "cheat_max_increase must be greater than 0".to_string(),
+                ))?,
+            }
+        }
+        if payload.review_top_ratio != 0.0
+            && !(payload.review_top_ratio > 1.0 && payload.review_top_ratio <= 1000.0)
+        {
+            Err(RequestError::CommonError(
+                "review_top_ratio must be 0 or between 1 and 1000".to_string(),
+            ))?
+        }
+        // 组合排行榜的分数范围由来源排行榜决定
+        if rank_table_config.is_composite() {
+            rank_table_config.min_score = 0;
+            rank_table_config.max_score = MAX_SAFE_SCORE;
+        }
+
+        match self
+            .rank_repo
+            .add_rank_config_to_mysql(payload, &rank_table_config)
+            .await
+        {
+            Ok(_) => {}
+            Err(sqlx::Error::Database(err)) => match err.code() {
+                Some(code) => {
+                    if code == "1062" {
+                        Err(DbError::UniqueConstraintViolation(err.to_string()))?
+                    } else {
+                        Err(DbError::SomethingWentWrong(err.to_string()))?
+                    }
+                }
+                _ => Err(DbError::SomethingWentWrong(err.to_string()))?,
+            },
+            Err(err) => {
+                tracing::error!("add_rank_config mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+
         // 开启定时任务
         if let Some(uuid) = self.start_rank_cron_job(&rank_table_config).await {
             if !uuid.is_nil() {
                 tracing::info!("add_rank_config - uuid:{}", uuid.to_string());
                 rank_table_config.cron_uuid = uuid.to_string();
             }
-			{
-				let mut guard = self.rank_table_configs.lock().unwrap();
-				let rank_table_configs: &mut Vec<RankTableConfig> = guard.as_mut();
-				rank_table_configs.push(rank_table_config.clone());
-			}
-			// 添加秘钥映射
-			{
-				let mut secret_map = self.rank_config_secret_map.write().await;
-				if (*secret_map).contains_key(&rank_table_config.appid) {
-					secret_map.insert(rank_table_config.appid, rank_table_config.app_secret);
-				} else {
-					secret_map.insert(rank_table_config.appid, rank_table_config.app_secret);
-				}
-			}
-			self.config_update_time.store(chrono::Utc::now().timestamp_millis() as u64,Ordering::Relaxed);
+            let composites = {
+                let mut guard = self.rank_table_configs.lock().unwrap();
+                let rank_table_configs: &mut Vec<RankTableConfig> = guard.as_mut();
+                rank_table_configs.push(rank_table_config.clone());
+                get_composite_source_weights(rank_table_configs, |config| {
+                    config.appid == rank_table_config.appid
+                        && config.rank_key == rank_table_config.rank_key
+                })
+            };
+            // 组合排行榜按来源排行榜已有的分数计算一次
+            self.merge_composite_rank(composites).await;
+            // 添加秘钥映射
+            {
+                let mut secret_map = self.rank_config_secret_map.write().await;
+                if (*secret_map).contains_key(&rank_table_config.appid) {
+                    secret_map.insert(rank_table_config.appid, rank_table_config.app_secret);
+                } else {
+                    secret_map.insert(rank_table_config.appid, rank_table_config.app_secret);
+                }
+            }
+            self.config_update_time.store(
+                chrono::Utc::now().timestamp_millis() as u64,
+                Ordering::Relaxed,
+            );
             Ok(())
         } else {
             tracing::error!(
@@ -451,7 +1439,7 @@ impl RankConfigService {
         }
     }
 
-	/// 删除排行榜配置
+    /// 删除排行榜配置
     pub async fn delete_rank_config(
         &self,
         appid: &String,
@@ -488,10 +1476,24 @@ impl RankConfigService {
                 );
                 return Err("rank config is not exist".to_string());
             }
+            // 被组合排行榜引用的排行榜需要先删除组合排行榜
+            if guard.iter().any(|config| {
+                config.appid == *appid
+                    && config
+                        .composite_sources
+                        .0
+                        .iter()
+                        .any(|source| source.rank_key == *rank_key)
+            }) {
+                return Err("rank config is used by composite rank".to_string());
+            }
             let rank_table_configs: &mut Vec<RankTableConfig> = guard.as_mut();
             config = rank_table_configs.remove(wait_delete_index);
         }
-		self.config_update_time.store(chrono::Utc::now().timestamp_millis() as u64,Ordering::Relaxed);
+        self.config_update_time.store(
+            chrono::Utc::now().timestamp_millis() as u64,
+            Ordering::Relaxed,
+        );
 
         // 如果没有这个appid的所有排行榜就删除密钥映射
         {
@@ -521,10 +1523,27 @@ impl RankConfigService {
         }
 
         // 先清理 数据库
-        self.clear_rank_data(config.appid, config.rank_key).await;
+        self.clear_rank_data(config.appid.clone(), config.rank_key.clone())
+            .await;
+        if config.is_rolling_window() {
+            if let Err(e) = self
+                .rank_repo
+                .clear_rank_window_from_redis(&config, chrono::Utc::now().timestamp())
+                .await
+            {
+                tracing::error!(
+                    "delete_rank_config - redis clear window err:{} | appid:{} | rank_key:{}",
+                    e.to_string(),
+                    appid,
+                    rank_key
+                );
+            }
+        }
 
         // 判断是否有定时任务
-        if !config.cron_expression.is_empty() && config.cron_uuid.len() > 0 {
+        if (!config.cron_expression.is_empty() || config.is_rolling_window())
+            && config.cron_uuid.len() > 0
+        {
             tracing::info!("delete_rank_config - cancel sched");
             if let Ok(uuid) = Uuid::parse_str(config.cron_uuid.as_str()) {
                 match self.sched.remove(&uuid).await {
@@ -552,7 +1571,482 @@ impl RankConfigService {
         Ok(())
     }
 
-	/// 从主节点中获取到的新排行榜更新配置
+    /// 分页获取玩家在排行榜上的分数提交历史，最新的在前
+    pub async fn get_score_history(
+        &self,
+        payload: &ScoreHistoryReq,
+    ) -> Result<Vec<ScoreHistoryRes>, ApiError> {
+        let config = {
+            let guard = self.rank_table_configs.lock().unwrap();
+            guard
+                .iter()
+                .find(|config| config.appid == payload.appid && config.rank_key == payload.rank_key)
+                .cloned()
+        };
+        let config = match config {
+            Some(config) => config,
+            None => Err(RankError::RankConfigNotFound)?,
+        };
+        match self
+            .rank_repo
+            .get_score_history_from_mysql(
+                &payload.appid,
+                &payload.rank_key,
+                &payload.openid,
+                payload.offset as u64,
+                payload.limit as u64,
+            )
+            .await
+        {
+            Ok(histories) => Ok(histories
+                .into_iter()
+                .map(|history| ScoreHistoryRes {
+                    id: history.id,
+                    old_score: history
+                        .old_score
+                        .map(|score| config.to_display_score(score)),
+                    new_score: config.to_display_score(history.new_score),
+                    submitted_score: config.to_display_score(history.submitted_score),
+                    source_ip: history.source_ip,
+                    request_id: history.request_id,
+                    create_time: history.create_time,
+                })
+                .collect()),
+            Err(err) => {
+                tracing::error!("get score history from mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 封禁玩家，对appid下所有的排行榜生效
+    ///
+    /// 把玩家移到每个排行榜对应的封禁的排行榜，重新计算所在队伍和组合排行榜的分数。
+    /// 重复封禁时更新封禁方式、原因和过期时间
+    pub async fn ban_user(&self, payload: &BanUserReq) -> Result<(), ApiError> {
+        let now = chrono::Utc::now().timestamp();
+        if payload.expire_time != 0 && payload.expire_time <= now {
+            Err(RequestError::CommonError(
+                "expire_time must be 0 or later than now".to_string(),
+            ))?
+        }
+        let (configs, composites) = self.get_ban_rank_configs(&payload.appid);
+        if configs.is_empty() {
+            Err(RankError::RankConfigNotFound)?
+        }
+        let ban = RankBan {
+            appid: payload.appid.clone(),
+            openid: payload.openid.clone(),
+            ban_type: payload.ban_type,
+            reason: payload.reason.clone(),
+            expire_time: payload.expire_time,
+            create_time: now,
+        };
+        if let Err(err) = self.rank_repo.update_ban_to_mysql(&ban).await {
+            tracing::error!("update ban to mysql error :{}", err.to_string());
+            Err(DbError::SomethingWentWrong(err.to_string()))?
+        }
+        if let Err(err) = self.rank_repo.ban_user_to_redis(&ban, &configs).await {
+            tracing::error!("ban user to redis error :{}", err.to_string());
+            Err(DbError::SomethingWentWrong(err.to_string()))?
+        }
+        self.update_ban_composite_score(&payload.appid, &payload.openid, composites)
+            .await
+    }
+
+    /// 解除封禁，把玩家移回每个排行榜
+    pub async fn unban_user(&self, payload: &UnbanUserReq) -> Result<(), ApiError> {
+        match self
+            .rank_repo
+            .delete_ban_from_mysql(&payload.appid, &payload.openid)
+            .await
+        {
+            Ok(0) => Err(RankError::BanNotFound)?,
+            Ok(_) => {
+                self.unban_user_in_redis(&payload.appid, &payload.openid)
+                    .await
+            }
+            Err(err) => {
+                tracing::error!("delete ban from mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 分页获取appid下的封禁，最新的在前
+    pub async fn get_bans(&self, payload: &BanListReq) -> Result<Vec<BanRes>, ApiError> {
+        match self
+            .rank_repo
+            .get_app_bans_from_mysql(&payload.appid, payload.offset as u64, payload.limit as u64)
+            .await
+        {
+            Ok(bans) => Ok(bans
+                .into_iter()
+                .map(|ban| BanRes {
+                    openid: ban.openid,
+                    ban_type: ban.ban_type,
+                    reason: ban.reason,
+                    expire_time: ban.expire_time,
+                    create_time: ban.create_time,
+                })
+                .collect()),
+            Err(err) => {
+                tracing::error!("get bans from mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 分页获取appid下违反防作弊规则的提交，最新的在前
+    pub async fn get_cheat_violations(
+        &self,
+        payload: &CheatViolationListReq,
+    ) -> Result<Vec<CheatViolationRes>, ApiError> {
+        let violations = match self
+            .rank_repo
+            .get_cheat_violations_from_mysql(
+                &payload.appid,
+                &payload.openid,
+                payload.offset as u64,
+                payload.limit as u64,
+            )
+            .await
+        {
+            Ok(violations) => violations,
+            Err(err) => {
+                tracing::error!("get cheat violations from mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+        // 分数按排行榜的精度显示，排行榜已经删除时显示存储的分数
+        let configs: HashMap<String, RankTableConfig> = {
+            let guard = self.rank_table_configs.lock().unwrap();
+            guard
+                .iter()
+                .filter(|config| config.appid == payload.appid)
+                .map(|config| (config.rank_key.clone(), config.clone()))
+                .collect()
+        };
+        let to_display_score = |rank_key: &String, score: i64| match configs.get(rank_key) {
+            Some(config) => config.to_display_score(score),
+            None => serde_json::Number::from(score),
+        };
+        Ok(violations
+            .into_iter()
+            .map(|violation| CheatViolationRes {
+                id: violation.id,
+                score: to_display_score(&violation.rank_key, violation.score),
+                current_score: violation
+                    .current_score
+                    .map(|score| to_display_score(&violation.rank_key, score)),
+                rank_key: violation.rank_key,
+                openid: violation.openid,
+                rule: violation.rule,
+                source_ip: violation.source_ip,
+                request_id: violation.request_id,
+                create_time: violation.create_time,
+            })
+            .collect())
+    }
+
+    /// 从redis解除封禁，mysql中的封禁已经删除
+    async fn unban_user_in_redis(&self, appid: &String, openid: &String) -> Result<(), ApiError> {
+        let (configs, composites) = self.get_ban_rank_configs(appid);
+        if let Err(err) = self
+            .rank_repo
+            .unban_user_to_redis(appid, openid, &configs)
+            .await
+        {
+            tracing::error!("unban user to redis error :{}", err.to_string());
+            Err(DbError::SomethingWentWrong(err.to_string()))?
+        }
+        self.update_ban_composite_score(appid, openid, composites)
+            .await
+    }
+
+    /// 封禁影响的排行榜：appid下的普通排行榜，以及按来源排行榜重新计算的组合排行榜
+    fn get_ban_rank_configs(
+        &self,
+        appid: &String,
+    ) -> (
+        Vec<RankTableConfig>,
+        Vec<(RankTableConfig, Vec<(String, f64)>)>,
+    ) {
+        let guard = self.rank_table_configs.lock().unwrap();
+        let configs = guard
+            .iter()
+            .filter(|config| config.appid == *appid && !config.is_composite())
+            .cloned()
+            .collect();
+        let composites = get_composite_source_weights(&guard, |config| config.appid == *appid);
+        (configs, composites)
+    }
+
+    /// 封禁、解除封禁后重新计算玩家在组合排行榜上的分数
+    async fn update_ban_composite_score(
+        &self,
+        appid: &String,
+        openid: &String,
+        composites: Vec<(RankTableConfig, Vec<(String, f64)>)>,
+    ) -> Result<(), ApiError> {
+        let user_composites = composites
+            .into_iter()
+            .map(|(composite, source_weights)| (openid.clone(), composite.rank_key, source_weights))
+            .collect();
+        if let Err(err) = self
+            .rank_repo
+            .update_composite_score_to_redis(appid, &user_composites)
+            .await
+        {
+            tracing::error!("update composite score to redis error :{}", err.to_string());
+            Err(DbError::SomethingWentWrong(err.to_string()))?
+        }
+        Ok(())
+    }
+
+    /// 从排行榜中移除玩家
+    ///
+    /// 删除mysql中的分数和redis中的排名、分区，拒绝等待审核的分数，重新计算所在队伍和组合排行榜的分数。
+    /// 玩家的昵称、队伍和分数提交历史保留
+    pub async fn remove_user(&self, payload: &RemoveUserReq) -> Result<RemovedRankRes, ApiError> {
+        let (config, composites) = {
+            let guard = self.rank_table_configs.lock().unwrap();
+            let config = guard
+                .iter()
+                .find(|config| config.appid == payload.appid && config.rank_key == payload.rank_key)
+                .cloned();
+            let composites = get_composite_source_weights(&guard, |config| {
+                config.appid == payload.appid
+                    && config
+                        .composite_sources
+                        .0
+                        .iter()
+                        .any(|source| source.rank_key == payload.rank_key)
+            });
+            (config, composites)
+        };
+        let config = match config {
+            Some(config) if config.is_composite() => Err(RankError::CompositeRankReadOnly)?,
+            Some(config) => config,
+            None => Err(RankError::RankConfigNotFound)?,
+        };
+
+        // 先拒绝等待审核的分数，避免移除后审核通过又写回排行榜
+        if let Err(err) = self
+            .rank_repo
+            .reject_user_score_reviews_to_mysql(
+                &payload.appid,
+                &payload.rank_key,
+                &payload.openid,
+                chrono::Utc::now().timestamp(),
+            )
+            .await
+        {
+            tracing::error!(
+                "reject user score reviews to mysql error :{}",
+                err.to_string()
+            );
+            Err(DbError::SomethingWentWrong(err.to_string()))?
+        }
+        let mysql_rows = match self
+            .rank_repo
+            .delete_users_score_info_from_mysql(
+                &payload.appid,
+                &payload.rank_key,
+                &vec![payload.openid.clone()],
+            )
+            .await
+        {
+            Ok(rows) => rows,
+            Err(err) => {
+                tracing::error!("delete user score from mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+        let removed = match self
+            .rank_repo
+            .remove_user_from_redis(
+                &payload.appid,
+                &payload.openid,
+                &vec![config],
+                chrono::Utc::now().timestamp(),
+            )
+            .await
+        {
+            Ok(removed) => removed,
+            Err(err) => {
+                tracing::error!("remove user from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+        // 玩家可能还在其他来源排行榜上，按剩余的分数重新计算
+        let user_composites = composites
+            .into_iter()
+            .map(|(composite, source_weights)| {
+                (payload.openid.clone(), composite.rank_key, source_weights)
+            })
+            .collect();
+        if let Err(err) = self
+            .rank_repo
+            .update_composite_score_to_redis(&payload.appid, &user_composites)
+            .await
+        {
+            tracing::error!("update composite score to redis error :{}", err.to_string());
+            Err(DbError::SomethingWentWrong(err.to_string()))?
+        }
+        Ok(RemovedRankRes {
+            rank_key: payload.rank_key.clone(),
+            redis: removed.into_iter().any(|removed| removed),
+            mysql_rows,
+        })
+    }
+
+    /// 从appid下所有的排行榜中删除玩家
+    ///
+    /// 删除mysql中的分数、队伍、分数提交历史、归档排名、奖励结算、违规记录和可疑分数审核，
+    /// redis中的排名、分区、队伍和昵称。封禁保留
+    pub async fn erase_user(&self, payload: &EraseUserReq) -> Result<EraseUserRes, ApiError> {
+        let configs: Vec<RankTableConfig> = {
+            let guard = self.rank_table_configs.lock().unwrap();
+            guard
+                .iter()
+                .filter(|config| config.appid == payload.appid)
+                .cloned()
+                .collect()
+        };
+        if configs.is_empty() {
+            Err(RankError::RankConfigNotFound)?
+        }
+
+        // 归档排名、奖励结算、违规记录和审核中的分数也是玩家的个人数据。
+        // 先于分数删除，避免删除过程中审核通过的分数又写回排行榜
+        let (archive_entry_rows, reward_settlement_rows, cheat_violation_rows, score_review_rows) =
+            match self
+                .rank_repo
+                .delete_user_records_from_mysql(&payload.appid, &payload.openid)
+                .await
+            {
+                Ok(rows) => rows,
+                Err(err) => {
+                    tracing::error!("delete user records from mysql error :{}", err.to_string());
+                    Err(DbError::SomethingWentWrong(err.to_string()))?
+                }
+            };
+
+        let mut mysql_rows = vec![];
+        for config in &configs {
+            // 组合排行榜的分数只保存在redis中
+            if config.is_composite() {
+                mysql_rows.push(0);
+                continue;
+            }
+            match self
+                .rank_repo
+                .delete_users_score_info_from_mysql(
+                    &payload.appid,
+                    &config.rank_key,
+                    &vec![payload.openid.clone()],
+                )
+                .await
+            {
+                Ok(rows) => mysql_rows.push(rows),
+                Err(err) => {
+                    tracing::error!(
+                        "delete user score from mysql error, rank_key:{} | error:{}",
+                        config.rank_key,
+                        err.to_string()
+                    );
+                    Err(DbError::SomethingWentWrong(err.to_string()))?
+                }
+            }
+        }
+        let team_member_rows = match self
+            .rank_repo
+            .delete_team_member_from_mysql(&payload.appid, &payload.openid)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(err) => {
+                tracing::error!("delete team member from mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+        let score_history_rows = match self
+            .rank_repo
+            .delete_score_history_from_mysql(&payload.appid, &payload.openid)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(err) => {
+                tracing::error!("delete score history from mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+        // 组合排行榜与来源排行榜一起移除，不需要重新计算
+        let removed = match self
+            .rank_repo
+            .remove_user_from_redis(
+                &payload.appid,
+                &payload.openid,
+                &configs,
+                chrono::Utc::now().timestamp(),
+            )
+            .await
+        {
+            Ok(removed) => removed,
+            Err(err) => {
+                tracing::error!("remove user from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+        let team_configs: Vec<RankTableConfig> = configs
+            .iter()
+            .filter(|config| config.is_team_board())
+            .cloned()
+            .collect();
+        if let Err(err) = self
+            .rank_repo
+            .update_team_member_to_redis(&payload.appid, &payload.openid, &None, 0, &team_configs)
+            .await
+        {
+            tracing::error!("update team member to redis error :{}", err.to_string());
+            Err(DbError::SomethingWentWrong(err.to_string()))?
+        }
+        let user_info = match self
+            .rank_repo
+            .delete_user_info_from_redis(&payload.appid, &payload.openid)
+            .await
+        {
+            Ok(user_info) => user_info,
+            Err(err) => {
+                tracing::error!("delete user info from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+
+        Ok(EraseUserRes {
+            ranks: configs
+                .into_iter()
+                .zip(mysql_rows)
+                .zip(removed)
+                .filter(|((_, mysql_rows), redis)| *redis || *mysql_rows > 0)
+                .map(|((config, mysql_rows), redis)| RemovedRankRes {
+                    rank_key: config.rank_key,
+                    redis,
+                    mysql_rows,
+                })
+                .collect(),
+            user_info,
+            team_member: team_member_rows > 0,
+            score_history_rows,
+            archive_entry_rows,
+            reward_settlement_rows,
+            cheat_violation_rows,
+            score_review_rows,
+        })
+    }
+
+    /// 从主节点中获取到的新排行榜更新配置
     pub async fn update_rank_config_from_master_server(
         &self,
         update_time: u64,
@@ -573,9 +2067,13 @@ impl RankConfigService {
             let mut configs_guard = self.rank_table_configs.lock().unwrap();
             configs_guard.clear();
             *configs_guard = rank_tbl_configs;
-			tracing::info!("update_rank_config_from_master_server new : {:?}", *configs_guard);
+            tracing::info!(
+                "update_rank_config_from_master_server new : {:?}",
+                *configs_guard
+            );
         }
-		self.config_update_time.store(update_time,Ordering::Relaxed);
+        self.config_update_time
+            .store(update_time, Ordering::Relaxed);
     }
     /// ---更新计划任务id
     fn update_sched_uuid(&self, uuid: &String, appid: &String, rank_key: &String) {
@@ -589,6 +2087,21 @@ impl RankConfigService {
     }
 }
 
+/// 获取需要重新计算的组合排行榜和每个来源排行榜在redis中的权重
+fn get_composite_source_weights<F>(
+    configs: &[RankTableConfig],
+    filter: F,
+) -> Vec<(RankTableConfig, Vec<(String, f64)>)>
+where
+    F: Fn(&RankTableConfig) -> bool,
+{
+    configs
+        .iter()
+        .filter(|config| config.is_composite() && filter(config))
+        .map(|config| (config.clone(), config.composite_source_weights(configs)))
+        .collect()
+}
+
 /// 主节点更新排行榜的GRPC server
 pub struct UpdateRankConfigGrpcServer {
     rank_config_service: Arc<RankConfigService>,
@@ -611,7 +2124,10 @@ impl update_rank_config::update_rank_config_server::UpdateRankConfig
         request: tonic::Request<update_rank_config::UpdataTimeRequest>,
     ) -> std::result::Result<tonic::Response<update_rank_config::UpdateTimeResponse>, tonic::Status>
     {
-        tracing::debug!("get_last_update_time - Got a request from {:?}", request.remote_addr());
+        tracing::debug!(
+            "get_last_update_time - Got a request from {:?}",
+            request.remote_addr()
+        );
 
         let a = self
             .rank_config_service
@@ -629,7 +2145,10 @@ impl update_rank_config::update_rank_config_server::UpdateRankConfig
     {
         let mut msg = update_rank_config::UpdataConfigResponse::default();
         // let list = msg.rank_table_configs.as_mut();
-        tracing::debug!("get_rank_table_config - Got a request from {:?}", request.remote_addr());
+        tracing::debug!(
+            "get_rank_table_config - Got a request from {:?}",
+            request.remote_addr()
+        );
         msg.update_time = self
             .rank_config_service
             .config_update_time
@@ -644,6 +2163,31 @@ impl update_rank_config::update_rank_config_server::UpdateRankConfig
                         app_secret: config.app_secret.clone(),
                         rank_key: config.rank_key.clone(),
                         cron_expression: config.cron_expression.clone(),
+                        update_policy: config.update_policy.as_str().to_string(),
+                        sort_order: config.sort_order.as_str().to_string(),
+                        max_page_size: config.max_page_size,
+                        score_type: config.score_type.as_str().to_string(),
+                        score_precision: config.score_precision,
+                        min_score: config.min_score,
+                        max_score: config.max_score,
+                        tie_policy: config.tie_policy.as_str().to_string(),
+                        rank_style: config.rank_style.as_str().to_string(),
+                        season: config.season,
+                        reward_brackets: config.reward_brackets.to_json(),
+                        window_seconds: config.window_seconds,
+                        bucket_seconds: config.bucket_seconds,
+                        board_group: config.board_group.clone(),
+                        team_aggregate: config.team_aggregate.as_str().to_string(),
+                        team_top_k: config.team_top_k,
+                        composite_sources: config.composite_sources.to_json(),
+                        max_entries: config.max_entries,
+                        prune_mysql: config.prune_mysql,
+                        cheat_max_score: config.cheat_max_score,
+                        cheat_max_increase: config.cheat_max_increase,
+                        cheat_max_per_minute: config.cheat_max_per_minute,
+                        cheat_min_interval: config.cheat_min_interval,
+                        cheat_action: config.cheat_action.as_str().to_string(),
+                        review_top_ratio: config.review_top_ratio,
                     });
             }
         }
@@ -713,6 +2257,36 @@ impl UpdateRankConfigGrpcClient {
                         app_secret: config.app_secret,
                         rank_key: config.rank_key,
                         cron_expression: config.cron_expression,
+                        update_policy: UpdatePolicy::try_from(config.update_policy)
+                            .unwrap_or_default(),
+                        sort_order: SortOrder::try_from(config.sort_order).unwrap_or_default(),
+                        max_page_size: config.max_page_size,
+                        score_type: ScoreType::try_from(config.score_type).unwrap_or_default(),
+                        score_precision: config.score_precision,
+                        min_score: config.min_score,
+                        max_score: config.max_score,
+                        tie_policy: TiePolicy::try_from(config.tie_policy).unwrap_or_default(),
+                        rank_style: RankStyle::try_from(config.rank_style).unwrap_or_default(),
+                        season: config.season,
+                        reward_brackets: RewardBrackets::try_from(config.reward_brackets)
+                            .unwrap_or_default(),
+                        window_seconds: config.window_seconds,
+                        bucket_seconds: config.bucket_seconds,
+                        board_group: config.board_group,
+                        team_aggregate: TeamAggregate::try_from(config.team_aggregate)
+                            .unwrap_or_default(),
+                        team_top_k: config.team_top_k,
+                        composite_sources: CompositeSources::try_from(config.composite_sources)
+                            .unwrap_or_default(),
+                        max_entries: config.max_entries,
+                        prune_mysql: config.prune_mysql,
+                        cheat_max_score: config.cheat_max_score,
+                        cheat_max_increase: config.cheat_max_increase,
+                        cheat_max_per_minute: config.cheat_max_per_minute,
+                        cheat_min_interval: config.cheat_min_interval,
+                        cheat_action: CheatAction::try_from(config.cheat_action)
+                            .unwrap_or_default(),
+                        review_top_ratio: config.review_top_ratio,
                         cron_uuid: String::default(),
                     })
                 }
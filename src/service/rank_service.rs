@@ -1,11 +1,24 @@
 use crate::db::database::Database;
-use crate::dto::rank_dto::{UpdateScoreRequest, UserScoreRes};
+use crate::dto::rank_dto::{
+    AroundUserRes, BatchUpdateScoreItemRes, BatchUpdateScoreReq, BulkUserRankRes, FriendRankRes,
+    JoinTeamReq, LeaveTeamReq, RankArchivePeriodRes, RankPartitionRes, RankStatsRes,
+    RewardSettlementRes, ScoreRequestSource, ScoreReviewListReq, ScoreReviewReq, ScoreReviewRes,
+    TeamMemberRes, TeamScoreRes, UpdateGroupScoreReq, UpdateScoreRequest, UpdateScoreRes,
+    UserScoreRes,
+};
 use crate::error::api_error::ApiError;
 use crate::error::db_error::DbError;
-use crate::model::user::RankTableConfig;
+use crate::error::rank_error::RankError;
+use crate::model::user::{
+    AntiCheatRule, BanType, CheatAction, RankCheatViolation, RankScoreReview, RankStyle,
+    RankTableConfig, RankTeamMember, ReviewStatus, UpdatePolicy, UserScoreInfo, MAX_TEAM_SIZE,
+    REVIEW_REASON_OUTLIER,
+};
 use crate::repository::rank_repository::{RankRepository, RankRepositoryTrait};
 use deadpool_redis::Pool;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
+use validator::Validate;
 
 #[derive(Clone)]
 pub struct RankService {
@@ -32,146 +45,2019 @@ impl RankService {
         Ok("ok".to_string())
     }
 
-    pub async fn update_rank_score(&self, payload: UpdateScoreRequest) -> Result<(), ApiError> {
+    /// 获取排行榜配置
+    pub fn get_rank_table_config(
+        &self,
+        appid: &String,
+        rank_key: &String,
+    ) -> Result<RankTableConfig, ApiError> {
+        let guard = self.rank_table_configs.lock().unwrap();
+        match guard
+            .iter()
+            .find(|config| config.appid == *appid && config.rank_key == *rank_key)
+        {
+            Some(config) => Ok(config.clone()),
+            None => Err(RankError::RankConfigNotFound)?,
+        }
+    }
+
+    /// 获取排行榜当前的赛季
+    pub fn get_rank_season(&self, appid: &String, rank_key: &String) -> Result<i32, ApiError> {
+        Ok(self.get_rank_table_config(appid, rank_key)?.season)
+    }
+
+    /// 校验赛季和分数范围，并转换为存储的用户分数
+    fn to_user_score_info(
+        &self,
+        payload: &UpdateScoreRequest,
+        rank_table_config: &RankTableConfig,
+    ) -> Result<UserScoreInfo, RankError> {
+        if rank_table_config.is_composite() {
+            return Err(RankError::CompositeRankReadOnly);
+        }
+        if let Some(season) = payload.season {
+            if season != rank_table_config.season {
+                return Err(RankError::SeasonMismatch(rank_table_config.season));
+            }
+        }
+        if let Some(partition) = &payload.partition {
+            // 滚动排行榜合并分桶时不会同步分区
+            if rank_table_config.is_rolling_window() {
+                return Err(RankError::InvalidPartition(
+                    "rolling window rank does not support partition".to_string(),
+                ));
+            }
+            if !partition
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(RankError::InvalidPartition(
+                    "partition only supports letters, digits, '_' and '-'".to_string(),
+                ));
+            }
+        }
+        match rank_table_config.to_stored_score(payload.score) {
+            Some(score) if rank_table_config.is_score_in_bounds(score) => Ok(UserScoreInfo {
+                appid: payload.appid.clone(),
+                rank_key: payload.rank_key.clone(),
+                openid: payload.openid.clone(),
+                nick_name: payload.nick_name.clone(),
+                score,
+                update_time: chrono::Utc::now().timestamp(),
+                partition: payload.partition.clone().unwrap_or_default(),
+            }),
+            _ => Err(RankError::InvalidScore(format!(
+                "score must be a {} between {} and {}",
+                rank_table_config.score_type.as_str(),
+                rank_table_config.to_display_score(rank_table_config.min_score),
+                rank_table_config.to_display_score(rank_table_config.max_score)
+            ))),
+        }
+    }
+
+    /// 更新玩家分数，返回校验分数时使用的赛季和分数是否进入审核队列
+    pub async fn update_rank_score(
+        &self,
+        payload: UpdateScoreRequest,
+        source: &ScoreRequestSource,
+    ) -> Result<(i32, UpdateScoreRes), ApiError> {
+        let rank_table_config = self.get_rank_table_config(&payload.appid, &payload.rank_key)?;
+        let user_score = self.to_user_score_info(&payload, &rank_table_config)?;
+        let ban_types = self
+            .get_ban_types(&user_score.appid, &vec![user_score.openid.clone()])
+            .await?;
+        let ban_type = ban_types.get(&user_score.openid).copied();
+        if ban_type == Some(BanType::Ban) {
+            Err(RankError::UserBanned)?
+        }
+        // 进入审核队列的分数审核通过后才写入排行榜
+        if self
+            .check_anti_cheat(&vec![(&user_score, &rank_table_config)], &ban_types, source)
+            .await?
+            .remove(0)?
+        {
+            return Ok((
+                rank_table_config.season,
+                UpdateScoreRes {
+                    pending_review: true,
+                },
+            ));
+        }
+        if let Err(err) = self
+            .write_rank_score(&user_score, &rank_table_config, ban_type, source)
+            .await
+        {
+            self.refund_anti_cheat_rate(&vec![(&user_score, &rank_table_config)])
+                .await;
+            Err(err)?
+        }
+        Ok((
+            rank_table_config.season,
+            UpdateScoreRes {
+                pending_review: false,
+            },
+        ))
+    }
+
+    /// 把校验后的分数写入mysql和redis，影子封禁的玩家只写入封禁的排行榜
+    async fn write_rank_score(
+        &self,
+        user_score: &UserScoreInfo,
+        rank_table_config: &RankTableConfig,
+        ban_type: Option<BanType>,
+        source: &ScoreRequestSource,
+    ) -> Result<(), ApiError> {
         // 更新到mysql
-        match self.rank_repo.update_rank_score_to_mysql(&payload).await {
-            // 更新到redis
-            Ok(_) => match self.rank_repo.update_rank_score_to_redis(&payload).await {
-                Ok(_) => match self.rank_repo.update_user_info_to_redis(&payload).await {
-                    Ok(_) => Ok(()),
+        match self
+            .rank_repo
+            .update_rank_score_to_mysql(user_score, rank_table_config, source)
+            .await
+        {
+            Ok(_) => {
+                self.write_rank_score_to_redis(user_score, rank_table_config, ban_type)
+                    .await
+            }
+            Err(err) => {
+                tracing::error!("update score to mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 把已经写入mysql的分数写入redis，影子封禁的玩家只写入封禁的排行榜
+    async fn write_rank_score_to_redis(
+        &self,
+        user_score: &UserScoreInfo,
+        rank_table_config: &RankTableConfig,
+        ban_type: Option<BanType>,
+    ) -> Result<(), ApiError> {
+        // 影子封禁的玩家只写入封禁的排行榜
+        if ban_type == Some(BanType::Shadow) {
+            return match self
+                .rank_repo
+                .update_shadow_score_to_redis(&vec![(user_score, rank_table_config)])
+                .await
+            {
+                Ok(_) => Ok(()),
+                Err(err) => {
+                    tracing::error!("update shadow score to redis error :{}", err.to_string());
+                    Err(DbError::SomethingWentWrong(err.to_string()))?
+                }
+            };
+        }
+        // 被裁剪的玩家按mysql中更新后的分数重新上榜
+        let restored = self
+            .get_restored_trimmed_scores(rank_table_config, &vec![user_score])
+            .await?
+            .remove(&user_score.openid);
+        let (user_score, update_policy) = match &restored {
+            Some(restored) => (restored, &UpdatePolicy::Replace),
+            None => (user_score, &rank_table_config.update_policy),
+        };
+        // 更新到redis
+        match self
+            .rank_repo
+            .update_rank_score_to_redis(user_score, update_policy, rank_table_config)
+            .await
+        {
+            Ok(_) => match self.rank_repo.update_user_info_to_redis(user_score).await {
+                Ok(_) => {
+                    self.update_composite_score(
+                        &user_score.appid,
+                        &vec![(user_score.openid.clone(), user_score.rank_key.clone())],
+                    )
+                    .await;
+                    Ok(())
+                }
+                Err(err) => {
+                    tracing::error!("update user info to redis error :{}", err.to_string());
+                    Err(DbError::SomethingWentWrong(err.to_string()))?
+                }
+            },
+            Err(err) => {
+                // TODO@ 当更新排行榜出错后需要重试，或者记录错误信息到本地
+                tracing::error!("update score to redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 批量更新分数
+    ///
+    /// 每个排行榜一次mysql批量写入，所有排行榜一次redis pipeline写入，返回每一项的结果
+    pub async fn batch_update_rank_score(
+        &self,
+        payload: BatchUpdateScoreReq,
+        source: &ScoreRequestSource,
+    ) -> Result<Vec<BatchUpdateScoreItemRes>, ApiError> {
+        let mut res: Vec<BatchUpdateScoreItemRes> = payload
+            .items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| BatchUpdateScoreItemRes {
+                index,
+                rank_key: item.rank_key.clone(),
+                openid: item.openid.clone(),
+                success: false,
+                pending_review: false,
+                msg: None,
+                season: None,
+            })
+            .collect();
+
+        let mut openids: Vec<String> = payload
+            .items
+            .iter()
+            .map(|item| item.openid.clone())
+            .collect();
+        openids.sort();
+        openids.dedup();
+        let ban_types = self.get_ban_types(&payload.appid, &openids).await?;
+
+        // 校验每一项并按排行榜分组
+        let mut groups: HashMap<String, (RankTableConfig, Vec<usize>, Vec<UserScoreInfo>)> =
+            HashMap::new();
+        for (index, item) in payload.items.iter().enumerate() {
+            if item.appid != payload.appid {
+                res[index].msg = Some("appid is mismatching".to_string());
+                continue;
+            }
+            if let Err(err) = item.validate() {
+                res[index].msg = Some(err.to_string().replace('\n', ", "));
+                continue;
+            }
+            if !groups.contains_key(&item.rank_key) {
+                match self.get_rank_table_config(&item.appid, &item.rank_key) {
+                    Ok(rank_table_config) => {
+                        groups.insert(item.rank_key.clone(), (rank_table_config, vec![], vec![]));
+                    }
                     Err(err) => {
-                        tracing::error!("update user info to redis error :{}", err.to_string());
-                        Err(DbError::SomethingWentWrong(err.to_string()))?
+                        res[index].msg = Some(err.to_string());
+                        continue;
                     }
-                },
+                }
+            }
+            if let Some((rank_table_config, indexes, user_scores)) = groups.get_mut(&item.rank_key)
+            {
+                res[index].season = Some(rank_table_config.season);
+                match self.to_user_score_info(item, rank_table_config) {
+                    Ok(_) if ban_types.get(&item.openid) == Some(&BanType::Ban) => {
+                        res[index].msg = Some(RankError::UserBanned.to_string())
+                    }
+                    Ok(user_score) => {
+                        indexes.push(index);
+                        user_scores.push(user_score);
+                    }
+                    Err(err) => res[index].msg = Some(err.to_string()),
+                }
+            }
+        }
+
+        // 防作弊校验，违反规则和进入审核队列的项不更新
+        let mut cheat_indexes = vec![];
+        let mut cheat_payloads = vec![];
+        for (rank_table_config, indexes, user_scores) in groups.values() {
+            for (index, user_score) in indexes.iter().zip(user_scores) {
+                cheat_indexes.push(*index);
+                cheat_payloads.push((user_score, rank_table_config));
+            }
+        }
+        let errs = self
+            .check_anti_cheat(&cheat_payloads, &ban_types, source)
+            .await?;
+        let mut rejected_indexes = HashSet::new();
+        for (index, result) in cheat_indexes.into_iter().zip(errs) {
+            match result {
+                Ok(false) => {}
+                // 进入审核队列的项视为提交成功，审核通过后才写入排行榜
+                Ok(true) => {
+                    res[index].success = true;
+                    res[index].pending_review = true;
+                    rejected_indexes.insert(index);
+                }
+                Err(err) => {
+                    res[index].msg = Some(err.to_string());
+                    rejected_indexes.insert(index);
+                }
+            }
+        }
+        if !rejected_indexes.is_empty() {
+            for (_, indexes, user_scores) in groups.values_mut() {
+                (*indexes, *user_scores) = indexes
+                    .drain(..)
+                    .zip(user_scores.drain(..))
+                    .filter(|(index, _)| !rejected_indexes.contains(index))
+                    .unzip();
+            }
+        }
+
+        // 更新到mysql
+        let mut redis_indexes = vec![];
+        let mut redis_payloads = vec![];
+        let mut shadow_indexes = vec![];
+        let mut shadow_payloads = vec![];
+        let mut restore_groups = vec![];
+        for (rank_key, (rank_table_config, indexes, user_scores)) in &groups {
+            if user_scores.is_empty() {
+                continue;
+            }
+            match self
+                .rank_repo
+                .batch_update_rank_score_to_mysql(
+                    &payload.appid,
+                    rank_key,
+                    user_scores,
+                    rank_table_config,
+                    source,
+                )
+                .await
+            {
+                Ok(_) => {
+                    let mut restore_indexes = vec![];
+                    let mut restore_scores = vec![];
+                    for (index, user_score) in indexes.iter().zip(user_scores) {
+                        // 影子封禁的玩家只写入封禁的排行榜
+                        if ban_types.get(&user_score.openid) == Some(&BanType::Shadow) {
+                            shadow_indexes.push(*index);
+                            shadow_payloads.push((user_score, rank_table_config));
+                        } else {
+                            restore_indexes.push(*index);
+                            restore_scores.push(user_score);
+                        }
+                    }
+                    restore_groups.push((rank_table_config, restore_indexes, restore_scores));
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "batch update score to mysql error, rank_key:{} | error:{}",
+                        rank_key,
+                        err.to_string()
+                    );
+                    for index in indexes {
+                        res[*index].msg = Some(err.to_string());
+                    }
+                    self.refund_anti_cheat_rate(
+                        &user_scores
+                            .iter()
+                            .map(|user_score| (user_score, rank_table_config))
+                            .collect(),
+                    )
+                    .await;
+                }
+            }
+        }
+
+        // 被裁剪的玩家按mysql中更新后的分数重新上榜
+        let mut restored_groups = vec![];
+        for (rank_table_config, indexes, user_scores) in &restore_groups {
+            match self
+                .get_restored_trimmed_scores(rank_table_config, user_scores)
+                .await
+            {
+                Ok(restored) => {
+                    let mut replace_config = (*rank_table_config).clone();
+                    replace_config.update_policy = UpdatePolicy::Replace;
+                    restored_groups.push((restored, replace_config));
+                }
+                Err(err) => {
+                    for index in indexes {
+                        res[*index].msg = Some(err.to_string());
+                    }
+                    restored_groups.push((HashMap::new(), (*rank_table_config).clone()));
+                }
+            }
+        }
+        for ((rank_table_config, indexes, user_scores), (restored, replace_config)) in
+            restore_groups.iter().zip(&restored_groups)
+        {
+            for (index, user_score) in indexes.iter().zip(user_scores) {
+                if res[*index].msg.is_some() {
+                    continue;
+                }
+                redis_indexes.push(*index);
+                match restored.get(&user_score.openid) {
+                    Some(restored) => redis_payloads.push((restored, replace_config)),
+                    None => redis_payloads.push((*user_score, *rank_table_config)),
+                }
+            }
+        }
+
+        // 更新到redis
+        match self
+            .rank_repo
+            .batch_update_rank_score_to_redis(&redis_payloads)
+            .await
+        {
+            Ok(_) => {
+                for index in redis_indexes {
+                    res[index].success = true;
+                }
+                let user_rank_keys = redis_payloads
+                    .iter()
+                    .map(|(user_score, _)| (user_score.openid.clone(), user_score.rank_key.clone()))
+                    .collect();
+                self.update_composite_score(&payload.appid, &user_rank_keys)
+                    .await;
+            }
+            Err(err) => {
+                // TODO@ 当更新排行榜出错后需要重试，或者记录错误信息到本地
+                tracing::error!("batch update score to redis error :{}", err.to_string());
+                for index in redis_indexes {
+                    res[index].msg = Some(err.to_string());
+                }
+            }
+        }
+        match self
+            .rank_repo
+            .update_shadow_score_to_redis(&shadow_payloads)
+            .await
+        {
+            Ok(_) => {
+                for index in shadow_indexes {
+                    res[index].success = true;
+                }
+            }
+            Err(err) => {
+                tracing::error!(
+                    "batch update shadow score to redis error :{}",
+                    err.to_string()
+                );
+                for index in shadow_indexes {
+                    res[index].msg = Some(err.to_string());
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    /// 获取这些玩家中因为超出保留人数被移出排行榜的玩家，排行榜没有限制人数时不查询
+    async fn get_trimmed_openids(
+        &self,
+        rank_table_config: &RankTableConfig,
+        openids: &Vec<String>,
+    ) -> Result<HashSet<String>, ApiError> {
+        if rank_table_config.max_entries <= 0 {
+            return Ok(HashSet::new());
+        }
+        match self
+            .rank_repo
+            .get_trimmed_users_from_redis(
+                &rank_table_config.appid,
+                &rank_table_config.rank_key,
+                openids,
+            )
+            .await
+        {
+            Ok(trimmed) => Ok(openids
+                .iter()
+                .zip(trimmed)
+                .filter(|(_, trimmed)| *trimmed)
+                .map(|(openid, _)| openid.clone())
+                .collect()),
+            Err(err) => {
+                tracing::error!("get trimmed users from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 被裁剪过的玩家再次提交分数时，获取mysql中更新后的分数，按 openid 返回
+    ///
+    /// 没有开启 prune_mysql 时被裁剪的玩家的分数保留在mysql中，redis中已经没有原来的分数，
+    /// 除覆盖策略外不能在redis中按更新策略写入，需要用mysql中更新后的分数覆盖
+    async fn get_restored_trimmed_scores(
+        &self,
+        rank_table_config: &RankTableConfig,
+        user_scores: &Vec<&UserScoreInfo>,
+    ) -> Result<HashMap<String, UserScoreInfo>, ApiError> {
+        let mut restored = HashMap::new();
+        if rank_table_config.prune_mysql
+            || rank_table_config.update_policy == UpdatePolicy::Replace
+            || rank_table_config.is_rolling_window()
+        {
+            return Ok(restored);
+        }
+        let openids = user_scores
+            .iter()
+            .map(|user_score| user_score.openid.clone())
+            .collect();
+        for openid in self
+            .get_trimmed_openids(rank_table_config, &openids)
+            .await?
+        {
+            match self
+                .rank_repo
+                .get_user_score_info_from_mysql(
+                    &rank_table_config.appid,
+                    &openid,
+                    &rank_table_config.rank_key,
+                )
+                .await
+            {
+                Ok(user_score) => {
+                    restored.insert(openid, user_score);
+                }
+                Err(err) => {
+                    tracing::error!("get score from mysql error :{}", err.to_string());
+                    Err(DbError::SomethingWentWrong(err.to_string()))?
+                }
+            }
+        }
+        Ok(restored)
+    }
+
+    /// 批量获取玩家的封禁方式，没有被封禁的玩家不返回
+    async fn get_ban_types(
+        &self,
+        appid: &String,
+        openids: &Vec<String>,
+    ) -> Result<HashMap<String, BanType>, ApiError> {
+        match self
+            .rank_repo
+            .get_ban_types_from_redis(appid, openids)
+            .await
+        {
+            Ok(ban_types) => Ok(openids
+                .iter()
+                .zip(ban_types)
+                .filter_map(|(openid, ban_type)| {
+                    ban_type.map(|ban_type| (openid.clone(), ban_type))
+                })
+                .collect()),
+            Err(err) => {
+                tracing::error!("get ban types from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 按排行榜的防作弊规则和离群阈值校验提交的分数，返回每一项是否进入审核队列，被拒绝时返回原因
+    ///
+    /// 违反规则的提交记录到mysql，记录失败时只打印日志。违反分数规则时按 cheat_action 拒绝或进入审核队列，
+    /// 超过离群阈值时进入审核队列；通过分数规则或进入审核队列的提交再在redis中校验并记录提交频率，
+    /// 违反频率规则总是拒绝。被拒绝的提交不计入提交频率，写入失败时调用方退还
+    async fn check_anti_cheat(
+        &self,
+        user_scores: &Vec<(&UserScoreInfo, &RankTableConfig)>,
+        ban_types: &HashMap<String, BanType>,
+        source: &ScoreRequestSource,
+    ) -> Result<Vec<Result<bool, RankError>>, ApiError> {
+        let mut errs: Vec<Result<bool, RankError>> =
+            user_scores.iter().map(|_| Ok(false)).collect();
+        let (indexes, checks): (Vec<usize>, Vec<(&UserScoreInfo, &RankTableConfig, bool)>) =
+            user_scores
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, rank_table_config))| rank_table_config.has_anti_cheat())
+                .map(|(index, (user_score, rank_table_config))| {
+                    let shadow = ban_types.get(&user_score.openid) == Some(&BanType::Shadow);
+                    (index, (*user_score, *rank_table_config, shadow))
+                })
+                .unzip();
+        if checks.is_empty() {
+            return Ok(errs);
+        }
+        let now = chrono::Utc::now().timestamp();
+        let results = match self
+            .rank_repo
+            .get_anti_cheat_scores_from_redis(&checks)
+            .await
+        {
+            Ok(results) => results,
+            Err(err) => {
+                tracing::error!(
+                    "get anti cheat scores from redis error :{}",
+                    err.to_string()
+                );
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+        // 先按分数规则校验，被拒绝的提交不记录提交频率
+        let mut decisions = vec![];
+        for ((index, (user_score, rank_table_config, _)), (score, top_score)) in
+            indexes.into_iter().zip(checks).zip(results)
+        {
+            let current_score = score.map(|score| rank_table_config.decode_redis_score(score));
+            let top_score = top_score.map(|score| rank_table_config.decode_redis_score(score));
+            let rule = rank_table_config.check_cheat_score(user_score.score, current_score);
+            let review_reason = match rule {
+                Some(rule) if rank_table_config.cheat_action == CheatAction::Review => {
+                    Some(rule.as_str())
+                }
+                Some(_) => None,
+                None if rank_table_config.is_score_outlier(
+                    user_score.score,
+                    current_score,
+                    top_score,
+                ) =>
+                {
+                    Some(REVIEW_REASON_OUTLIER)
+                }
+                None => None,
+            };
+            decisions.push((
+                index,
+                user_score,
+                rank_table_config,
+                current_score,
+                top_score,
+                rule,
+                review_reason,
+            ));
+        }
+        // 写入排行榜和进入审核队列的提交才记录提交频率
+        let rate_checks: Vec<(&UserScoreInfo, &RankTableConfig)> = decisions
+            .iter()
+            .filter(|(_, _, rank_table_config, _, _, rule, review_reason)| {
+                rank_table_config.has_rate_limit() && (rule.is_none() || review_reason.is_some())
+            })
+            .map(|(_, user_score, rank_table_config, ..)| (*user_score, *rank_table_config))
+            .collect();
+        let mut rate_rules = match self
+            .rank_repo
+            .charge_anti_cheat_rate_in_redis(&rate_checks, now)
+            .await
+        {
+            Ok(rate_rules) => rate_rules.into_iter(),
+            Err(err) => {
+                tracing::error!("charge anti cheat rate in redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+
+        let mut violations = vec![];
+        let mut reviews = vec![];
+        for (index, user_score, rank_table_config, current_score, top_score, rule, review_reason) in
+            decisions
+        {
+            let mut rule = rule;
+            let mut review_reason = review_reason;
+            if rank_table_config.has_rate_limit() && (rule.is_none() || review_reason.is_some()) {
+                // 违反频率规则的提交直接拒绝，不进入审核队列
+                if let Some(rate_rule) = rate_rules
+                    .next()
+                    .and_then(|rate_rule| AntiCheatRule::try_from(rate_rule).ok())
+                {
+                    rule = Some(rate_rule);
+                    review_reason = None;
+                }
+            }
+            if let Some(reason) = review_reason {
+                reviews.push(RankScoreReview {
+                    id: 0,
+                    appid: user_score.appid.clone(),
+                    rank_key: user_score.rank_key.clone(),
+                    openid: user_score.openid.clone(),
+                    nick_name: user_score.nick_name.clone(),
+                    partition: user_score.partition.clone(),
+                    score: user_score.score,
+                    current_score,
+                    top_score,
+                    reason: reason.to_string(),
+                    status: ReviewStatus::Pending,
+                    season: rank_table_config.season,
+                    source_ip: source.source_ip.clone(),
+                    request_id: source.request_id.clone(),
+                    create_time: now,
+                    review_time: 0,
+                });
+                errs[index] = Ok(true);
+            } else if let Some(rule) = rule {
+                errs[index] = Err(RankError::CheatDetected(rule.as_str().to_string()));
+            }
+            if let Some(rule) = rule {
+                violations.push(RankCheatViolation {
+                    id: 0,
+                    appid: user_score.appid.clone(),
+                    rank_key: user_score.rank_key.clone(),
+                    openid: user_score.openid.clone(),
+                    rule,
+                    score: user_score.score,
+                    current_score,
+                    source_ip: source.source_ip.clone(),
+                    request_id: source.request_id.clone(),
+                    create_time: now,
+                });
+            }
+        }
+        if let Err(err) = self
+            .rank_repo
+            .add_cheat_violations_to_mysql(&violations)
+            .await
+        {
+            tracing::error!("add cheat violations to mysql error :{}", err.to_string());
+        }
+        // 审核队列写入失败时拒绝提交，避免分数丢失而客户端以为在等待审核
+        if let Err(err) = self.rank_repo.add_score_reviews_to_mysql(&reviews).await {
+            tracing::error!("add score reviews to mysql error :{}", err.to_string());
+            Err(DbError::SomethingWentWrong(err.to_string()))?
+        }
+        Ok(errs)
+    }
+
+    /// 分数没有写入时退还记录的提交频率，失败时只打印日志
+    async fn refund_anti_cheat_rate(&self, user_scores: &Vec<(&UserScoreInfo, &RankTableConfig)>) {
+        let user_scores = user_scores
+            .iter()
+            .filter(|(_, rank_table_config)| rank_table_config.has_rate_limit())
+            .copied()
+            .collect();
+        if let Err(err) = self
+            .rank_repo
+            .refund_anti_cheat_rate_in_redis(&user_scores, chrono::Utc::now().timestamp())
+            .await
+        {
+            tracing::error!("refund anti cheat rate in redis error :{}", err.to_string());
+        }
+    }
+
+    /// 分页获取appid下的可疑分数审核，先提交的在前
+    pub async fn get_score_reviews(
+        &self,
+        payload: &ScoreReviewListReq,
+    ) -> Result<Vec<ScoreReviewRes>, ApiError> {
+        let reviews = match self
+            .rank_repo
+            .get_score_reviews_from_mysql(
+                &payload.appid,
+                &payload.status,
+                payload.offset as u64,
+                payload.limit as u64,
+            )
+            .await
+        {
+            Ok(reviews) => reviews,
+            Err(err) => {
+                tracing::error!("get score reviews from mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+        Ok(reviews
+            .into_iter()
+            .map(|review| {
+                // 分数按排行榜的精度显示，排行榜已经删除时显示存储的分数
+                let config = self
+                    .get_rank_table_config(&review.appid, &review.rank_key)
+                    .ok();
+                let to_display_score = |score: i64| match &config {
+                    Some(config) => config.to_display_score(score),
+                    None => serde_json::Number::from(score),
+                };
+                ScoreReviewRes {
+                    id: review.id,
+                    score: to_display_score(review.score),
+                    current_score: review.current_score.map(to_display_score),
+                    top_score: review.top_score.map(to_display_score),
+                    rank_key: review.rank_key,
+                    openid: review.openid,
+                    nick_name: review.nick_name,
+                    partition: review.partition,
+                    reason: review.reason,
+                    status: review.status,
+                    season: review.season,
+                    source_ip: review.source_ip,
+                    request_id: review.request_id,
+                    create_time: review.create_time,
+                    review_time: review.review_time,
+                }
+            })
+            .collect())
+    }
+
+    /// 审核通过一条等待审核的可疑分数，按排行榜的更新策略写入排行榜
+    ///
+    /// 同分排序和滚动排行榜的分桶按审核时间计算。审核状态和mysql中的分数在同一个事务中修改，
+    /// 写入失败时仍然等待审核；同一条记录只能写入一次，累加策略的分数不会重复累加
+    pub async fn approve_score_review(&self, payload: &ScoreReviewReq) -> Result<(), ApiError> {
+        let review = self.get_pending_score_review(payload).await?;
+        let rank_table_config = self.get_rank_table_config(&review.appid, &review.rank_key)?;
+        if review.season != rank_table_config.season {
+            Err(RankError::SeasonMismatch(rank_table_config.season))?
+        }
+        let ban_type = self
+            .get_ban_types(&review.appid, &vec![review.openid.clone()])
+            .await?
+            .remove(&review.openid);
+        if ban_type == Some(BanType::Ban) {
+            Err(RankError::UserBanned)?
+        }
+        let user_score = UserScoreInfo {
+            appid: review.appid.clone(),
+            rank_key: review.rank_key.clone(),
+            openid: review.openid.clone(),
+            nick_name: review.nick_name.clone(),
+            score: review.score,
+            update_time: chrono::Utc::now().timestamp(),
+            partition: review.partition.clone(),
+        };
+        let source = ScoreRequestSource {
+            source_ip: review.source_ip.clone(),
+            request_id: review.request_id.clone(),
+        };
+        match self
+            .rank_repo
+            .approve_score_review_to_mysql(&review, &user_score, &rank_table_config, &source)
+            .await
+        {
+            Ok(true) => {
+                self.write_rank_score_to_redis(&user_score, &rank_table_config, ban_type)
+                    .await
+            }
+            // 并发审核时已经被其他请求审核
+            Ok(false) => Err(RankError::ScoreReviewNotFound)?,
+            Err(err) => {
+                tracing::error!("approve score review to mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 审核拒绝一条等待审核的可疑分数
+    pub async fn reject_score_review(&self, payload: &ScoreReviewReq) -> Result<(), ApiError> {
+        self.update_score_review_status(
+            payload,
+            &ReviewStatus::Rejected,
+            chrono::Utc::now().timestamp(),
+        )
+        .await
+    }
+
+    /// 获取等待审核的可疑分数
+    async fn get_pending_score_review(
+        &self,
+        payload: &ScoreReviewReq,
+    ) -> Result<RankScoreReview, ApiError> {
+        match self
+            .rank_repo
+            .get_score_review_from_mysql(&payload.appid, payload.id)
+            .await
+        {
+            Ok(Some(review)) if review.status == ReviewStatus::Pending => Ok(review),
+            Ok(_) => Err(RankError::ScoreReviewNotFound)?,
+            Err(err) => {
+                tracing::error!("get score review from mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 修改等待审核的可疑分数的审核状态，已经审核过时返回不存在
+    async fn update_score_review_status(
+        &self,
+        payload: &ScoreReviewReq,
+        status: &ReviewStatus,
+        review_time: i64,
+    ) -> Result<(), ApiError> {
+        match self
+            .rank_repo
+            .update_score_review_status_to_mysql(
+                &payload.appid,
+                payload.id,
+                &ReviewStatus::Pending,
+                status,
+                review_time,
+            )
+            .await
+        {
+            Ok(0) => Err(RankError::ScoreReviewNotFound)?,
+            Ok(_) => Ok(()),
+            Err(err) => {
+                tracing::error!(
+                    "update score review status to mysql error :{}",
+                    err.to_string()
+                );
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 获取不在排行榜上的玩家被封禁时的分数
+    ///
+    /// 没有被封禁返回 None；影子封禁返回封禁的排行榜上的分数，其他封禁方式不返回分数
+    async fn get_banned_user_score(
+        &self,
+        rank_table_config: &RankTableConfig,
+        openid: &String,
+    ) -> Result<Option<Option<f64>>, ApiError> {
+        let appid = &rank_table_config.appid;
+        match self
+            .get_ban_types(appid, &vec![openid.clone()])
+            .await?
+            .remove(openid)
+        {
+            Some(BanType::Shadow) => match self
+                .rank_repo
+                .get_banned_score_from_redis(appid, openid, &rank_table_config.rank_key)
+                .await
+            {
+                Ok(score) => Ok(Some(score)),
+                Err(err) => {
+                    tracing::error!("get banned score from redis error :{}", err.to_string());
+                    Err(DbError::SomethingWentWrong(err.to_string()))?
+                }
+            },
+            Some(BanType::Ban) => Ok(Some(None)),
+            None => Ok(None),
+        }
+    }
+
+    /// 来源排行榜的分数变化后重新计算玩家在组合排行榜上的分数
+    ///
+    /// 组合排行榜由来源排行榜计算，失败时只记录日志，不影响来源排行榜的更新结果
+    async fn update_composite_score(&self, appid: &String, user_rank_keys: &Vec<(String, String)>) {
+        let mut user_composites = vec![];
+        {
+            let guard = self.rank_table_configs.lock().unwrap();
+            for composite in guard
+                .iter()
+                .filter(|config| config.appid == *appid && config.is_composite())
+            {
+                let mut openids: Vec<&String> = user_rank_keys
+                    .iter()
+                    .filter(|(_, rank_key)| {
+                        composite
+                            .composite_sources
+                            .0
+                            .iter()
+                            .any(|source| source.rank_key == *rank_key)
+                    })
+                    .map(|(openid, _)| openid)
+                    .collect();
+                if openids.is_empty() {
+                    continue;
+                }
+                openids.sort();
+                openids.dedup();
+                let source_weights = composite.composite_source_weights(&guard);
+                for openid in openids {
+                    user_composites.push((
+                        openid.clone(),
+                        composite.rank_key.clone(),
+                        source_weights.clone(),
+                    ));
+                }
+            }
+        }
+        if let Err(err) = self
+            .rank_repo
+            .update_composite_score_to_redis(appid, &user_composites)
+            .await
+        {
+            tracing::error!("update composite score to redis error :{}", err.to_string());
+        }
+    }
+
+    /// 按排行榜分组更新分数
+    ///
+    /// 分组内每个排行榜按自己的更新策略、分数范围和赛季单独处理，与批量更新分数一样一次pipeline写入redis
+    pub async fn update_group_score(
+        &self,
+        payload: UpdateGroupScoreReq,
+        source: &ScoreRequestSource,
+    ) -> Result<Vec<BatchUpdateScoreItemRes>, ApiError> {
+        let rank_keys: Vec<String> = {
+            let guard = self.rank_table_configs.lock().unwrap();
+            guard
+                .iter()
+                .filter(|config| {
+                    config.appid == payload.appid && config.board_group == payload.board_group
+                })
+                .map(|config| config.rank_key.clone())
+                .collect()
+        };
+        if rank_keys.is_empty() {
+            Err(RankError::BoardGroupNotFound)?
+        }
+        let items = rank_keys
+            .into_iter()
+            .map(|rank_key| UpdateScoreRequest {
+                appid: payload.appid.clone(),
+                rank_key,
+                openid: payload.openid.clone(),
+                nick_name: payload.nick_name.clone(),
+                score: payload.score,
+                season: None,
+                partition: payload.partition.clone(),
+            })
+            .collect();
+        self.batch_update_rank_score(
+            BatchUpdateScoreReq {
+                appid: payload.appid,
+                items,
+            },
+            source,
+        )
+        .await
+    }
+
+    pub async fn get_user_score(
+        &self,
+        appid: &String,
+        openid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+    ) -> Result<serde_json::Number, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        match self
+            .rank_repo
+            .get_user_score_from_redis(appid, openid, rank_key, partition)
+            .await
+        {
+            Ok(score) => {
+                // 被封禁的玩家不在排行榜上，影子封禁的玩家能看到自己的分数，其他封禁方式不能从mysql恢复到排行榜
+                let score = match score {
+                    None if partition.is_none() => {
+                        match self
+                            .get_banned_user_score(&rank_table_config, openid)
+                            .await?
+                        {
+                            Some(Some(score)) => Some(score),
+                            Some(None) => Err(DbError::SomethingWentWrong(
+                                "openid is not exist".to_string(),
+                            ))?,
+                            None => None,
+                        }
+                    }
+                    score => score,
+                };
+                if let Some(score) = score {
+                    Ok(rank_table_config.to_display_redis_score(score))
+                } else if rank_table_config.is_rolling_window()
+                    || rank_table_config.is_composite()
+                    || partition.is_some()
+                {
+                    // 滚动排行榜、组合排行榜只保存在redis中，不在榜上说明时间窗口内或来源排行榜上没有分数
+                    // 不在分区中说明玩家不属于这个分区
+                    Err(DbError::SomethingWentWrong(
+                        "openid is not exist".to_string(),
+                    ))?
+                } else if self
+                    .get_trimmed_openids(&rank_table_config, &vec![openid.clone()])
+                    .await?
+                    .contains(openid)
+                {
+                    // 被裁剪的玩家的分数可能保留在mysql中，不能重新加载回排行榜
+                    Err(DbError::SomethingWentWrong(
+                        "openid is not exist".to_string(),
+                    ))?
+                } else {
+                    match self
+                        .rank_repo
+                        .get_user_score_info_from_mysql(appid, openid, rank_key)
+                        .await
+                    {
+                        Ok(user_info) => {
+                            let _ = self
+                                .rank_repo
+                                .update_rank_score_to_redis(
+                                    &user_info,
+                                    &UpdatePolicy::Replace,
+                                    &rank_table_config,
+                                )
+                                .await;
+                            Ok(rank_table_config.to_display_score(user_info.score))
+                        }
+                        Err(sqlx::Error::RowNotFound) => {
+                            tracing::error!(
+                                "get score from mysql error :{}",
+                                sqlx::Error::RowNotFound.to_string()
+                            );
+                            Err(DbError::SomethingWentWrong(
+                                "openid is not exist".to_string(),
+                            ))?
+                        }
+                        Err(err) => {
+                            tracing::error!("get score from mysql error2 :{}", err.to_string());
+                            Err(DbError::SomethingWentWrong(err.to_string()))?
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::error!("get user score to redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 玩家是否在排行榜保留的人数之外，排行榜没有限制人数时返回 None
+    ///
+    /// ranking 为 0 时，玩家被裁剪过说明分数不够上榜，再次上榜后按排名判断
+    pub async fn get_below_cutoff(
+        &self,
+        appid: &String,
+        openid: &String,
+        rank_key: &String,
+        ranking: i32,
+    ) -> Result<Option<bool>, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        if rank_table_config.max_entries <= 0 {
+            return Ok(None);
+        }
+        if ranking > 0 {
+            return Ok(Some(ranking as i64 > rank_table_config.max_entries));
+        }
+        // 不在榜上时只有被裁剪过的玩家才是低于保留线，没有提交过分数的玩家不是
+        match self
+            .rank_repo
+            .get_trimmed_users_from_redis(appid, rank_key, &vec![openid.clone()])
+            .await
+        {
+            Ok(trimmed) => Ok(Some(trimmed.first().copied().unwrap_or(false))),
+            Err(err) => {
+                tracing::error!("get trimmed user from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    pub async fn get_user_ranking(
+        &self,
+        appid: &String,
+        openid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+    ) -> Result<i32, ApiError> {
+        let ranking = self
+            .get_rank_user_ranking(appid, openid, rank_key, partition)
+            .await?;
+        if ranking > 0 || partition.is_some() {
+            return Ok(ranking);
+        }
+        // 影子封禁的玩家按自己的分数计算在整个排行榜上的排名，其他玩家看不到
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        match self
+            .get_banned_user_score(&rank_table_config, openid)
+            .await?
+        {
+            Some(Some(score)) => Ok(self
+                .get_scores_ranking(&rank_table_config, partition, &vec![score])
+                .await?
+                .pop()
+                .unwrap_or_default()),
+            _ => Ok(0),
+        }
+    }
+
+    /// 玩家在排行榜上的排名，0 未上榜
+    async fn get_rank_user_ranking(
+        &self,
+        appid: &String,
+        openid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+    ) -> Result<i32, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        // 并列排名需要按分数计算
+        if rank_table_config.rank_style != RankStyle::Ordinal {
+            return match self
+                .rank_repo
+                .get_user_score_from_redis(appid, openid, rank_key, partition)
+                .await
+            {
+                Ok(Some(score)) => Ok(self
+                    .get_scores_ranking(&rank_table_config, partition, &vec![score])
+                    .await?
+                    .pop()
+                    .unwrap_or_default()),
+                Ok(None) => Ok(0),
+                Err(err) => {
+                    tracing::error!("get user score from redis error :{}", err.to_string());
+                    Err(DbError::SomethingWentWrong(err.to_string()))?
+                }
+            };
+        }
+        match self
+            .rank_repo
+            .get_user_ranking(
+                appid,
+                openid,
+                rank_key,
+                partition,
+                &rank_table_config.sort_order,
+            )
+            .await
+        {
+            Ok(ranking) => Ok(ranking),
+            Err(err) => {
+                tracing::error!("get user ranking from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 按排行榜配置的排名方式获取redis分数对应的排名
+    async fn get_scores_ranking(
+        &self,
+        rank_table_config: &RankTableConfig,
+        partition: &Option<String>,
+        scores: &Vec<f64>,
+    ) -> Result<Vec<i32>, ApiError> {
+        match self
+            .rank_repo
+            .get_scores_ranking(
+                &rank_table_config.appid,
+                &rank_table_config.rank_key,
+                partition,
+                scores,
+                &rank_table_config.rank_style,
+                &rank_table_config.sort_order,
+            )
+            .await
+        {
+            Ok(rankings) => Ok(rankings),
+            Err(err) => {
+                tracing::error!("get scores ranking from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 计算排名百分比，保留两位小数，未上榜返回 None
+    pub async fn get_ranking_percentile(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+        ranking: i32,
+    ) -> Result<Option<f64>, ApiError> {
+        if ranking <= 0 {
+            return Ok(None);
+        }
+        match self
+            .rank_repo
+            .get_rank_user_count(appid, rank_key, partition)
+            .await
+        {
+            Ok(total) if total > 0 => {
+                let percentile = ranking as f64 * 100.0 / total as f64;
+                Ok(Some((percentile * 100.0).ceil() / 100.0))
+            }
+            Ok(_) => Ok(None),
+            Err(err) => {
+                tracing::error!("get rank user count from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 排行榜统计
+    pub async fn get_rank_stats(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+    ) -> Result<RankStatsRes, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        match self
+            .rank_repo
+            .get_rank_stats_from_redis(appid, rank_key, partition)
+            .await
+        {
+            Ok((total, min_score, max_score)) => Ok(RankStatsRes {
+                total,
+                min_score: min_score.map(|score| rank_table_config.to_display_redis_score(score)),
+                max_score: max_score.map(|score| rank_table_config.to_display_redis_score(score)),
+            }),
+            Err(err) => {
+                tracing::error!("get rank stats from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 批量获取用户分数和排名，不在榜上的用户 on_board 为 false
+    pub async fn get_bulk_user_rank(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+        openids: &Vec<String>,
+    ) -> Result<Vec<BulkUserRankRes>, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        let (score_rankings, nick_names) = match self
+            .rank_repo
+            .get_users_score_ranking_from_redis(
+                appid,
+                rank_key,
+                partition,
+                openids,
+                &rank_table_config.sort_order,
+            )
+            .await
+        {
+            Ok(res) => res,
+            Err(err) => {
+                tracing::error!(
+                    "get bulk user ranking from redis error :{}",
+                    err.to_string()
+                );
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+
+        let mut res = vec![];
+        let mut on_board_scores = vec![];
+        for ((openid, (score, ranking)), nick_name) in
+            openids.iter().zip(score_rankings).zip(nick_names)
+        {
+            match (score, ranking) {
+                (Some(score), Some(ranking)) => {
+                    on_board_scores.push((res.len(), score));
+                    res.push(BulkUserRankRes {
+                        openid: openid.clone(),
+                        on_board: true,
+                        nick_name: Some(nick_name.unwrap_or("momo".to_string())),
+                        score: Some(rank_table_config.to_display_redis_score(score)),
+                        ranking: Some(ranking + 1),
+                    })
+                }
+                _ => res.push(BulkUserRankRes {
+                    openid: openid.clone(),
+                    on_board: false,
+                    nick_name: None,
+                    score: None,
+                    ranking: None,
+                }),
+            }
+        }
+
+        // 并列排名需要按分数重新计算
+        if rank_table_config.rank_style != RankStyle::Ordinal && !on_board_scores.is_empty() {
+            let scores = on_board_scores.iter().map(|(_, score)| *score).collect();
+            let rankings = self
+                .get_scores_ranking(&rank_table_config, partition, &scores)
+                .await?;
+            for ((index, _), ranking) in on_board_scores.iter().zip(rankings) {
+                res[*index].ranking = Some(ranking);
+            }
+        }
+        Ok(res)
+    }
+
+    /// 好友排行榜
+    ///
+    /// 按全局排名排序，返回在指定玩家中的排名和全局排名，不在榜上的玩家不返回
+    pub async fn get_friend_user_rank(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+        openids: &Vec<String>,
+    ) -> Result<Vec<FriendRankRes>, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        let mut openids = openids.clone();
+        openids.sort();
+        openids.dedup();
+
+        let users = self
+            .get_bulk_user_rank(appid, rank_key, partition, &openids)
+            .await?;
+        let mut res: Vec<FriendRankRes> = users
+            .into_iter()
+            .filter(|user| user.on_board)
+            .map(|user| FriendRankRes {
+                openid: user.openid,
+                nick_name: user.nick_name.unwrap_or_default(),
+                score: user.score.unwrap_or(serde_json::Number::from(0)),
+                ranking: 0,
+                global_ranking: user.ranking.unwrap_or_default(),
+            })
+            .collect();
+        // 全局排名已经包含同分时的排序规则，全局排名并列时好友排名也并列
+        res.sort_by_key(|user| user.global_ranking);
+        let mut prev: Option<(i32, i32)> = None;
+        for (index, user) in res.iter_mut().enumerate() {
+            user.ranking = match prev {
+                Some((prev_global_ranking, prev_ranking)) => next_ranking(
+                    &rank_table_config.rank_style,
+                    prev_ranking,
+                    index as i32 + 1,
+                    prev_global_ranking == user.global_ranking,
+                ),
+                None => 1,
+            };
+            prev = Some((user.global_ranking, user.ranking));
+        }
+        Ok(res)
+    }
+
+    pub async fn get_top_user_rank(
+        &self,
+        appid: &String,
+        rank_type_key: &String,
+        partition: &Option<String>,
+        top: i32,
+    ) -> Result<Vec<UserScoreRes>, ApiError> {
+        self.get_range_user_rank(appid, rank_type_key, partition, 0, top)
+            .await
+    }
+
+    /// 分页获取排行榜
+    pub async fn get_range_user_rank(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<UserScoreRes>, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        if limit > rank_table_config.max_page_size {
+            Err(RankError::PageSizeTooLarge(rank_table_config.max_page_size))?
+        }
+        self.query_range_user_rank(
+            &rank_table_config,
+            partition,
+            offset,
+            offset.saturating_add(limit) - 1,
+        )
+        .await
+    }
+
+    /// 获取用户上下各 count 名相邻玩家
+    pub async fn get_around_user_rank(
+        &self,
+        appid: &String,
+        openid: &String,
+        rank_key: &String,
+        partition: &Option<String>,
+        count: i32,
+    ) -> Result<AroundUserRes, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        if count.saturating_mul(2).saturating_add(1) > rank_table_config.max_page_size {
+            Err(RankError::PageSizeTooLarge(rank_table_config.max_page_size))?
+        }
+        // 在榜上的位置，不考虑并列
+        let position = match self
+            .rank_repo
+            .get_user_ranking(
+                appid,
+                openid,
+                rank_key,
+                partition,
+                &rank_table_config.sort_order,
+            )
+            .await
+        {
+            Ok(position) => position,
+            Err(err) => {
+                tracing::error!("get user ranking from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+        // 未上榜
+        if position == 0 {
+            // 影子封禁的玩家不在排行榜上，但能看到自己按分数所在的位置，其他玩家看不到
+            if partition.is_none() {
+                if let Some(Some(score)) = self
+                    .get_banned_user_score(&rank_table_config, openid)
+                    .await?
+                {
+                    return self
+                        .get_shadow_around_user_rank(&rank_table_config, openid, score, count)
+                        .await;
+                }
+            }
+            return Ok(AroundUserRes {
+                ranking: position,
+                users: vec![],
+            });
+        }
+        // 榜首和榜尾时区间会被截断
+        let start = (position - 1 - count).max(0);
+        let stop = position - 1 + count;
+        let users = self
+            .query_range_user_rank(&rank_table_config, partition, start, stop)
+            .await?;
+        let ranking = users
+            .iter()
+            .find(|user| user.openid.as_ref() == Some(openid))
+            .and_then(|user| user.ranking)
+            .unwrap_or(position);
+        Ok(AroundUserRes { ranking, users })
+    }
+
+    /// 影子封禁的玩家查询自己前后的玩家
+    ///
+    /// 玩家按分数插入到榜上玩家中间，排名与查询自己的排名一致，其他玩家的排名不变
+    async fn get_shadow_around_user_rank(
+        &self,
+        rank_table_config: &RankTableConfig,
+        openid: &String,
+        score: f64,
+        count: i32,
+    ) -> Result<AroundUserRes, ApiError> {
+        // 按分数插入的位置，不考虑并列
+        let ordinal_config = RankTableConfig {
+            rank_style: RankStyle::Ordinal,
+            ..rank_table_config.clone()
+        };
+        let position = self
+            .get_scores_ranking(&ordinal_config, &None, &vec![score])
+            .await?
+            .pop()
+            .unwrap_or(1);
+        let ranking = self
+            .get_scores_ranking(rank_table_config, &None, &vec![score])
+            .await?
+            .pop()
+            .unwrap_or(position);
+        // 玩家不占用榜上的位置，后面的玩家从 position - 1 开始
+        let start = (position - 1 - count).max(0);
+        let stop = position - 2 + count;
+        let users = self
+            .query_range_user_rank(rank_table_config, &None, start, stop)
+            .await?;
+        let nick_name = match self
+            .rank_repo
+            .get_users_info_from_redis(&rank_table_config.appid, &vec![openid.clone()])
+            .await
+        {
+            Ok(mut nick_names) => nick_names.pop().flatten(),
+            Err(err) => {
+                tracing::error!(" user name find failed, error: {}", err.to_string());
+                None
+            }
+        };
+        let user = UserScoreRes {
+            openid: Some(openid.clone()),
+            ranking: Some(ranking),
+            score: Some(rank_table_config.to_display_redis_score(score)),
+            nick_name: Some(nick_name.unwrap_or("momo".to_string())),
+            percentile: None,
+            below_cutoff: None,
+        };
+        Ok(AroundUserRes {
+            ranking,
+            users: insert_shadow_user(users, start, position, user),
+        })
+    }
+
+    /// 获取排行榜的分区，按分区名排序
+    pub async fn get_rank_partitions(
+        &self,
+        appid: &String,
+        rank_key: &String,
+    ) -> Result<Vec<RankPartitionRes>, ApiError> {
+        self.get_rank_table_config(appid, rank_key)?;
+        match self
+            .rank_repo
+            .get_rank_partitions_from_redis(appid, rank_key)
+            .await
+        {
+            Ok(mut partitions) => {
+                partitions.sort();
+                Ok(partitions
+                    .into_iter()
+                    .map(|(partition, total)| RankPartitionRes { partition, total })
+                    .collect())
+            }
+            Err(err) => {
+                tracing::error!("get rank partitions from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 获取开启了队伍排行榜的排行榜配置
+    fn get_team_rank_table_config(
+        &self,
+        appid: &String,
+        rank_key: &String,
+    ) -> Result<RankTableConfig, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        if !rank_table_config.is_team_board() {
+            Err(RankError::TeamRankDisabled)?
+        }
+        Ok(rank_table_config)
+    }
+
+    /// appid下所有开启了队伍排行榜的排行榜配置，appid没有任何排行榜时返回错误
+    fn get_team_rank_table_configs(
+        &self,
+        appid: &String,
+    ) -> Result<Vec<RankTableConfig>, ApiError> {
+        let guard = self.rank_table_configs.lock().unwrap();
+        if !guard.iter().any(|config| config.appid == *appid) {
+            Err(RankError::RankConfigNotFound)?
+        }
+        Ok(guard
+            .iter()
+            .filter(|config| config.appid == *appid && config.is_team_board())
+            .cloned()
+            .collect())
+    }
+
+    /// 加入队伍，已经在其他队伍时转到新队伍
+    ///
+    /// 原来的队伍和新队伍在所有队伍排行榜上的分数都会重新计算
+    pub async fn join_team(&self, payload: JoinTeamReq) -> Result<(), ApiError> {
+        if !payload
+            .team_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            Err(RankError::InvalidTeam(
+                "team_id only supports letters, digits, '_' and '-'".to_string(),
+            ))?
+        }
+        let rank_table_configs = self.get_team_rank_table_configs(&payload.appid)?;
+        let team_member = RankTeamMember {
+            appid: payload.appid,
+            openid: payload.openid,
+            team_id: payload.team_id,
+            update_time: chrono::Utc::now().timestamp(),
+        };
+        // 先在redis中加入队伍，队伍人数在脚本中校验，避免并发加入时超过上限
+        let old_team_id = match self
+            .rank_repo
+            .update_team_member_to_redis(
+                &team_member.appid,
+                &team_member.openid,
+                &Some(team_member.team_id.clone()),
+                MAX_TEAM_SIZE,
+                &rank_table_configs,
+            )
+            .await
+        {
+            Ok((true, old_team_id)) => old_team_id,
+            Ok((false, _)) => Err(RankError::InvalidTeam(format!(
+                "team is full, at most {} members",
+                MAX_TEAM_SIZE
+            )))?,
+            Err(err) => {
+                tracing::error!("update team member to redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+        match self
+            .rank_repo
+            .update_team_member_to_mysql(&team_member)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                tracing::error!("update team member to mysql error :{}", err.to_string());
+                // 写入mysql失败时恢复redis中原来的队伍
+                if let Err(err) = self
+                    .rank_repo
+                    .update_team_member_to_redis(
+                        &team_member.appid,
+                        &team_member.openid,
+                        &old_team_id,
+                        0,
+                        &rank_table_configs,
+                    )
+                    .await
+                {
+                    tracing::error!("restore team member to redis error :{}", err.to_string());
+                }
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 离开队伍，不在队伍中时直接返回成功
+    pub async fn leave_team(&self, payload: LeaveTeamReq) -> Result<(), ApiError> {
+        let rank_table_configs = self.get_team_rank_table_configs(&payload.appid)?;
+        match self
+            .rank_repo
+            .delete_team_member_from_mysql(&payload.appid, &payload.openid)
+            .await
+        {
+            Ok(_) => match self
+                .rank_repo
+                .update_team_member_to_redis(
+                    &payload.appid,
+                    &payload.openid,
+                    &None,
+                    0,
+                    &rank_table_configs,
+                )
+                .await
+            {
+                Ok(_) => Ok(()),
                 Err(err) => {
-                    // TODO@ 当更新排行榜出错后需要重试，或者记录错误信息到本地
-                    tracing::error!("update score to redis error :{}", err.to_string());
+                    tracing::error!("update team member to redis error :{}", err.to_string());
                     Err(DbError::SomethingWentWrong(err.to_string()))?
                 }
             },
             Err(err) => {
-                tracing::error!("update score to mysql error :{}", err.to_string());
+                tracing::error!("delete team member from mysql error :{}", err.to_string());
                 Err(DbError::SomethingWentWrong(err.to_string()))?
             }
         }
     }
 
-    pub async fn get_user_score(
+    /// 队伍排行榜前N名，同分的队伍按队伍id排序，不并列
+    pub async fn get_team_top_rank(
         &self,
         appid: &String,
-        openid: &String,
         rank_key: &String,
-    ) -> Result<i32, ApiError> {
+        top: i32,
+    ) -> Result<Vec<TeamScoreRes>, ApiError> {
+        let rank_table_config = self.get_team_rank_table_config(appid, rank_key)?;
+        if top > rank_table_config.max_page_size {
+            Err(RankError::PageSizeTooLarge(rank_table_config.max_page_size))?
+        }
         match self
             .rank_repo
-            .get_user_score_from_redis(appid, openid, rank_key)
+            .get_team_range_from_redis(appid, rank_key, 0, top - 1, &rank_table_config.sort_order)
             .await
         {
-            Ok(score) => {
-                if score != -1 {
-                    Ok(score)
-                } else {
-                    match self
-                        .rank_repo
-                        .get_user_score_info_from_mysql(appid, openid, rank_key)
-                        .await
-                    {
-                        Ok(user_info) => {
-                            let _ = self
-                                .rank_repo
-                                .update_rank_score_to_redis(&UpdateScoreRequest {
-                                    appid: appid.clone(),
-                                    openid: openid.clone(),
-                                    rank_key: rank_key.clone(),
-                                    score: user_info.score,
-                                    nick_name: user_info.nick_name,
-                                })
-                                .await;
-                            Ok(user_info.score)
-                        }
-                        Err(sqlx::Error::RowNotFound) => {
-                            tracing::error!(
-                                "get score from mysql error :{}",
-                                sqlx::Error::RowNotFound.to_string()
-                            );
-                            Err(DbError::SomethingWentWrong(
-                                "openid is not exist".to_string(),
-                            ))?
-                        }
-                        Err(err) => {
-                            tracing::error!("get score from mysql error2 :{}", err.to_string());
-                            Err(DbError::SomethingWentWrong(err.to_string()))?
-                        }
-                    }
-                }
+            Ok(teams) => Ok(teams
+                .into_iter()
+                .enumerate()
+                .map(|(index, (team_id, score))| TeamScoreRes {
+                    team_id,
+                    score: Some(rank_table_config.to_display_score(score as i64)),
+                    ranking: index as i32 + 1,
+                })
+                .collect()),
+            Err(err) => {
+                tracing::error!("get team range from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
             }
+        }
+    }
+
+    /// 获取队伍的分数和排名
+    pub async fn get_team_rank(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        team_id: &String,
+    ) -> Result<TeamScoreRes, ApiError> {
+        let rank_table_config = self.get_team_rank_table_config(appid, rank_key)?;
+        match self
+            .rank_repo
+            .get_team_rank_from_redis(appid, rank_key, team_id, &rank_table_config.sort_order)
+            .await
+        {
+            Ok((score, ranking)) => Ok(TeamScoreRes {
+                team_id: team_id.clone(),
+                score: score.map(|score| rank_table_config.to_display_score(score as i64)),
+                ranking,
+            }),
             Err(err) => {
-                tracing::error!("get user score to redis error :{}", err.to_string());
+                tracing::error!("get team rank from redis error :{}", err.to_string());
                 Err(DbError::SomethingWentWrong(err.to_string()))?
             }
         }
     }
 
-    pub async fn get_user_ranking(
+    /// 获取队伍成员在排行榜上的分数，按排名排序，不在榜上的成员排在最后
+    ///
+    /// counted 表示成员分数计入了队伍分数
+    pub async fn get_team_members(
         &self,
         appid: &String,
-        openid: &String,
         rank_key: &String,
-    ) -> Result<i32, ApiError> {
+        team_id: &String,
+    ) -> Result<Vec<TeamMemberRes>, ApiError> {
+        let rank_table_config = self.get_team_rank_table_config(appid, rank_key)?;
+        let mut openids = match self
+            .rank_repo
+            .get_team_members_from_redis(appid, team_id)
+            .await
+        {
+            Ok(openids) => openids,
+            Err(err) => {
+                tracing::error!("get team members from redis error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        };
+        openids.sort();
+        let mut users = self
+            .get_bulk_user_rank(appid, rank_key, &None, &openids)
+            .await?;
+        users.sort_by_key(|user| (!user.on_board, user.ranking));
+
+        let member_limit = rank_table_config.team_member_limit() as usize;
+        Ok(users
+            .into_iter()
+            .enumerate()
+            .map(|(index, user)| TeamMemberRes {
+                counted: user.on_board && (member_limit == 0 || index < member_limit),
+                openid: user.openid,
+                on_board: user.on_board,
+                nick_name: user.nick_name,
+                score: user.score,
+                ranking: user.ranking,
+            })
+            .collect())
+    }
+
+    /// 分页获取排行榜归档的期，最新的在前
+    pub async fn get_archive_periods(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<RankArchivePeriodRes>, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        if limit > rank_table_config.max_page_size {
+            Err(RankError::PageSizeTooLarge(rank_table_config.max_page_size))?
+        }
         match self
             .rank_repo
-            .get_user_ranking(appid, openid, rank_key)
+            .get_archive_periods_from_mysql(appid, rank_key, offset as u64, limit as u64)
             .await
         {
-            Ok(ranking) => Ok(ranking),
+            Ok(periods) => Ok(periods
+                .into_iter()
+                .map(|period| RankArchivePeriodRes {
+                    period_id: period.period_id,
+                    season: period.season,
+                    start_time: period.start_time,
+                    end_time: period.end_time,
+                    total: period.total,
+                })
+                .collect()),
             Err(err) => {
-                tracing::error!("get user ranking from redis error :{}", err.to_string());
+                tracing::error!("get archive periods from mysql error :{}", err.to_string());
                 Err(DbError::SomethingWentWrong(err.to_string()))?
             }
         }
     }
 
-    pub async fn get_top_user_rank(
+    /// 分页获取排行榜归档的排名
+    pub async fn get_archive_range_rank(
         &self,
         appid: &String,
-        rank_type_key: &String,
-        top: i32,
+        rank_key: &String,
+        period_id: i64,
+        offset: i32,
+        limit: i32,
+    ) -> Result<Vec<UserScoreRes>, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        if limit > rank_table_config.max_page_size {
+            Err(RankError::PageSizeTooLarge(rank_table_config.max_page_size))?
+        }
+        match self
+            .rank_repo
+            .get_archive_period_from_mysql(appid, rank_key, period_id)
+            .await
+        {
+            Ok(_) => {}
+            Err(sqlx::Error::RowNotFound) => Err(RankError::ArchiveNotFound)?,
+            Err(err) => {
+                tracing::error!("get archive period from mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+        match self
+            .rank_repo
+            .get_archive_entries_from_mysql(period_id, offset as u64, limit as u64)
+            .await
+        {
+            Ok(entries) => Ok(entries
+                .into_iter()
+                .map(|entry| UserScoreRes {
+                    openid: Some(entry.openid),
+                    nick_name: Some(entry.nick_name),
+                    score: Some(rank_table_config.to_display_score(entry.score)),
+                    ranking: Some(entry.ranking),
+                    percentile: None,
+                    below_cutoff: None,
+                })
+                .collect()),
+            Err(err) => {
+                tracing::error!("get archive entries from mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 获取未确认的奖励结算，按结算顺序返回
+    pub async fn get_reward_settlements(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        limit: i32,
+    ) -> Result<Vec<RewardSettlementRes>, ApiError> {
+        let rank_table_config = self.get_rank_table_config(appid, rank_key)?;
+        match self
+            .rank_repo
+            .get_pending_reward_settlements_from_mysql(appid, rank_key, limit as u64)
+            .await
+        {
+            Ok(settlements) => Ok(settlements
+                .into_iter()
+                .map(|settlement| RewardSettlementRes {
+                    id: settlement.id,
+                    season: settlement.season,
+                    period_id: settlement.period_id,
+                    openid: settlement.openid,
+                    nick_name: settlement.nick_name,
+                    ranking: settlement.ranking,
+                    score: rank_table_config.to_display_score(settlement.score),
+                    reward: settlement.reward,
+                    create_time: settlement.create_time,
+                })
+                .collect()),
+            Err(err) => {
+                tracing::error!(
+                    "get reward settlements from mysql error :{}",
+                    err.to_string()
+                );
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 确认奖励结算，重复确认不会报错
+    pub async fn ack_reward_settlements(
+        &self,
+        appid: &String,
+        rank_key: &String,
+        ids: &Vec<i64>,
+    ) -> Result<u64, ApiError> {
+        self.get_rank_table_config(appid, rank_key)?;
+        match self
+            .rank_repo
+            .ack_reward_settlements_to_mysql(appid, rank_key, ids)
+            .await
+        {
+            Ok(acked) => Ok(acked),
+            Err(err) => {
+                tracing::error!("ack reward settlements to mysql error :{}", err.to_string());
+                Err(DbError::SomethingWentWrong(err.to_string()))?
+            }
+        }
+    }
+
+    /// 获取排名区间内的用户信息，start、stop 从0开始，包含stop
+    async fn query_range_user_rank(
+        &self,
+        rank_table_config: &RankTableConfig,
+        partition: &Option<String>,
+        start: i32,
+        stop: i32,
     ) -> Result<Vec<UserScoreRes>, ApiError> {
+        let appid = &rank_table_config.appid;
         match self
             .rank_repo
-            .get_top_user_rank(appid, rank_type_key, top)
+            .get_range_user_rank(
+                appid,
+                &rank_table_config.rank_key,
+                partition,
+                start,
+                stop,
+                &rank_table_config.sort_order,
+            )
             .await
         {
             Ok(users) => {
-                let count = users.len();
-                let mut i = 0;
-                let mut rank = 1;
+                // 区间第一个玩家可能与区间前面的玩家并列，需要按分数计算排名
+                let mut rank = match users.first() {
+                    Some((_, score)) if rank_table_config.rank_style != RankStyle::Ordinal => self
+                        .get_scores_ranking(rank_table_config, partition, &vec![*score])
+                        .await?
+                        .pop()
+                        .unwrap_or(start + 1),
+                    _ => start + 1,
+                };
+                let mut prev_score = None;
                 let mut res = vec![];
-                while i < count {
-                    // 转化分数
-                    let score_f: f64 = users[i + 1].parse().unwrap();
-                    let score = score_f as i32;
+                let mut openids = vec![];
+                for (index, (openid, score)) in users.into_iter().enumerate() {
+                    if prev_score.is_some() {
+                        rank = next_ranking(
+                            &rank_table_config.rank_style,
+                            rank,
+                            start + index as i32 + 1,
+                            prev_score == Some(score),
+                        );
+                    }
                     let user = UserScoreRes {
-                        openid: Some(users[i].clone()),
+                        openid: Some(openid.clone()),
                         ranking: Some(rank),
-                        score: Some(score),
+                        score: Some(rank_table_config.to_display_redis_score(score)),
                         nick_name: None,
+                        percentile: None,
+                        below_cutoff: None,
                     };
                     res.push(user);
-                    rank += 1;
-                    i += 2;
+                    openids.push(openid);
+                    prev_score = Some(score);
                 }
 
-                for user in &mut res {
-                    match self
-                        .rank_repo
-                        .get_user_info_from_redis(appid, &user.openid.clone().unwrap())
-                        .await
-                    {
-                        Ok(nick_name) => user.nick_name = Some(nick_name),
-                        Err(err) => {
-                            tracing::error!(" user name find failed, error: {}", err.to_string());
+                match self
+                    .rank_repo
+                    .get_users_info_from_redis(appid, &openids)
+                    .await
+                {
+                    Ok(nick_names) => {
+                        for (user, nick_name) in res.iter_mut().zip(nick_names) {
+                            user.nick_name = Some(nick_name.unwrap_or("momo".to_string()));
+                        }
+                    }
+                    Err(err) => {
+                        tracing::error!(" user name find failed, error: {}", err.to_string());
+                        for user in &mut res {
                             user.nick_name = Some("momo".to_string())
                         }
                     }
@@ -179,9 +2065,125 @@ impl RankService {
                 Ok(res)
             }
             Err(err) => {
-                tracing::error!("get top user ranking from redis error :{}", err.to_string());
+                tracing::error!(
+                    "get range user ranking from redis error :{}",
+                    err.to_string()
+                );
                 Err(DbError::SomethingWentWrong(err.to_string()))?
             }
         }
     }
 }
+
+/// 把影子封禁的玩家插入到从 start 开始查询的榜上玩家中，position 为按分数插入的位置(从1开始)
+fn insert_shadow_user(
+    mut users: Vec<UserScoreRes>,
+    start: i32,
+    position: i32,
+    user: UserScoreRes,
+) -> Vec<UserScoreRes> {
+    let index = ((position - 1 - start).max(0) as usize).min(users.len());
+    users.insert(index, user);
+    users
+}
+
+/// 按排名方式计算下一个玩家的排名
+///
+/// position 为在榜上的位置(从1开始)，same 表示与上一个玩家同分
+fn next_ranking(rank_style: &RankStyle, prev_ranking: i32, position: i32, same: bool) -> i32 {
+    match rank_style {
+        RankStyle::Ordinal => position,
+        _ if same => prev_ranking,
+        RankStyle::Competition => position,
+        RankStyle::Dense => prev_ranking + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按已经排序的分数计算排名
+    fn rankings(rank_style: RankStyle, scores: &[i64]) -> Vec<i32> {
+        let mut res: Vec<i32> = vec![];
+        for (index, score) in scores.iter().enumerate() {
+            let ranking = match res.last() {
+                Some(prev) => next_ranking(
+                    &rank_style,
+                    *prev,
+                    index as i32 + 1,
+                    scores[index - 1] == *score,
+                ),
+                None => 1,
+            };
+            res.push(ranking);
+        }
+        res
+    }
+
+    #[test]
+    fn next_ranking_by_rank_style() {
+        let scores = [100, 90, 90, 80, 80, 80, 70];
+        assert_eq!(
+            rankings(RankStyle::Ordinal, &scores),
+            vec![1, 2, 3, 4, 5, 6, 7]
+        );
+        assert_eq!(
+            rankings(RankStyle::Competition, &scores),
+            vec![1, 2, 2, 4, 4, 4, 7]
+        );
+        assert_eq!(
+            rankings(RankStyle::Dense, &scores),
+            vec![1, 2, 2, 3, 3, 3, 4]
+        );
+    }
+
+    #[test]
+    fn next_ranking_continues_from_range_start() {
+        // 区间第一个玩家的排名按分数计算，后面的玩家在此基础上继续
+        assert_eq!(next_ranking(&RankStyle::Competition, 3, 6, true), 3);
+        assert_eq!(next_ranking(&RankStyle::Competition, 3, 6, false), 6);
+        assert_eq!(next_ranking(&RankStyle::Dense, 3, 6, false), 4);
+        assert_eq!(next_ranking(&RankStyle::Ordinal, 3, 6, true), 6);
+    }
+
+    fn around_user(openid: &str) -> UserScoreRes {
+        UserScoreRes {
+            openid: Some(openid.to_string()),
+            ranking: None,
+            score: None,
+            nick_name: None,
+            percentile: None,
+            below_cutoff: None,
+        }
+    }
+
+    fn around_openids(users: &[UserScoreRes]) -> Vec<String> {
+        users
+            .iter()
+            .map(|user| user.openid.clone().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn insert_shadow_user_at_score_position() {
+        // 榜上第3到第6名，影子封禁的玩家排在第4名之后
+        let users = ["p3", "p4", "p5", "p6"].map(around_user).to_vec();
+        let users = insert_shadow_user(users, 2, 5, around_user("me"));
+        assert_eq!(around_openids(&users), vec!["p3", "p4", "me", "p5", "p6"]);
+
+        // 分数高于榜首
+        let users = ["p1", "p2"].map(around_user).to_vec();
+        let users = insert_shadow_user(users, 0, 1, around_user("me"));
+        assert_eq!(around_openids(&users), vec!["me", "p1", "p2"]);
+
+        // 分数低于榜尾，后面没有玩家
+        let users = ["p8", "p9"].map(around_user).to_vec();
+        let users = insert_shadow_user(users, 7, 10, around_user("me"));
+        assert_eq!(around_openids(&users), vec!["p8", "p9", "me"]);
+
+        // 排行榜为空
+        let users = insert_shadow_user(vec![], 0, 1, around_user("me"));
+        assert_eq!(around_openids(&users), vec!["me"]);
+    }
+}
@@ -1,12 +1,15 @@
 use crate::db::database::Database;
 use crate::repository::rank_repository::{RankRepository, RankRepositoryTrait};
 use crate::service::rank_config_service::RankConfigService;
+use crate::service::rank_service::RankService;
 use deadpool_redis::Pool;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct RankConfigState {
     pub rank_config_service: Arc<RankConfigService>,
+    /// 审核可疑分数时写入排行榜
+    pub rank_service: Arc<RankService>,
     pub rank_repo: RankRepository,
 }
 
@@ -18,6 +21,11 @@ impl RankConfigState {
     ) -> Self {
         Self {
             rank_config_service: rank_config_service.clone(),
+            rank_service: Arc::new(RankService::new(
+                db_conn,
+                redis_con_pool,
+                &rank_config_service.rank_table_configs,
+            )),
             rank_repo: RankRepository::new(db_conn, redis_con_pool),
         }
     }